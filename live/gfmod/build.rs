@@ -15,6 +15,13 @@ fn main() {
         .prepend_enum_name(false)
         .derive_debug(true);
 
+    // the Geode build links FMOD by itself, standalone binaries (like zcblive-render) need this
+    println!("cargo:rerun-if-env-changed=FMOD_LIB_DIR");
+    if let Ok(dir) = std::env::var("FMOD_LIB_DIR") {
+        println!("cargo:rustc-link-search=native={dir}");
+        println!("cargo:rustc-link-lib=dylib=fmod");
+    }
+
     bindings
         .generate()
         .expect("unable to generate bindings")
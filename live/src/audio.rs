@@ -0,0 +1,555 @@
+//! Audio output. The clickbot only talks to an [`AudioBackend`], so the same click logic
+//! runs on kittyaudio, FMOD or no audio device at all.
+
+use crate::clickpack::SoundWrapper;
+use gfmod::*;
+use kittyaudio::{Device, Frame, Mixer, PlaybackRate, Sound, SoundHandle, StreamSettings};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Weak},
+};
+
+/// Left and right channel gains for a pan from -1 (left) to 1 (right). Works like a
+/// balance control, so stereo sounds keep both channels and centered sounds are
+/// unchanged.
+pub fn pan_gains(pan: f64) -> (f32, f32) {
+    let pan = pan.clamp(-1.0, 1.0) as f32;
+    ((1.0 - pan).min(1.0), (1.0 + pan).min(1.0))
+}
+
+/// One-pole low-pass filter.
+pub struct LowPass {
+    alpha: f32,
+    prev: Frame,
+}
+
+impl LowPass {
+    /// `amount` goes from 0 (unfiltered) to 1 (200 Hz cutoff).
+    pub fn new(amount: f64, sample_rate: u32) -> Self {
+        Self::with_cutoff(Self::cutoff(amount), sample_rate)
+    }
+
+    /// Cutoff frequency in Hz for a filter `amount`, see [`LowPass::new`]. Every backend
+    /// uses it, so filtered clicks sound the same everywhere.
+    pub fn cutoff(amount: f64) -> f64 {
+        // 20 kHz to 200 Hz, logarithmically
+        20000.0 * 0.01f64.powf(amount.clamp(0.0, 1.0))
+    }
+
+    /// One-pole low-pass filter with a cutoff frequency in Hz.
+    pub fn with_cutoff(cutoff: f64, sample_rate: u32) -> Self {
+        let alpha = 1.0 - (-std::f64::consts::TAU * cutoff / sample_rate.max(1) as f64).exp();
+        Self {
+            alpha: alpha as f32,
+            prev: Frame {
+                left: 0.0,
+                right: 0.0,
+            },
+        }
+    }
+
+    pub fn process(&mut self, frame: Frame) -> Frame {
+        self.prev.left += (frame.left - self.prev.left) * self.alpha;
+        self.prev.right += (frame.right - self.prev.right) * self.alpha;
+        self.prev
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        let x = x * std::f64::consts::PI;
+        x.sin() / x
+    }
+}
+
+/// Blackman window, `t` goes from -1 to 1.
+fn blackman(t: f64) -> f64 {
+    let t = t * std::f64::consts::PI;
+    0.42 + 0.5 * t.cos() + 0.08 * (2.0 * t).cos()
+}
+
+/// Resamples frames with a windowed sinc filter. When downsampling the filter also cuts
+/// the frequencies that the new rate can't hold, so they don't alias.
+pub fn resample(frames: &[Frame], from: u32, to: u32) -> Vec<Frame> {
+    // zero crossings of the sinc on each side
+    const ZERO_CROSSINGS: f64 = 16.0;
+    if from == to || from == 0 || to == 0 || frames.is_empty() {
+        return frames.to_vec();
+    }
+    let ratio = to as f64 / from as f64;
+    let cutoff = ratio.min(1.0);
+    let radius = ZERO_CROSSINGS / cutoff; // in input frames
+    let len = (frames.len() as f64 * ratio).ceil() as usize;
+    (0..len)
+        .map(|i| {
+            let pos = i as f64 / ratio;
+            let start = (pos - radius).ceil().max(0.0) as usize;
+            let end = ((pos + radius).floor() as usize).min(frames.len() - 1);
+            let (mut left, mut right) = (0.0, 0.0);
+            for (j, frame) in frames.iter().enumerate().take(end + 1).skip(start) {
+                let x = j as f64 - pos;
+                let w = cutoff * sinc(x * cutoff) * blackman(x / radius);
+                left += frame.left as f64 * w;
+                right += frame.right as f64 * w;
+            }
+            Frame {
+                left: left as f32,
+                right: right as f32,
+            }
+        })
+        .collect()
+}
+
+/// Makes a sound loop seamlessly by crossfading its last `len` frames into its start.
+/// The result is `len` frames shorter.
+pub fn crossfade_loop(frames: &[Frame], len: usize) -> Vec<Frame> {
+    let len = len.min(frames.len() / 2);
+    let body = frames.len() - len;
+    let mut looped = frames[..body].to_vec();
+    for (i, (frame, end)) in looped.iter_mut().zip(&frames[body..]).enumerate() {
+        // equal power, the start and the end of a noise aren't correlated
+        let t = (i as f32 + 0.5) / len as f32 * std::f32::consts::FRAC_PI_2;
+        let (fade_in, fade_out) = (t.sin(), t.cos());
+        frame.left = frame.left * fade_in + end.left * fade_out;
+        frame.right = frame.right * fade_in + end.right * fade_out;
+    }
+    looped
+}
+
+pub trait AudioBackend {
+    /// Plays a sound once. `pan` goes from -1 (left) to 1 (right) and `lowpass` is the
+    /// low-pass filter amount, see [`LowPass::new`].
+    fn play(&mut self, sound: SoundWrapper, pitch: f64, volume: f64, pan: f64, lowpass: f64);
+
+    /// Starts looping a noise layer from frame `start`. Layers keep playing together
+    /// until [`AudioBackend::stop_noise`].
+    fn start_noise(&mut self, noise: &SoundWrapper, speed: f64, volume: f64, start: usize);
+
+    /// Stops all noise layers.
+    fn stop_noise(&mut self);
+
+    /// Multiplies the volume of every noise layer, used for fading the noise in and out.
+    fn set_noise_gain(&mut self, gain: f64);
+
+    /// Stops all playing sounds except the noise.
+    fn cut(&mut self);
+
+    /// Switches the output device. An empty name selects the default device.
+    fn set_device(&mut self, name: &str);
+
+    /// Returns `false` if the buffer size only applies after a restart.
+    fn set_buffer_size(&mut self, buffer_size: u32) -> bool;
+
+    /// Buffer size the backend actually uses, if it's known.
+    fn real_buffer_size(&self) -> Option<u64> {
+        None
+    }
+
+    /// Output sample rate, if it's known.
+    fn sample_rate(&self) -> Option<u32> {
+        None
+    }
+}
+
+/// Copies of a sound with only its left or only its right channel.
+struct SplitChannels {
+    /// Frames the copies were made from, the copies are dropped with them
+    source: Weak<[Frame]>,
+    left: Arc<[Frame]>,
+    right: Arc<[Frame]>,
+}
+
+pub struct KittyaudioBackend {
+    mixer: Mixer,
+    /// Panned sounds are played as one sound per channel, so the pan can be set with
+    /// their volumes. Keyed by the address of the source frames
+    split_channels: HashMap<usize, SplitChannels>,
+    /// Playing noise layers and their volumes
+    noise: Vec<(SoundHandle, f64)>,
+    noise_gain: f64,
+    device: String,
+    buffer_size: u32,
+    /// Sample rate to open the device with, or the device default if `None`
+    sample_rate: Option<u32>,
+}
+
+impl KittyaudioBackend {
+    pub fn new(device: &str, buffer_size: u32, sample_rate: Option<u32>) -> Self {
+        let mut backend = Self {
+            mixer: Mixer::new(),
+            split_channels: HashMap::new(),
+            noise: vec![],
+            noise_gain: 1.0,
+            device: device.to_string(),
+            buffer_size,
+            sample_rate,
+        };
+        backend.init_mixer();
+        backend
+    }
+
+    fn init_mixer(&mut self) {
+        log::debug!("starting kittyaudio playback thread");
+        self.noise.clear();
+        self.mixer = Mixer::new();
+        self.mixer.init_ex(
+            Device::from_name(&self.device).unwrap_or_default(),
+            StreamSettings {
+                buffer_size: Some(self.buffer_size),
+                sample_rate: self.sample_rate,
+                ..Default::default()
+            },
+        );
+    }
+
+    /// The left and right channels of `frames`, made the first time a sound is panned.
+    fn split_channels(&mut self, frames: &Arc<[Frame]>) -> (Arc<[Frame]>, Arc<[Frame]>) {
+        let key = Arc::as_ptr(frames) as *const Frame as usize;
+        if let Some(split) = self.split_channels.get(&key).filter(|split| {
+            split
+                .source
+                .upgrade()
+                .is_some_and(|f| Arc::ptr_eq(&f, frames))
+        }) {
+            return (split.left.clone(), split.right.clone());
+        }
+
+        // forget the copies of sounds that are gone
+        self.split_channels
+            .retain(|_, split| split.source.strong_count() != 0);
+        let channel = |left: bool| -> Arc<[Frame]> {
+            frames
+                .iter()
+                .map(|f| Frame {
+                    left: if left { f.left } else { 0.0 },
+                    right: if left { 0.0 } else { f.right },
+                })
+                .collect()
+        };
+        let split = SplitChannels {
+            source: Arc::downgrade(frames),
+            left: channel(true),
+            right: channel(false),
+        };
+        let channels = (split.left.clone(), split.right.clone());
+        self.split_channels.insert(key, split);
+        channels
+    }
+}
+
+impl AudioBackend for KittyaudioBackend {
+    fn play(&mut self, mut sound: SoundWrapper, pitch: f64, volume: f64, pan: f64, lowpass: f64) {
+        let (left, right) = pan_gains(pan);
+        if lowpass > 0.0 {
+            // kittyaudio has no filters, so play a filtered copy, panned while at it
+            let mut filter = LowPass::new(lowpass, sound.sample_rate());
+            let frames: Vec<Frame> = sound
+                .frames
+                .iter()
+                .map(|f| {
+                    filter.process(Frame {
+                        left: f.left * left,
+                        right: f.right * right,
+                    })
+                })
+                .collect();
+            sound.sound = Sound::from_frames(sound.sample_rate(), &frames);
+        } else if pan != 0.0 {
+            // kittyaudio has no pan either, play each channel with its own volume
+            let (left_frames, right_frames) = self.split_channels(&sound.frames);
+            for (frames, gain) in [(left_frames, left), (right_frames, right)] {
+                let mut channel = sound.sound.clone();
+                channel.frames = frames;
+                channel.set_playback_rate(PlaybackRate::Factor(pitch));
+                channel.set_volume(volume as f32 * gain);
+                self.mixer.play(channel);
+            }
+            return;
+        }
+        sound.set_playback_rate(PlaybackRate::Factor(pitch));
+        sound.set_volume(volume as f32);
+        self.mixer.play(sound.sound);
+    }
+
+    fn start_noise(&mut self, noise: &SoundWrapper, speed: f64, volume: f64, start: usize) {
+        let mut noise = noise.clone();
+        noise.set_volume((volume * self.noise_gain) as f32);
+        noise.set_loop_enabled(true);
+        let frames = noise.frames.len().saturating_sub(1);
+        noise.set_loop_index(0..=frames);
+        noise.seek_to_index(start.min(frames));
+        noise.set_playback_rate(PlaybackRate::Factor(speed));
+        self.noise.push((self.mixer.play(noise.sound), volume));
+    }
+
+    fn stop_noise(&mut self) {
+        for (noise, _) in self.noise.drain(..) {
+            noise.set_playback_rate(PlaybackRate::Factor(1.0));
+            noise.set_loop_enabled(false);
+            noise.seek_to_end();
+        }
+    }
+
+    fn set_noise_gain(&mut self, gain: f64) {
+        self.noise_gain = gain;
+        for (noise, volume) in &self.noise {
+            noise.set_volume((volume * gain) as f32);
+        }
+    }
+
+    fn cut(&mut self) {
+        for sound in &self.mixer.renderer.guard().sounds {
+            // check if this is a noise sound, we don't want to stop it
+            let sound_len = sound.guard().frames.len();
+            if self
+                .noise
+                .iter()
+                .any(|(noise, _)| noise.guard().frames.len() == sound_len)
+            {
+                continue;
+            }
+
+            // kis!!
+            sound.seek_to_end();
+        }
+    }
+
+    fn set_device(&mut self, name: &str) {
+        // start a new mixer on new device
+        self.device = name.to_string();
+        self.init_mixer();
+    }
+
+    fn set_buffer_size(&mut self, buffer_size: u32) -> bool {
+        self.buffer_size = buffer_size;
+        self.init_mixer();
+        true
+    }
+
+    fn real_buffer_size(&self) -> Option<u64> {
+        Some(self.mixer.renderer.guard().last_buffer_size as u64)
+    }
+
+    fn sample_rate(&self) -> Option<u32> {
+        self.sample_rate
+    }
+}
+
+/// Plays sounds through the game's FMOD system. The system itself is owned by the bot,
+/// since sounds are created on it when loading clickpacks.
+pub struct FmodBackend {
+    system: *mut FMOD_SYSTEM,
+    /// Playing clicks and their low-pass filters, if they have one
+    channels: Vec<(*mut FMOD_CHANNEL, *mut FMOD_DSP)>,
+    /// Channels of the playing noise layers and their volumes
+    noise_channels: Vec<(*mut FMOD_CHANNEL, f64)>,
+    noise_gain: f64,
+}
+
+impl FmodBackend {
+    pub fn new(system: *mut FMOD_SYSTEM) -> Self {
+        Self {
+            system,
+            channels: vec![],
+            noise_channels: vec![],
+            noise_gain: 1.0,
+        }
+    }
+}
+
+impl AudioBackend for FmodBackend {
+    fn play(&mut self, sound: SoundWrapper, pitch: f64, volume: f64, pan: f64, lowpass: f64) {
+        // forget channels that have finished playing
+        self.channels.retain(|&(channel, filter)| {
+            let mut is_playing: FMOD_BOOL = 0;
+            unsafe { FMOD_Channel_IsPlaying(channel, &mut is_playing) };
+            if is_playing == 0 {
+                release_dsp(filter);
+            }
+            is_playing != 0
+        });
+
+        let mut channel: *mut FMOD_CHANNEL = std::ptr::null_mut();
+        let mut filter: *mut FMOD_DSP = std::ptr::null_mut();
+        unsafe {
+            // start paused, so the filter is there from the first sample
+            FMOD_System_PlaySound(
+                self.system,
+                sound.fmod_sound,
+                std::ptr::null_mut(),
+                1,
+                &mut channel,
+            );
+            FMOD_Channel_SetPitch(channel, pitch as f32);
+            FMOD_Channel_SetVolume(channel, volume as _);
+            FMOD_Channel_SetPan(channel, pan as f32);
+            if lowpass > 0.0 {
+                // the same one-pole filter and cutoff as the other backends
+                FMOD_System_CreateDSPByType(self.system, FMOD_DSP_TYPE_LOWPASS_SIMPLE, &mut filter);
+                FMOD_DSP_SetParameterFloat(
+                    filter,
+                    FMOD_DSP_LOWPASS_SIMPLE_CUTOFF as _,
+                    LowPass::cutoff(lowpass) as f32,
+                );
+                FMOD_Channel_AddDSP(channel, 0, filter);
+            }
+            FMOD_Channel_SetPaused(channel, 0);
+            FMOD_System_Update(self.system);
+        }
+        self.channels.push((channel, filter));
+    }
+
+    fn start_noise(&mut self, noise: &SoundWrapper, speed: f64, volume: f64, start: usize) {
+        let mut channel: *mut FMOD_CHANNEL = std::ptr::null_mut();
+        unsafe {
+            // set loop points for sound
+            FMOD_Sound_SetLoopCount(noise.fmod_sound, i32::MAX);
+
+            // start paused, so the position can be set before anything is heard
+            FMOD_System_PlaySound(
+                self.system,
+                noise.fmod_sound,
+                std::ptr::null_mut(),
+                1,
+                &mut channel,
+            );
+
+            // update channel
+            FMOD_Channel_SetPosition(channel, start as u32, FMOD_TIMEUNIT_PCM);
+            FMOD_Channel_SetVolume(channel, (volume * self.noise_gain) as _);
+            FMOD_Channel_SetLoopCount(channel, i32::MAX);
+            FMOD_Channel_SetPitch(channel, speed as f32);
+            FMOD_Channel_SetPaused(channel, 0);
+            FMOD_System_Update(self.system);
+        }
+        self.noise_channels.push((channel, volume));
+    }
+
+    fn stop_noise(&mut self) {
+        for (channel, _) in self.noise_channels.drain(..) {
+            unsafe { FMOD_Channel_Stop(channel) };
+        }
+    }
+
+    fn set_noise_gain(&mut self, gain: f64) {
+        self.noise_gain = gain;
+        for &(channel, volume) in &self.noise_channels {
+            unsafe { FMOD_Channel_SetVolume(channel, (volume * gain) as _) };
+        }
+    }
+
+    fn cut(&mut self) {
+        for (channel, filter) in self.channels.drain(..) {
+            unsafe { FMOD_Channel_Stop(channel) };
+            release_dsp(filter);
+        }
+    }
+
+    fn set_device(&mut self, _name: &str) {
+        // the game picks the device
+    }
+
+    fn set_buffer_size(&mut self, _buffer_size: u32) -> bool {
+        // FMOD buffer size can only be set before the system is initialized
+        false
+    }
+
+    fn sample_rate(&self) -> Option<u32> {
+        let mut sample_rate = 0;
+        let mut speaker_mode = FMOD_SPEAKERMODE_STEREO;
+        let mut num_raw_speakers = 0;
+        unsafe {
+            FMOD_System_GetSoftwareFormat(
+                self.system,
+                &mut sample_rate,
+                &mut speaker_mode,
+                &mut num_raw_speakers,
+            )
+            .fmod_result()
+            .map_err(|e| log::error!("failed to get fmod sample rate: {e}"))
+            .ok()?;
+        }
+        u32::try_from(sample_rate).ok()
+    }
+}
+
+impl Drop for FmodBackend {
+    fn drop(&mut self) {
+        self.stop_noise();
+    }
+}
+
+fn release_dsp(dsp: *mut FMOD_DSP) {
+    if !dsp.is_null() {
+        unsafe { FMOD_DSP_Release(dsp) };
+    }
+}
+
+/// A call made to a [`NullBackend`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum AudioEvent {
+    Play {
+        pitch: f64,
+        volume: f64,
+        pan: f64,
+        lowpass: f64,
+    },
+    StartNoise {
+        speed: f64,
+        volume: f64,
+        start: usize,
+    },
+    StopNoise,
+    SetNoiseGain(f64),
+    Cut,
+    SetDevice(String),
+    SetBufferSize(u32),
+}
+
+/// Doesn't output any audio, only records what would have been played.
+#[derive(Default)]
+pub struct NullBackend {
+    pub events: Vec<AudioEvent>,
+}
+
+impl AudioBackend for NullBackend {
+    fn play(&mut self, _sound: SoundWrapper, pitch: f64, volume: f64, pan: f64, lowpass: f64) {
+        self.events.push(AudioEvent::Play {
+            pitch,
+            volume,
+            pan,
+            lowpass,
+        });
+    }
+
+    fn start_noise(&mut self, _noise: &SoundWrapper, speed: f64, volume: f64, start: usize) {
+        self.events.push(AudioEvent::StartNoise {
+            speed,
+            volume,
+            start,
+        });
+    }
+
+    fn stop_noise(&mut self) {
+        self.events.push(AudioEvent::StopNoise);
+    }
+
+    fn set_noise_gain(&mut self, gain: f64) {
+        self.events.push(AudioEvent::SetNoiseGain(gain));
+    }
+
+    fn cut(&mut self) {
+        self.events.push(AudioEvent::Cut);
+    }
+
+    fn set_device(&mut self, name: &str) {
+        self.events.push(AudioEvent::SetDevice(name.to_string()));
+    }
+
+    fn set_buffer_size(&mut self, buffer_size: u32) -> bool {
+        self.events.push(AudioEvent::SetBufferSize(buffer_size));
+        true
+    }
+}
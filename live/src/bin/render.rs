@@ -0,0 +1,141 @@
+//! Renders a list of actions or a bot macro into a WAV file using the ZCB Live click
+//! algorithm.
+//!
+//! ```text
+//! zcblive-render <clickpack> <actions> <output.wav> [options]
+//! ```
+
+use anyhow::{Context, Result};
+use std::path::PathBuf;
+use zcblive::{
+    bot::Config,
+    clickpack::{Clickpack, LoadClickpackFor},
+    render::{self, Action, RenderSettings},
+    replay::{Replay, ReplayFormat},
+};
+
+const USAGE: &str = "usage: zcblive-render <clickpack> <actions> <output.wav> [options]
+
+<actions> is either a JSON list of actions or a bot macro (gdr, mhr, tasbot, echo, zbot,
+ybot or plain text \"frame button push [player2]\" lines)
+
+options:
+    --format <name>        macro format, guessed if not specified
+    --fps <fps>            override the macro framerate
+    --config <path>        load settings from a ZCB Live config.json
+    --sample-rate <rate>   output sample rate (default: 44100)
+    --tail <seconds>       time rendered after the last action (default: 1.0)
+    --noise                mix the clickpack noise under the clicks
+    --seed <seed>          random seed, renders with the same seed sound the same";
+
+struct Args {
+    clickpack: PathBuf,
+    actions: PathBuf,
+    output: PathBuf,
+    format: Option<ReplayFormat>,
+    fps: Option<f64>,
+    config: Option<PathBuf>,
+    seed: Option<u64>,
+    settings: RenderSettings,
+}
+
+fn parse_args() -> Result<Args> {
+    let mut positional = vec![];
+    let mut format = None;
+    let mut fps = None;
+    let mut config = None;
+    let mut seed = None;
+    let mut settings = RenderSettings::default();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().with_context(|| format!("{name} needs a value"));
+        match arg.as_str() {
+            "--format" => {
+                let name = value("--format")?;
+                format = Some(
+                    ReplayFormat::from_name(&name)
+                        .with_context(|| format!("unknown macro format \"{name}\""))?,
+                );
+            }
+            "--fps" => fps = Some(value("--fps")?.parse()?),
+            "--config" => config = Some(PathBuf::from(value("--config")?)),
+            "--sample-rate" => settings.sample_rate = value("--sample-rate")?.parse()?,
+            "--tail" => settings.tail = value("--tail")?.parse()?,
+            "--noise" => settings.noise = true,
+            "--seed" => seed = Some(value("--seed")?.parse()?),
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                std::process::exit(0);
+            }
+            _ if arg.starts_with("--") => anyhow::bail!("unknown option {arg}\n\n{USAGE}"),
+            _ => positional.push(PathBuf::from(arg)),
+        }
+    }
+
+    let [clickpack, actions, output]: [PathBuf; 3] = positional
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("expected 3 arguments\n\n{USAGE}"))?;
+    Ok(Args {
+        clickpack,
+        actions,
+        output,
+        format,
+        fps,
+        config,
+        seed,
+        settings,
+    })
+}
+
+/// Loads a JSON list of actions, or a bot macro if the file isn't one.
+fn load_actions(args: &Args) -> Result<Vec<Action>> {
+    if args.format.is_none() {
+        let data = std::fs::read(&args.actions)
+            .with_context(|| format!("failed to read {:?}", args.actions))?;
+        if let Ok(actions) = serde_json::from_slice(&data) {
+            return Ok(actions);
+        }
+    }
+    Ok(Replay::load(&args.actions, args.format, args.fps)?.actions)
+}
+
+fn main() -> Result<()> {
+    let _ = simple_logger::SimpleLogger::new()
+        .with_level(log::LevelFilter::Info)
+        .init();
+    let args = parse_args()?;
+
+    let mut conf = if let Some(path) = &args.config {
+        let f = std::fs::File::open(path).with_context(|| format!("failed to open {path:?}"))?;
+        serde_json::from_reader::<_, Config>(f)
+            .with_context(|| format!("failed to parse config {path:?}"))?
+            .fixup()
+    } else {
+        Config::default()
+    };
+
+    if args.seed.is_some() {
+        conf.rng_seed = args.seed;
+    }
+
+    let actions = load_actions(&args)?;
+
+    // the output rate of a render is known
+    conf.sample_settings.sample_rate = args.settings.sample_rate;
+    let mut clickpack = Clickpack::default();
+    clickpack.load_from_path(
+        &args.clickpack,
+        LoadClickpackFor::All,
+        std::ptr::null_mut(),
+        &conf.sample_settings,
+    )?;
+    clickpack.synthesize_releases(&conf.release_synth, std::ptr::null_mut());
+    clickpack.normalize(&conf.normalization);
+    conf.manifest.clone_from(&clickpack.manifest);
+
+    let frames = render::render(&conf, &mut clickpack, &actions, &args.settings)?;
+    render::write_wav(&args.output, &frames, args.settings.sample_rate)?;
+    log::info!("wrote {} frames to {:?}", frames.len(), args.output);
+    Ok(())
+}
@@ -2,8 +2,15 @@
 use crate::{game::PlayLayer, hooks};
 
 use crate::{
-    clickpack::{Button, ClickType, Clickpack, LoadClickpackFor, Pitch, Timings, VolumeSettings},
-    utils,
+    audio::{AudioBackend, FmodBackend, KittyaudioBackend, NullBackend},
+    cache::SoundCache,
+    clickpack::{
+        Button, ClickQuery, ClickType, Clickpack, ClickpackManifest, Curve, Fallback,
+        LoadClickpackFor, Normalization, NormalizeMode, Pitch, SampleSelector, SampleSettings,
+        SelectionStrategy, SoundWrapper, Timings, VolumeSettings,
+    },
+    synth::{self, ReleaseSynth},
+    utils, vfs,
 };
 use anyhow::Result;
 use egui::{
@@ -13,13 +20,15 @@ use egui_clickpack_db::ClickpackDb;
 use egui_keybind::{Bind, Keybind, Shortcut};
 use egui_modal::{Icon, Modal};
 use egui_notify::{Toast, Toasts};
+use fastrand::Rng;
 use gfmod::*;
-use kittyaudio::{Device, Mixer, PlaybackRate, SoundHandle, StreamSettings};
+use kittyaudio::Device;
 use once_cell::sync::Lazy;
 use parking_lot::Mutex;
 use rfd::FileDialog;
 use serde::{Deserialize, Serialize};
 use std::{
+    collections::BTreeMap,
     ops::RangeInclusive,
     path::{Path, PathBuf},
     process::Command,
@@ -183,15 +192,15 @@ pub enum Stage {
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Default)]
-struct IgnoredClickTypes {
-    hardclicks: bool,
-    hardreleases: bool,
-    softclicks: bool,
-    softreleases: bool,
-    clicks: bool,
-    releases: bool,
-    microclicks: bool,
-    microreleases: bool,
+pub struct IgnoredClickTypes {
+    pub hardclicks: bool,
+    pub hardreleases: bool,
+    pub softclicks: bool,
+    pub softreleases: bool,
+    pub clicks: bool,
+    pub releases: bool,
+    pub microclicks: bool,
+    pub microreleases: bool,
 }
 
 impl IgnoredClickTypes {
@@ -223,6 +232,339 @@ impl IgnoredClickTypes {
     }
 }
 
+/// What to play for each click type when the clickpack doesn't have it.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct ClickFallbacks {
+    pub hardclicks: Fallback,
+    pub hardreleases: Fallback,
+    pub clicks: Fallback,
+    pub releases: Fallback,
+    pub softclicks: Fallback,
+    pub softreleases: Fallback,
+    pub microclicks: Fallback,
+    pub microreleases: Fallback,
+    /// Volume change per tier for [`Fallback::Synthesize`], 0.2 makes a click shifted
+    /// one tier softer 20% quieter
+    pub synth_volume_step: f64,
+    /// Pitch change per tier for [`Fallback::Synthesize`]
+    pub synth_pitch_step: f64,
+}
+
+impl Default for ClickFallbacks {
+    fn default() -> Self {
+        Self {
+            hardclicks: Fallback::default(),
+            hardreleases: Fallback::default(),
+            clicks: Fallback::default(),
+            releases: Fallback::default(),
+            softclicks: Fallback::default(),
+            softreleases: Fallback::default(),
+            microclicks: Fallback::default(),
+            microreleases: Fallback::default(),
+            synth_volume_step: 0.2,
+            synth_pitch_step: 0.02,
+        }
+    }
+}
+
+static NEAREST_FALLBACK: Fallback = Fallback::Nearest;
+
+impl ClickFallbacks {
+    pub fn get(&self, typ: ClickType) -> &Fallback {
+        match typ {
+            ClickType::HardClick => &self.hardclicks,
+            ClickType::HardRelease => &self.hardreleases,
+            ClickType::Click => &self.clicks,
+            ClickType::Release => &self.releases,
+            ClickType::SoftClick => &self.softclicks,
+            ClickType::SoftRelease => &self.softreleases,
+            ClickType::MicroClick => &self.microclicks,
+            ClickType::MicroRelease => &self.microreleases,
+            ClickType::None => &NEAREST_FALLBACK,
+        }
+    }
+
+    /// Panics on [`ClickType::None`].
+    pub fn get_mut(&mut self, typ: ClickType) -> &mut Fallback {
+        match typ {
+            ClickType::HardClick => &mut self.hardclicks,
+            ClickType::HardRelease => &mut self.hardreleases,
+            ClickType::Click => &mut self.clicks,
+            ClickType::Release => &mut self.releases,
+            ClickType::SoftClick => &mut self.softclicks,
+            ClickType::SoftRelease => &mut self.softreleases,
+            ClickType::MicroClick => &mut self.microclicks,
+            ClickType::MicroRelease => &mut self.microreleases,
+            ClickType::None => panic!("ClickType::None has no fallback"),
+        }
+    }
+}
+
+/// Stereo placement of clicks. Pans go from -1 (left) to 1 (right).
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct Panning {
+    pub enabled: bool,
+    pub player1: f64,
+    pub player2: f64,
+    /// Added to the player pan for platformer left/right sounds
+    pub left: f64,
+    pub right: f64,
+    /// Maximum random offset added to every click
+    pub jitter: f64,
+}
+
+impl Default for Panning {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            player1: -0.3,
+            player2: 0.3,
+            left: -0.3,
+            right: 0.3,
+            jitter: 0.05,
+        }
+    }
+}
+
+impl Panning {
+    fn get(&self, button: Button, player2: bool, rng: &mut Rng) -> f64 {
+        if !self.enabled {
+            return 0.0;
+        }
+        let mut pan = if player2 { self.player2 } else { self.player1 };
+        pan += match button {
+            Button::Jump => 0.0,
+            Button::Left => self.left,
+            Button::Right => self.right,
+        };
+        if self.jitter > 0.0 {
+            pan += utils::f64_range(rng, -self.jitter..=self.jitter);
+        }
+        pan.clamp(-1.0, 1.0)
+    }
+}
+
+/// Changes clicks by how fast a button is being clicked, so long spams sound different
+/// from isolated clicks.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct ClickRateSettings {
+    pub enabled: bool,
+    /// Time in seconds that the clicks per second estimate averages over
+    pub window: f64,
+    /// Clicks per second where the changes start
+    pub min_rate: f64,
+    /// Clicks per second where the changes are fully applied
+    pub max_rate: f64,
+    /// Pitch multiplier at `max_rate`
+    pub pitch: f64,
+    /// Volume multiplier at `max_rate`
+    pub volume: f64,
+    /// Low-pass filter amount at `max_rate`, from 0 (none) to 1
+    pub lowpass: f64,
+}
+
+impl Default for ClickRateSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            window: 0.5,
+            min_rate: 6.0,
+            max_rate: 16.0,
+            pitch: 1.04,
+            volume: 0.85,
+            lowpass: 0.3,
+        }
+    }
+}
+
+impl ClickRateSettings {
+    /// New clicks per second estimate after a click `dt` seconds after the previous one.
+    fn update(&self, rate: f64, dt: f64) -> f64 {
+        let dt = dt.max(0.001);
+        let k = if self.window > 0.0 {
+            1.0 - (-dt / self.window).exp()
+        } else {
+            1.0
+        };
+        rate + (1.0 / dt - rate) * k
+    }
+
+    /// How much of the changes apply at a click rate, from 0 to 1.
+    fn amount(&self, rate: f64) -> f64 {
+        if !self.enabled {
+            return 0.0;
+        }
+        if self.max_rate <= self.min_rate {
+            return if rate >= self.min_rate { 1.0 } else { 0.0 };
+        }
+        ((rate - self.min_rate) / (self.max_rate - self.min_rate)).clamp(0.0, 1.0)
+    }
+}
+
+/// Settings of one noise layer.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct NoiseLayerSettings {
+    pub enabled: bool,
+    /// Multiplied with the noise volume
+    pub volume: f64,
+}
+
+impl Default for NoiseLayerSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            volume: 1.0,
+        }
+    }
+}
+
+/// How the noise layers of a clickpack are played.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct NoiseSettings {
+    /// Start every layer at a random point, so the noise doesn't always start the same
+    pub random_start: bool,
+    /// Seconds to fade the noise in when it starts
+    pub fade_in: f64,
+    /// Seconds to fade the noise out when it stops
+    pub fade_out: f64,
+    /// Only play noise while in a level
+    pub only_in_levels: bool,
+    /// Volume of the noise outside of levels, if it plays there
+    pub menu_volume: f64,
+    /// Volume of the noise after dying, until the next attempt starts
+    pub death_volume: f64,
+    /// Seconds to move between the menu, level and death volumes
+    pub ducking_time: f64,
+    /// Seconds to fade the noise in at the start of every attempt, 0 to not fade
+    pub attempt_fade_in: f64,
+    /// How much quieter the noise gets under each click, from 0 to 1
+    pub click_duck: f64,
+    /// Seconds for the noise to come back after a click ducked it
+    pub click_duck_release: f64,
+    /// Settings of each layer by name, missing layers use the defaults
+    pub layers: BTreeMap<String, NoiseLayerSettings>,
+}
+
+impl Default for NoiseSettings {
+    fn default() -> Self {
+        Self {
+            random_start: true,
+            fade_in: 0.0,
+            fade_out: 0.0,
+            only_in_levels: false,
+            menu_volume: 1.0,
+            death_volume: 1.0,
+            ducking_time: 0.25,
+            attempt_fade_in: 0.0,
+            click_duck: 0.0,
+            click_duck_release: 0.15,
+            layers: BTreeMap::new(),
+        }
+    }
+}
+
+impl NoiseSettings {
+    pub fn layer(&self, name: &str) -> NoiseLayerSettings {
+        self.layers.get(name).cloned().unwrap_or_default()
+    }
+}
+
+/// Volume of the noise while it fades in or out, see [`NoiseSettings::fade_in`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NoiseFade {
+    /// Multiplier for the volume of every layer
+    pub gain: f64,
+    /// Whether the noise should be heard, the gain fades towards 0 if not
+    pub active: bool,
+    /// Whether noise layers are playing in the audio backend
+    pub playing: bool,
+}
+
+impl Default for NoiseFade {
+    fn default() -> Self {
+        Self {
+            gain: 1.0,
+            active: false,
+            playing: false,
+        }
+    }
+}
+
+impl NoiseFade {
+    /// Moves the gain `dt` seconds closer to where it's fading.
+    fn advance(&mut self, dt: f64, settings: &NoiseSettings) {
+        let (target, time) = if self.active {
+            (1.0, settings.fade_in)
+        } else {
+            (0.0, settings.fade_out)
+        };
+        let step = if time > 0.0 { dt / time } else { 1.0 };
+        self.gain = if self.gain < target {
+            (self.gain + step).min(target)
+        } else {
+            (self.gain - step).max(target)
+        };
+    }
+}
+
+/// Volume of the noise driven by the game state and by clicks, see
+/// [`NoiseSettings::menu_volume`] and [`NoiseSettings::click_duck`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NoiseDuck {
+    /// Volume for the menu, level or death
+    pub gain: f64,
+    /// Volume `gain` moves towards
+    pub target: f64,
+    /// Seconds for `gain` to move from 0 to 1
+    pub time: f64,
+    /// How much the last click ducks the noise, goes back to 0 after the release
+    pub click: f64,
+}
+
+impl Default for NoiseDuck {
+    fn default() -> Self {
+        Self {
+            gain: 1.0,
+            target: 1.0,
+            time: 0.0,
+            click: 0.0,
+        }
+    }
+}
+
+impl NoiseDuck {
+    /// Starts moving the gain towards `target` over `time` seconds per unit of volume.
+    fn duck_to(&mut self, target: f64, time: f64) {
+        self.target = target;
+        self.time = time;
+    }
+
+    /// Moves the gain and the click ducking `dt` seconds further.
+    fn advance(&mut self, dt: f64, settings: &NoiseSettings) {
+        let step = if self.time > 0.0 { dt / self.time } else { 1.0 };
+        self.gain = if self.gain < self.target {
+            (self.gain + step).min(self.target)
+        } else {
+            (self.gain - step).max(self.target)
+        };
+        self.click = if settings.click_duck_release > 0.0 {
+            (self.click - dt * settings.click_duck / settings.click_duck_release).max(0.0)
+        } else {
+            0.0
+        };
+    }
+
+    /// Multiplier for the volume of every layer.
+    pub fn volume(&self) -> f64 {
+        self.gain * (1.0 - self.click)
+    }
+}
+
 const fn death_release_delay_default() -> f64 {
     0.001
 }
@@ -250,12 +592,160 @@ impl ToastVisibility {
     }
 }
 
+/// Which settings are taken from the clickpack manifest (`zcb.json`), if it specifies
+/// them. Disabled fields keep the user's values.
 #[derive(Serialize, Deserialize, Clone, PartialEq)]
-pub struct Config {
+#[serde(default)]
+pub struct UseClickpackDefaults {
+    pub timings: bool,
+    pub pitch: bool,
+    pub volume_settings: bool,
+    pub noise_volume: bool,
+    pub selection_strategy: bool,
+}
+
+impl Default for UseClickpackDefaults {
+    fn default() -> Self {
+        Self {
+            timings: true,
+            pitch: true,
+            volume_settings: true,
+            noise_volume: true,
+            selection_strategy: true,
+        }
+    }
+}
+
+/// Timings, pitch and volume settings. The config has one set for player 1 jumps, and
+/// optionally more in [`SoundProfiles`].
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct SoundSettings {
     pub pitch_enabled: bool,
     pub pitch: Pitch,
     pub timings: Timings,
+    /// Hold durations for releases, see [`Config::hold_aware_timings`]
+    pub release_timings: Timings,
     pub volume_settings: VolumeSettings,
+}
+
+impl Default for SoundSettings {
+    fn default() -> Self {
+        Self {
+            pitch_enabled: true,
+            pitch: Pitch::default(),
+            timings: Timings::default(),
+            release_timings: Timings::default(),
+            volume_settings: VolumeSettings::default(),
+        }
+    }
+}
+
+/// [`SoundSettings`] that are played with, see [`Config::sound_settings`]. Every field
+/// borrows either from the config or from the clickpack manifest, so nothing is copied
+/// on a click.
+#[derive(Clone, Copy)]
+pub struct EffectiveSoundSettings<'a> {
+    pub pitch_enabled: bool,
+    pub pitch: &'a Pitch,
+    pub timings: &'a Timings,
+    pub release_timings: &'a Timings,
+    pub volume_settings: &'a VolumeSettings,
+}
+
+impl<'a> From<&'a SoundSettings> for EffectiveSoundSettings<'a> {
+    fn from(settings: &'a SoundSettings) -> Self {
+        Self {
+            pitch_enabled: settings.pitch_enabled,
+            pitch: &settings.pitch,
+            timings: &settings.timings,
+            release_timings: &settings.release_timings,
+            volume_settings: &settings.volume_settings,
+        }
+    }
+}
+
+impl<'a> EffectiveSoundSettings<'a> {
+    fn get_pitch(&self, rng: &mut Rng) -> f64 {
+        if self.pitch_enabled {
+            utils::f64_range(rng, self.pitch.from..=self.pitch.to)
+        } else {
+            1.0
+        }
+    }
+
+    /// Timings used to classify an action, see [`Config::hold_aware_timings`].
+    #[inline]
+    fn timings_for(&self, push: bool, hold_aware: bool) -> &'a Timings {
+        if hold_aware && !push {
+            self.release_timings
+        } else {
+            self.timings
+        }
+    }
+}
+
+/// Which buttons and player a set of [`SoundSettings`] is for.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ProfileKind {
+    #[default]
+    Player1Jump,
+    Player2Jump,
+    Player1Platformer,
+    Player2Platformer,
+}
+
+impl ProfileKind {
+    pub const ALL: [Self; 4] = [
+        Self::Player1Jump,
+        Self::Player2Jump,
+        Self::Player1Platformer,
+        Self::Player2Platformer,
+    ];
+
+    pub const fn of(button: Button, player2: bool) -> Self {
+        match (button.is_platformer(), player2) {
+            (false, false) => Self::Player1Jump,
+            (false, true) => Self::Player2Jump,
+            (true, false) => Self::Player1Platformer,
+            (true, true) => Self::Player2Platformer,
+        }
+    }
+
+    /// Profile whose settings are used if this one doesn't have its own.
+    pub const fn fallback(self) -> Option<Self> {
+        match self {
+            Self::Player1Jump => None,
+            Self::Player2Jump | Self::Player1Platformer => Some(Self::Player1Jump),
+            Self::Player2Platformer => Some(Self::Player1Platformer),
+        }
+    }
+
+    pub const fn text(self) -> &'static str {
+        match self {
+            Self::Player1Jump => "Player 1 jump",
+            Self::Player2Jump => "Player 2 jump",
+            Self::Player1Platformer => "Player 1 left/right",
+            Self::Player2Platformer => "Player 2 left/right",
+        }
+    }
+}
+
+/// Separate settings for player 2 and platformer buttons. Profiles that are `None` use
+/// the settings of their [`ProfileKind::fallback`].
+#[derive(Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(default)]
+pub struct SoundProfiles {
+    pub player2: Option<SoundSettings>,
+    pub platformer: Option<SoundSettings>,
+    pub platformer_player2: Option<SoundSettings>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct Config {
+    /// Settings for player 1 jumps, and every other profile that doesn't have its own
+    #[serde(flatten)]
+    pub sound: SoundSettings,
     #[serde(default = "Shortcuts::default")]
     pub shortcuts: Shortcuts,
     #[serde(default = "true_value")]
@@ -305,13 +795,53 @@ pub struct Config {
     #[serde(default = "bool::default")]
     pub play_noise_when_disabled: bool,
     #[serde(default = "IgnoredClickTypes::default")]
-    ignored_click_types: IgnoredClickTypes,
+    pub ignored_click_types: IgnoredClickTypes,
     #[serde(default = "bool::default")]
     pub use_ingame_time: bool,
     #[serde(default = "float_one")]
     pub ui_scale: f32,
     #[serde(default)]
     pub toast_visibility: ToastVisibility,
+    #[serde(default)]
+    pub use_clickpack_defaults: UseClickpackDefaults,
+    /// Seed for pitch, volume and sound choices. Random if not set
+    #[serde(default)]
+    pub rng_seed: Option<u64>,
+    #[serde(default = "bool::default")]
+    pub reseed_every_attempt: bool,
+    #[serde(default)]
+    pub selection_strategy: SelectionStrategy,
+    /// Classify releases by how long the button was held using `release_timings`, and
+    /// clicks by the time since the previous release using `timings`
+    #[serde(default = "bool::default")]
+    pub hold_aware_timings: bool,
+    /// With `hold_aware_timings`, time clicks from the previous click instead
+    #[serde(default = "bool::default")]
+    pub click_gap_from_click: bool,
+    #[serde(default)]
+    pub fallbacks: ClickFallbacks,
+    #[serde(default)]
+    pub profiles: SoundProfiles,
+    #[serde(default)]
+    pub panning: Panning,
+    #[serde(default)]
+    pub click_rate: ClickRateSettings,
+    #[serde(default)]
+    pub normalization: Normalization,
+    #[serde(default)]
+    pub sample_settings: SampleSettings,
+    #[serde(default)]
+    pub noise: NoiseSettings,
+    #[serde(default)]
+    pub release_synth: ReleaseSynth,
+    /// Samples with any of these tags are never played, see
+    /// [`crate::clickpack::SampleMeta::tags`]
+    #[serde(default)]
+    pub excluded_sample_tags: Vec<String>,
+    /// Manifest of the loaded clickpack. Its settings are used instead of the user's (see
+    /// [`UseClickpackDefaults`]), but they never replace them, so they aren't saved
+    #[serde(skip)]
+    pub manifest: Option<ClickpackManifest>,
 }
 
 impl Config {
@@ -332,10 +862,7 @@ impl Config {
 impl Default for Config {
     fn default() -> Self {
         Self {
-            pitch_enabled: true,
-            pitch: Pitch::default(),
-            timings: Timings::default(),
-            volume_settings: VolumeSettings::default(),
+            sound: SoundSettings::default(),
             shortcuts: Shortcuts::default(),
             enabled: true,
             hidden: false,
@@ -363,11 +890,127 @@ impl Default for Config {
             use_ingame_time: false,
             ui_scale: 1.0,
             toast_visibility: ToastVisibility::default(),
+            use_clickpack_defaults: UseClickpackDefaults::default(),
+            rng_seed: None,
+            reseed_every_attempt: false,
+            selection_strategy: SelectionStrategy::default(),
+            hold_aware_timings: false,
+            click_gap_from_click: false,
+            fallbacks: ClickFallbacks::default(),
+            profiles: SoundProfiles::default(),
+            panning: Panning::default(),
+            click_rate: ClickRateSettings::default(),
+            normalization: Normalization::default(),
+            sample_settings: SampleSettings::default(),
+            noise: NoiseSettings::default(),
+            release_synth: ReleaseSynth::default(),
+            excluded_sample_tags: vec![],
+            manifest: None,
         }
     }
 }
 
 impl Config {
+    fn profile_slot(&self, kind: ProfileKind) -> Option<&SoundSettings> {
+        match kind {
+            ProfileKind::Player1Jump => Some(&self.sound),
+            ProfileKind::Player2Jump => self.profiles.player2.as_ref(),
+            ProfileKind::Player1Platformer => self.profiles.platformer.as_ref(),
+            ProfileKind::Player2Platformer => self.profiles.platformer_player2.as_ref(),
+        }
+    }
+
+    /// The profile whose settings are used for `kind`.
+    pub fn resolve_profile(&self, mut kind: ProfileKind) -> ProfileKind {
+        while self.profile_slot(kind).is_none() {
+            kind = kind.fallback().unwrap_or_default();
+        }
+        kind
+    }
+
+    /// Settings used for `kind`, which may come from another profile.
+    pub fn profile(&self, kind: ProfileKind) -> &SoundSettings {
+        self.profile_slot(self.resolve_profile(kind))
+            .unwrap_or(&self.sound)
+    }
+
+    /// Settings used for `kind`, see [`Config::profile`].
+    pub fn profile_mut(&mut self, kind: ProfileKind) -> &mut SoundSettings {
+        let kind = self.resolve_profile(kind);
+        let profiles = &mut self.profiles;
+        let profile = match kind {
+            ProfileKind::Player1Jump => None,
+            ProfileKind::Player2Jump => profiles.player2.as_mut(),
+            ProfileKind::Player1Platformer => profiles.platformer.as_mut(),
+            ProfileKind::Player2Platformer => profiles.platformer_player2.as_mut(),
+        };
+        profile.unwrap_or(&mut self.sound)
+    }
+
+    /// Gives a profile its own settings, starting from the ones it used before, or
+    /// removes them.
+    pub fn set_profile_enabled(&mut self, kind: ProfileKind, enabled: bool) {
+        let settings = enabled.then(|| self.profile(kind).clone());
+        let profiles = &mut self.profiles;
+        match kind {
+            ProfileKind::Player1Jump => {}
+            ProfileKind::Player2Jump => profiles.player2 = settings,
+            ProfileKind::Player1Platformer => profiles.platformer = settings,
+            ProfileKind::Player2Platformer => profiles.platformer_player2 = settings,
+        }
+    }
+
+    /// The clickpack manifest, if the user chose to use its settings for `used`.
+    fn manifest_for(&self, used: fn(&UseClickpackDefaults) -> bool) -> Option<&ClickpackManifest> {
+        self.manifest
+            .as_ref()
+            .filter(|_| used(&self.use_clickpack_defaults))
+    }
+
+    /// Settings played with for `kind`: [`Config::profile`] with the settings recommended
+    /// by the clickpack on top. Profiles with their own settings are left as they are.
+    pub fn sound_settings(&self, kind: ProfileKind) -> EffectiveSoundSettings<'_> {
+        let mut settings = EffectiveSoundSettings::from(self.profile(kind));
+        if self.resolve_profile(kind) != ProfileKind::Player1Jump {
+            return settings;
+        }
+        if let Some(manifest) = self.manifest_for(|used| used.timings) {
+            if let Some(timings) = &manifest.timings {
+                settings.timings = timings;
+            }
+            if let Some(timings) = &manifest.release_timings {
+                settings.release_timings = timings;
+            }
+        }
+        if let Some(pitch) = self
+            .manifest_for(|used| used.pitch)
+            .and_then(|manifest| manifest.pitch.as_ref())
+        {
+            settings.pitch = pitch;
+        }
+        if let Some(volume_settings) = self
+            .manifest_for(|used| used.volume_settings)
+            .and_then(|manifest| manifest.volume_settings.as_ref())
+        {
+            settings.volume_settings = volume_settings;
+        }
+        settings
+    }
+
+    /// Noise volume played with, see [`Config::sound_settings`].
+    pub fn effective_noise_volume(&self) -> f64 {
+        self.manifest_for(|used| used.noise_volume)
+            .and_then(|manifest| manifest.noise_volume)
+            .unwrap_or(self.noise_volume)
+    }
+
+    /// Sound selection played with, see [`Config::sound_settings`].
+    pub fn effective_selection_strategy(&self) -> SelectionStrategy {
+        self.manifest_for(|used| used.selection_strategy)
+            .and_then(|manifest| manifest.selection_strategy)
+            .unwrap_or(self.selection_strategy)
+    }
+
     pub fn load() -> Result<Self> {
         let mut path = PathBuf::from(".zcb/");
         log::debug!("creating directory {path:?}");
@@ -409,6 +1052,12 @@ impl Config {
 struct ClickTime {
     time: f64,
     typ: ClickType,
+    /// Time of the last played click
+    push_time: f64,
+    /// Time of the last played release
+    release_time: f64,
+    /// Clicks per second estimate, see [`ClickRateSettings`]
+    click_rate: f64,
 }
 
 #[derive(Default)]
@@ -437,6 +1086,40 @@ impl ClickTimes {
         }
     }
 
+    /// Buttons whose last action was a push, as `(button, player2)`.
+    fn held_buttons(&self, decouple: bool) -> Vec<(Button, bool)> {
+        let mut held = vec![];
+        for button in [Button::Jump, Button::Left, Button::Right] {
+            // without decoupling right shares the left button state
+            if button == Button::Right && !decouple {
+                continue;
+            }
+            for player2 in [false, true] {
+                if self.get_prev_time(button, player2, decouple).typ.is_click() {
+                    held.push((button, player2));
+                }
+            }
+        }
+        held
+    }
+
+    /// Type of the last click or release that was played for the button.
+    #[inline]
+    pub const fn last_click_type(
+        &self,
+        button: Button,
+        player2: bool,
+        decouple: bool,
+    ) -> ClickType {
+        self.get_prev_time(button, player2, decouple).typ
+    }
+
+    /// Clicks per second estimate for the button as of its last click.
+    #[inline]
+    pub const fn click_rate(&self, button: Button, player2: bool, decouple: bool) -> f64 {
+        self.get_prev_time(button, player2, decouple).click_rate
+    }
+
     #[inline]
     const fn get_prev_time(&self, button: Button, player2: bool, decouple: bool) -> ClickTime {
         match button {
@@ -453,27 +1136,233 @@ impl ClickTimes {
     }
 }
 
+/// A click chosen by [`ClickState::next_click`], ready to be played.
+pub struct PlannedClick {
+    pub typ: ClickType,
+    pub sound: SoundWrapper,
+    pub pitch: f64,
+    pub volume: f64,
+    /// Stereo pan from -1 (left) to 1 (right)
+    pub pan: f64,
+    /// Low-pass filter amount from 0 (none) to 1
+    pub lowpass: f64,
+    /// Whether all currently playing clicks should be stopped first (acb behaviour)
+    pub cut: bool,
+}
+
+/// State of the click algorithm, shared by live playback and offline rendering.
+pub struct ClickState {
+    pub prev_times: ClickTimes,
+    pub prev_pitch: f64,
+    pub prev_volume: f64,
+    pub prev_spam_offset: f64,
+    /// All randomness goes through this, so a seed gives the same clicks every time
+    pub rng: Rng,
+    pub selector: SampleSelector,
+}
+
+impl Default for ClickState {
+    fn default() -> Self {
+        Self {
+            prev_times: ClickTimes::default(),
+            prev_pitch: f64::NAN,
+            prev_volume: f64::NAN,
+            prev_spam_offset: f64::NAN,
+            rng: Rng::new(),
+            selector: SampleSelector::default(),
+        }
+    }
+}
+
+impl ClickState {
+    pub fn reset(&mut self, conf: &Config) {
+        self.prev_times = ClickTimes::default();
+        self.prev_pitch = 0.0;
+        self.prev_volume = conf
+            .sound_settings(ProfileKind::Player1Jump)
+            .volume_settings
+            .global_volume;
+        self.prev_spam_offset = 0.0;
+        self.reseed(conf);
+    }
+
+    /// Restarts the random sequence if the config has a seed.
+    pub fn reseed(&mut self, conf: &Config) {
+        if let Some(seed) = conf.rng_seed {
+            self.rng.seed(seed);
+            self.selector.clear();
+        }
+    }
+
+    /// Runs the click algorithm for an action that happened at `now` seconds.
+    ///
+    /// Returns `None` if the action shouldn't play a sound.
+    pub fn next_click(
+        &mut self,
+        conf: &Config,
+        clickpack: &mut Clickpack,
+        now: f64,
+        button: Button,
+        player2: bool,
+        push: bool,
+    ) -> Option<PlannedClick> {
+        if button.is_platformer()
+            && !conf.force_playing_platformer
+            && !clickpack.has_platformer_sounds
+        {
+            return None;
+        }
+
+        let prev_time = self
+            .prev_times
+            .get_prev_time(button, player2, conf.decouple_platformer);
+        if prev_time.typ.is_click() == push {
+            return None;
+        }
+        let since = if !conf.hold_aware_timings {
+            prev_time.time
+        } else if !push || conf.click_gap_from_click {
+            // releases are timed by the hold duration
+            prev_time.push_time
+        } else {
+            prev_time.release_time
+        };
+        let dt = (now - since).abs();
+        let settings = conf.sound_settings(ProfileKind::of(button, player2));
+        let timings = settings.timings_for(push, conf.hold_aware_timings);
+        let click_type = ClickType::from_time(push, dt, timings);
+        if conf.ignored_click_types.is_ignored(click_type) {
+            return None;
+        }
+
+        let (push_time, release_time) = if push {
+            (now, prev_time.release_time)
+        } else {
+            (prev_time.push_time, now)
+        };
+        // releases keep the rate of their click
+        let click_rate = if push {
+            conf.click_rate
+                .update(prev_time.click_rate, now - prev_time.push_time)
+        } else {
+            prev_time.click_rate
+        };
+        self.prev_times.set_time(
+            button,
+            player2,
+            ClickTime {
+                time: now,
+                typ: click_type,
+                push_time,
+                release_time,
+                click_rate,
+            },
+            conf.decouple_platformer,
+        );
+
+        // get click
+        let query = ClickQuery {
+            typ: click_type,
+            dt,
+            timings,
+            fallback: conf.fallbacks.get(click_type),
+            excluded_tags: &conf.excluded_sample_tags,
+        };
+        let (selector, rng) = (&mut self.selector, &mut self.rng);
+        let strategy = conf.effective_selection_strategy();
+        let pick = clickpack.get_random_click(
+            &query,
+            player2,
+            button,
+            |slot, tier, release, sounds, candidates| {
+                selector.pick(strategy, (slot, tier, release), sounds, candidates, rng)
+            },
+        )?;
+        let (key, tier_offset) = (pick.key, pick.tier_offset);
+        let sound = clickpack
+            .use_sound(key)
+            .map_err(|e| log::warn!("can't play sound: {e}"))
+            .ok()?
+            .clone();
+        let vol = settings.volume_settings;
+        let is_spam = (push || vol.change_releases_volume) && dt < vol.spam_time && vol.enabled;
+
+        let mut pitch = settings.get_pitch(&mut self.rng) * conf.click_speedhack;
+        // shift sounds from another tier towards the wanted one
+        let fallbacks = &conf.fallbacks;
+        pitch *= (1.0 + fallbacks.synth_pitch_step).powi(tier_offset);
+        if is_spam && vol.spam_curves {
+            pitch *= vol.spam_pitch_curve.eval(dt);
+        }
+        let rate_amount = conf.click_rate.amount(click_rate);
+        pitch *= 1.0 + (conf.click_rate.pitch - 1.0) * rate_amount;
+        pitch *= sound.meta.pitch_factor();
+        // if self.conf.sync_speed_with_game {
+        //     pitch *= gd_audio_pitch() as f64;
+        // }
+
+        // compute volume
+        let mut volume = 1.0;
+        if vol.volume_var != 0.0 {
+            volume += utils::f64_range(&mut self.rng, -vol.volume_var..=vol.volume_var);
+        }
+
+        // calculate spam volume change
+        if is_spam && vol.spam_curves {
+            let factor = vol.spam_volume_curve.eval(dt);
+            self.prev_spam_offset = 1.0 - factor;
+            volume *= factor;
+        } else if is_spam {
+            let offset = (vol.spam_time - dt) * vol.spam_vol_offset_factor;
+            self.prev_spam_offset = offset;
+            volume -= offset.min(vol.max_spam_vol_offset);
+        } else {
+            self.prev_spam_offset = 0.0;
+        }
+
+        if button.is_platformer() {
+            volume *= vol.platformer_volume_factor;
+        }
+
+        volume *= (1.0 - fallbacks.synth_volume_step).powi(tier_offset);
+        volume *= 1.0 + (conf.click_rate.volume - 1.0) * rate_amount;
+        volume *= sound.gain * sound.meta.volume();
+
+        // multiply by global volume after all of the changes
+        volume *= vol.global_volume;
+
+        self.prev_pitch = pitch;
+        self.prev_volume = volume;
+
+        let pan = conf.panning.get(button, player2, &mut self.rng);
+
+        Some(PlannedClick {
+            typ: click_type,
+            sound,
+            pitch,
+            volume,
+            pan,
+            lowpass: conf.click_rate.lowpass.clamp(0.0, 1.0) * rate_amount,
+            cut: conf.cut_sounds && (!click_type.is_release() || conf.cut_by_releases),
+        })
+    }
+}
+
 pub struct Bot {
     pub conf: Config,
     pub prev_conf: Config,
     pub last_conf_save: Instant,
-    pub mixer: Mixer,
+    pub audio: Box<dyn AudioBackend>,
     #[cfg(not(feature = "geode"))]
     pub playlayer: PlayLayer,
-    pub prev_times: ClickTimes,
+    pub click_state: ClickState,
     pub is_loading_clickpack: Arc<AtomicBool>,
-    pub prev_pitch: f64,
-    pub prev_volume: f64,
-    pub prev_spam_offset: f64,
     pub buffer_size_changed: bool,
-    pub noise_sound: Option<SoundHandle>,
     pub clickpacks: Vec<String>,
     pub last_clickpack_reload: Instant,
     pub system: FmodSystem,
-    pub channel: *mut FMOD_CHANNEL,
     pub env: Env,
     pub toasts: Arc<Mutex<Toasts>>,
-    pub fmod_noise_sound: *mut FMOD_CHANNEL,
     pub show_fmod_buffersize_warn: bool,
     pub startup_buffer_size: u32,
     pub is_in_level: bool,
@@ -487,34 +1376,45 @@ pub struct Bot {
     pub dead_timer: f32,
     pub dead_timer_limit: f32,
     pub devices: Arc<Mutex<Vec<String>>>,
+    /// Used instead of the level time if set, e.g. by tests
+    pub time_override: Option<f64>,
+    /// Profile edited in the audio tab
+    pub profile_tab: ProfileKind,
+    pub noise_fade: NoiseFade,
+    pub noise_duck: NoiseDuck,
+    pub last_noise_update: Instant,
+    /// Whether the player died in this attempt
+    pub is_dead: bool,
+    /// Last release played by the "Preview" button of the release synthesis
+    pub release_preview: Option<SoundWrapper>,
 }
 
 impl Default for Bot {
     fn default() -> Self {
-        let conf = Config::load().unwrap_or_default().fixup();
+        Self::with_config(Config::load().unwrap_or_default().fixup(), Env::load())
+    }
+}
+
+impl Bot {
+    /// Creates a bot without reading or writing anything in .zcb.
+    pub fn with_config(conf: Config, env: Env) -> Self {
         let startup_buffer_size = conf.buffer_size;
         let now = Instant::now();
         Self {
             conf: conf.clone(),
             prev_conf: conf,
             last_conf_save: now,
-            mixer: Mixer::new(),
+            audio: Box::<NullBackend>::default(),
             #[cfg(not(feature = "geode"))]
             playlayer: PlayLayer::NULL,
-            prev_times: ClickTimes::default(),
+            click_state: ClickState::default(),
             is_loading_clickpack: Arc::new(AtomicBool::new(false)),
-            prev_pitch: f64::NAN,
-            prev_volume: f64::NAN,
-            prev_spam_offset: f64::NAN,
             buffer_size_changed: false,
-            noise_sound: None,
             clickpacks: vec![],
             last_clickpack_reload: now,
             system: FmodSystem::new(std::ptr::null_mut()),
-            channel: std::ptr::null_mut(),
-            env: Env::load(),
+            env,
             toasts: Arc::new(Mutex::new(Toasts::new())),
-            fmod_noise_sound: std::ptr::null_mut(),
             show_fmod_buffersize_warn: false,
             startup_buffer_size,
             is_in_level: false,
@@ -528,6 +1428,13 @@ impl Default for Bot {
             dead_timer: f32::NAN,
             dead_timer_limit: 0.0,
             devices: Arc::new(Mutex::new(vec![])),
+            time_override: None,
+            profile_tab: ProfileKind::default(),
+            noise_fade: NoiseFade::default(),
+            noise_duck: NoiseDuck::default(),
+            last_noise_update: now,
+            is_dead: false,
+            release_preview: None,
         }
     }
 }
@@ -537,10 +1444,239 @@ fn help_text<R>(ui: &mut egui::Ui, help: &str, add_contents: impl FnOnce(&mut eg
         add_contents(ui); // don't show help icon if there's no help text
         return;
     }
-    ui.horizontal(|ui| {
-        add_contents(ui);
-        ui.add_enabled_ui(false, |ui| ui.label("(?)").on_disabled_hover_text(help));
-    });
+    ui.horizontal(|ui| {
+        add_contents(ui);
+        ui.add_enabled_ui(false, |ui| ui.label("(?)").on_disabled_hover_text(help));
+    });
+}
+
+/// Drag values for the hard/regular/soft thresholds and the clickpack's extra tiers.
+/// `measured` is the time they are compared against and `names` the click types each
+/// threshold plays, hardest first.
+fn timings_edit(
+    ui: &mut egui::Ui,
+    timings: &mut Timings,
+    measured: &str,
+    names: [&str; 4],
+    extra_tiers: &[String],
+) {
+    let timings_copy = timings.clone();
+    drag_value(
+        ui,
+        &mut timings.hard,
+        "Hard timing",
+        timings_copy.regular..=f64::INFINITY,
+        &format!("Anything above this {measured} plays {}", names[0]),
+    );
+    drag_value(
+        ui,
+        &mut timings.regular,
+        "Regular timing",
+        timings_copy.soft..=timings_copy.hard,
+        &format!("Anything above this {measured} plays {}", names[1]),
+    );
+    drag_value(
+        ui,
+        &mut timings.soft,
+        "Soft timing",
+        0.0..=timings_copy.regular,
+        &format!("Anything above this {measured} plays {}", names[2]),
+    );
+    ui.label(format!(
+        "Any value smaller than {:.2?} plays {}",
+        Duration::from_secs_f64(timings.soft),
+        names[3],
+    ));
+
+    for tier in extra_tiers {
+        // e.g. "ultrasoftclicks/ultrasoftreleases"
+        let tier_names = names[1]
+            .split('/')
+            .map(|kind| format!("{tier}{kind}"))
+            .collect::<Vec<_>>()
+            .join("/");
+        if let Some(mut threshold) = timings.threshold(tier) {
+            let changed = drag_value(
+                ui,
+                &mut threshold,
+                format!("\"{tier}\" timing"),
+                0.0..=f64::INFINITY,
+                &format!(
+                    "Anything above this {measured} plays {tier_names}, \
+                    unless a harder tier matches"
+                ),
+            )
+            .changed();
+            if changed {
+                timings.tiers.insert(tier.clone(), threshold);
+            }
+        } else {
+            ui.horizontal(|ui| {
+                ui.label(format!("{tier_names} have no timing and won't play"));
+                if ui.button("Set").clicked() {
+                    timings.tiers.insert(tier.clone(), timings.soft);
+                }
+            });
+        }
+    }
+}
+
+/// Plot of a [`Curve`] with draggable points. Double-click adds a point, right-click
+/// removes one. Points are kept inside `x_range` and `y_range`.
+pub fn curve_edit(
+    ui: &mut egui::Ui,
+    curve: &mut Curve,
+    x_range: RangeInclusive<f64>,
+    y_range: RangeInclusive<f64>,
+    format: impl Fn(f64, f64) -> String,
+) {
+    const POINT_RADIUS: f32 = 4.0;
+    let size = vec2(ui.available_width().min(320.0), 140.0);
+    let (response, painter) = ui.allocate_painter(size, egui::Sense::click());
+    let rect = response.rect;
+    let (x0, x1) = (*x_range.start(), *x_range.end());
+    let (y0, y1) = (*y_range.start(), *y_range.end());
+    let to_screen = |(x, y): (f64, f64)| {
+        let tx = ((x.clamp(x0, x1) - x0) / (x1 - x0)) as f32;
+        let ty = ((y.clamp(y0, y1) - y0) / (y1 - y0)) as f32;
+        rect.lerp_inside(vec2(tx, 1.0 - ty))
+    };
+    let from_screen = |pos: egui::Pos2| {
+        let t = (pos - rect.min) / rect.size();
+        (
+            (x0 + t.x as f64 * (x1 - x0)).clamp(x0, x1),
+            (y0 + (1.0 - t.y as f64) * (y1 - y0)).clamp(y0, y1),
+        )
+    };
+
+    let visuals = ui.visuals();
+    painter.rect_filled(rect, 2.0, visuals.extreme_bg_color);
+    if (y0..=y1).contains(&1.0) {
+        // unchanged volume/pitch
+        painter.hline(
+            rect.x_range(),
+            to_screen((x0, 1.0)).y,
+            visuals.widgets.noninteractive.bg_stroke,
+        );
+    }
+
+    let mut remove = None;
+    let mut hovered = None;
+    for i in 0..curve.points.len() {
+        let pos = to_screen(curve.points[i]);
+        let point_rect =
+            egui::Rect::from_center_size(pos, vec2(POINT_RADIUS * 3.0, POINT_RADIUS * 3.0));
+        let point_resp = ui.interact(
+            point_rect,
+            response.id.with(i),
+            egui::Sense::click_and_drag(),
+        );
+        if point_resp.dragged() {
+            if let Some(pointer) = point_resp.interact_pointer_pos() {
+                // keep points sorted by not letting them pass their neighbors
+                let (mut x, y) = from_screen(pointer);
+                if i > 0 {
+                    x = x.max(curve.points[i - 1].0);
+                }
+                if let Some(next) = curve.points.get(i + 1) {
+                    x = x.min(next.0);
+                }
+                curve.points[i] = (x, y);
+            }
+        }
+        if point_resp.secondary_clicked() {
+            remove = Some(i);
+        }
+        if point_resp.hovered() || point_resp.dragged() {
+            hovered = Some(i);
+        }
+    }
+    if let Some(i) = remove {
+        curve.points.remove(i);
+        // the removed point isn't there to hover anymore, and the ones after it moved
+        hovered = hovered
+            .filter(|&h| h != i)
+            .map(|h| if h > i { h - 1 } else { h });
+    }
+    if response.double_clicked() {
+        if let Some(pointer) = response.interact_pointer_pos() {
+            curve.insert(from_screen(pointer));
+        }
+    }
+
+    // the curve is flat outside its points, so extend it to the edges
+    let mut line = vec![to_screen((x0, curve.eval(x0)))];
+    line.extend(curve.points.iter().map(|&p| to_screen(p)));
+    line.push(to_screen((x1, curve.eval(x1))));
+    let stroke = visuals.widgets.active.fg_stroke;
+    painter.add(egui::Shape::line(line, stroke));
+    for (i, &point) in curve.points.iter().enumerate() {
+        let color = if hovered == Some(i) {
+            visuals.selection.bg_fill
+        } else {
+            stroke.color
+        };
+        painter.circle_filled(to_screen(point), POINT_RADIUS, color);
+    }
+
+    let hovered = hovered
+        .and_then(|i| curve.points.get(i).copied())
+        .or_else(|| {
+            let x = from_screen(response.hover_pos()?).0;
+            Some((x, curve.eval(x)))
+        });
+    ui.label(match hovered {
+        Some((x, y)) => format(x, y),
+        None => "Double-click to add a point, right-click a point to remove it".to_string(),
+    });
+}
+
+/// Editor for the click types of a [`Fallback::Order`].
+fn fallback_order_edit(ui: &mut egui::Ui, typ: ClickType, types: &mut Vec<ClickType>) {
+    let mut remove = None;
+    let mut swap = None;
+    for (i, t) in types.iter().enumerate() {
+        ui.horizontal(|ui| {
+            ui.label(format!("{}. {}", i + 1, t.text()));
+            if ui.add_enabled(i > 0, egui::Button::new("⏶")).clicked() {
+                swap = Some(i - 1);
+            }
+            if ui
+                .add_enabled(i + 1 < types.len(), egui::Button::new("⏷"))
+                .clicked()
+            {
+                swap = Some(i);
+            }
+            if ui.button("🗙").clicked() {
+                remove = Some(i);
+            }
+        });
+    }
+    if let Some(i) = swap {
+        types.swap(i, i + 1);
+    }
+    if let Some(i) = remove {
+        types.remove(i);
+    }
+    if types.is_empty() {
+        ui.label("Nothing is played if none of the click types have sounds");
+    }
+
+    let available: Vec<_> = ClickType::ALL
+        .into_iter()
+        .filter(|t| *t != typ && !types.contains(t))
+        .collect();
+    if !available.is_empty() {
+        egui::ComboBox::new(("fallback_add", typ.text()), "")
+            .selected_text("Add...")
+            .show_ui(ui, |ui| {
+                for t in available {
+                    if ui.selectable_label(false, t.text()).clicked() {
+                        types.push(t);
+                    }
+                }
+            });
+    }
 }
 
 /// Value is always min clamped with 1.
@@ -630,21 +1766,18 @@ fn ureq_get(url: &str) -> Result<Vec<u8>, String> {
 }
 
 impl Bot {
-    fn maybe_init_kittyaudio(&mut self) {
-        if self.conf.use_fmod {
-            return;
-        }
-        log::debug!("starting kittyaudio playback thread");
-        self.mixer = Mixer::new();
-        let device = self.get_device();
-
-        self.mixer.init_ex(
-            device,
-            StreamSettings {
-                buffer_size: Some(self.conf.buffer_size),
-                ..Default::default()
-            },
-        );
+    fn init_audio_backend(&mut self) {
+        self.audio = if self.conf.use_fmod {
+            log::info!("using fmod audio backend");
+            Box::new(FmodBackend::new(*self.system))
+        } else {
+            log::info!("using kittyaudio audio backend");
+            Box::new(KittyaudioBackend::new(
+                &self.env.selected_device,
+                self.conf.buffer_size,
+                self.conf.sample_settings.resample_rate(),
+            ))
+        };
     }
 
     pub unsafe fn init_fmod(&mut self) -> Result<()> {
@@ -710,6 +1843,9 @@ impl Bot {
     }
 
     pub fn release_fmod(&mut self) {
+        if self.system.is_null() {
+            return;
+        }
         let _ = unsafe {
             FMOD_System_Release(*self.system)
                 .fmod_result()
@@ -741,14 +1877,12 @@ impl Bot {
         }
 
         // init audio playback
-        if !self.conf.use_fmod {
-            self.maybe_init_kittyaudio();
-        }
         unsafe {
             let _ = self
                 .init_fmod()
                 .map_err(|e| log::error!("failed to init fmod: {e}"));
         };
+        self.init_audio_backend();
 
         // reload clickpacks
         let _ = self
@@ -844,14 +1978,6 @@ impl Bot {
         }
     }
 
-    fn get_pitch(&self) -> f64 {
-        if self.conf.pitch_enabled {
-            utils::f64_range(self.conf.pitch.from..=self.conf.pitch.to)
-        } else {
-            1.0
-        }
-    }
-
     #[allow(unused_variables)]
     pub fn on_init(&mut self, playlayer: usize) {
         #[cfg(not(feature = "geode"))]
@@ -859,42 +1985,55 @@ impl Bot {
             self.playlayer.addr = playlayer;
         }
 
-        self.prev_times = ClickTimes::default();
-        self.prev_pitch = 0.0;
-        self.prev_volume = self.conf.volume_settings.global_volume;
-        self.prev_spam_offset = 0.0;
+        self.click_state.reset(&self.conf);
         self.is_in_level = true;
+        self.is_dead = false;
         self.level_start = Instant::now();
         self.dead_timer = f32::NAN;
+        self.update_noise_for_level();
+    }
+
+    /// Called when the player enters a level, which also starts the first attempt.
+    pub fn on_level_start(&mut self, playlayer: usize) {
+        self.on_init(playlayer);
+        self.start_noise_attempt();
     }
 
     pub fn on_reset(&mut self) {
         self.level_start = Instant::now();
         //for dir in [
-        //    &mut self.prev_times.jump,
-        //    &mut self.prev_times.left,
-        //    &mut self.prev_times.right,
+        //    &mut self.click_state.prev_times.jump,
+        //    &mut self.click_state.prev_times.left,
+        //    &mut self.click_state.prev_times.right,
         //] {
         //    for t in dir {
         //        t.time = 0.0;
         //        t.typ = ClickType::None;
         //    }
         //}
-        self.prev_times = ClickTimes::default();
+        self.click_state.prev_times = ClickTimes::default();
+        if self.conf.reseed_every_attempt {
+            self.click_state.reseed(&self.conf);
+        }
         self.dead_timer = f32::NAN;
+        self.start_noise_attempt();
     }
 
     pub fn on_exit(&mut self) {
         self.on_init(0);
         self.is_in_level = false;
         self.dead_timer = f32::NAN;
+        self.update_noise_for_level();
+        self.update_noise_duck();
     }
 
     pub unsafe fn on_death(&mut self) {
-        // nop for now
-        /*
+        self.is_dead = true;
+        self.update_noise_duck();
+
         let mut release_delay = self.conf.death_release_delay;
         let offset = self.conf.death_release_delay_offset;
+        let rng = &mut self.click_state.rng;
 
         // release all buttons that are still pressed (or do that in the future)
         if release_delay == 0.0 && offset == 0.0 {
@@ -902,24 +2041,22 @@ impl Bot {
         } else {
             if offset != 0.0 {
                 if self.conf.death_release_delay_neg {
-                    release_delay += utils::f64_range(-offset..=offset);
+                    release_delay += utils::f64_range(rng, -offset..=offset);
                 } else {
-                    release_delay += utils::f64_range(0.0..=offset);
+                    release_delay += utils::f64_range(rng, 0.0..=offset);
                 }
             }
             if release_delay <= 0.0 {
                 self.release_buttons();
                 return;
             }
+            log::debug!("releasing held buttons in {release_delay:.3}s");
             self.dead_timer_limit = release_delay as f32;
             self.dead_timer = 0.0;
         }
-        */
     }
 
-    pub unsafe fn on_update(&mut self, _dt: f32) {
-        // nop for now
-        /*
+    pub unsafe fn on_update(&mut self, dt: f32) {
         if !self.dead_timer.is_nan() {
             self.dead_timer += dt;
             if self.dead_timer >= self.dead_timer_limit {
@@ -927,7 +2064,17 @@ impl Bot {
                 self.release_buttons();
             }
         }
-        */
+    }
+
+    /// Plays releases for all buttons that are currently held.
+    unsafe fn release_buttons(&mut self) {
+        for (button, player2) in self
+            .click_state
+            .prev_times
+            .held_buttons(self.conf.decouple_platformer)
+        {
+            self.on_action(button, player2, false);
+        }
     }
 
     #[allow(unused_mut)]
@@ -945,123 +2092,39 @@ impl Bot {
         if !self.playlayer.is_null() {
             return;
         }
-        if button.is_platformer()
-            && !self.conf.force_playing_platformer
-            && !self.clickpack.has_platformer_sounds
-        {
-            return;
-        }
-
         let now = self.time();
         if now == 0.0 {
             return;
         }
-        let prev_time =
-            self.prev_times
-                .get_prev_time(button, player2, self.conf.decouple_platformer);
-        if prev_time.typ.is_click() == push {
-            return;
-        }
-        let dt = (now - prev_time.time).abs();
-        let click_type = ClickType::from_time(push, dt, &self.conf.timings);
-        if self.conf.ignored_click_types.is_ignored(click_type) {
+        let Some(click) = self.click_state.next_click(
+            &self.conf,
+            &mut self.clickpack,
+            now,
+            button,
+            player2,
+            push,
+        ) else {
             return;
-        }
-        let use_fmod = self.conf.use_fmod;
-
-        // get click
-        let mut click = self
-            .clickpack
-            .get_random_click(click_type, player2, button)
-            .clone();
-        let pitch = self.get_pitch() * self.conf.click_speedhack;
-        // if self.conf.sync_speed_with_game {
-        //     pitch *= gd_audio_pitch() as f64;
-        // }
-        if !use_fmod {
-            click.set_playback_rate(PlaybackRate::Factor(pitch));
-        }
-
-        // compute & change volume
-        {
-            let vol = &self.conf.volume_settings;
-            let mut volume = 1.0;
-            if vol.volume_var != 0.0 {
-                volume += utils::f64_range(-vol.volume_var..=vol.volume_var);
-            }
-
-            // calculate spam volume change
-            if (push || vol.change_releases_volume) && dt < vol.spam_time && vol.enabled {
-                let offset = (vol.spam_time - dt) * vol.spam_vol_offset_factor;
-                self.prev_spam_offset = offset;
-                volume -= offset.min(vol.max_spam_vol_offset);
-            } else {
-                self.prev_spam_offset = 0.0;
-            }
-
-            if button.is_platformer() {
-                volume *= vol.platformer_volume_factor;
-            }
-
-            // multiply by global volume after all of the changes
-            volume *= vol.global_volume;
-
-            if !use_fmod {
-                // blehh
-                click.set_volume(volume as f32);
-            }
-            self.prev_volume = volume;
-        }
-
+        };
         // stop all playing sounds (acb behaviour)
-        if !use_fmod
-            && self.conf.cut_sounds
-            && (!click_type.is_release() || self.conf.cut_by_releases)
-        {
-            for sound in &self.mixer.renderer.guard().sounds {
-                // check if this is the noise sound, we don't want to stop it
-                let sound_len = sound.guard().frames.len();
-                if let Some(noise_sound) = &self.noise_sound {
-                    if noise_sound.guard().frames.len() == sound_len {
-                        continue;
-                    }
-                }
-
-                // kis!!
-                sound.seek_to_end();
-            }
-        }
-        // FIXME: temporary 2.2 fix
-        if !use_fmod {
-            self.mixer.play(click.sound);
-        } else {
-            unsafe {
-                FMOD_System_PlaySound(
-                    *self.system,
-                    click.fmod_sound,
-                    std::ptr::null_mut(),
-                    0,
-                    &mut self.channel,
-                );
-                FMOD_Channel_SetPitch(self.channel, pitch as f32);
-                FMOD_Channel_SetVolume(self.channel, self.prev_volume as _);
-                FMOD_System_Update(*self.system);
-            }
+        if click.cut {
+            self.audio.cut();
         }
-        self.prev_times.set_time(
-            button,
-            player2,
-            ClickTime {
-                time: now,
-                typ: click_type,
-            },
-            self.conf.decouple_platformer,
+        self.audio.play(
+            click.sound,
+            click.pitch,
+            click.volume,
+            click.pan,
+            click.lowpass,
         );
-        self.prev_pitch = pitch;
+        self.duck_noise_for_click();
     }
 
     #[inline]
     fn time(&self) -> f64 {
+        if let Some(time) = self.time_override {
+            return time;
+        }
         #[cfg(feature = "geode")]
         if self.playlayer_time != 0.0 && self.conf.use_ingame_time {
             self.playlayer_time
@@ -1092,7 +2155,7 @@ impl Bot {
         for entry in dir {
             let entry = entry?;
             let path = entry.path();
-            if path.is_dir() {
+            if path.is_dir() || vfs::is_zip(&path) {
                 let name = path.file_name().unwrap().to_string_lossy().to_string();
                 self.clickpack_db.mark_downloaded(&name, path, true);
                 self.clickpacks.push(name);
@@ -1108,6 +2171,12 @@ impl Bot {
     }
 
     pub fn draw_ui(&mut self, ctx: &egui::Context) {
+        // fade the noise, this runs every frame even if the menu is closed
+        let now = Instant::now();
+        let dt = now.duration_since(self.last_noise_update).as_secs_f64();
+        self.last_noise_update = now;
+        self.update_noise_fade(dt);
+
         // process hotkeys
         let wants_keyboard = ctx.wants_keyboard_input();
         let (toggle_menu, toggle_bot, toggle_noise) = ctx.input_mut(|i| {
@@ -1452,10 +2521,6 @@ impl Bot {
         ui.allocate_space(ui.available_size() - vec2(0.0, 280.0));
     }
 
-    fn get_device(&mut self) -> Device {
-        Device::from_name(&self.env.selected_device).unwrap_or_default()
-    }
-
     fn show_device_switcher(&mut self, ui: &mut egui::Ui) {
         ui.horizontal(|ui| {
             egui::ComboBox::from_label("Output device")
@@ -1469,9 +2534,8 @@ impl Bot {
                             .clicked()
                             && !is_selected
                         {
-                            // start a new mixer on new device
                             log::info!("switching audio device to \"{device}\"");
-                            self.maybe_init_kittyaudio();
+                            self.audio.set_device(device);
                             self.play_noise();
                             self.env.save();
                             self.toasts
@@ -1490,8 +2554,7 @@ impl Bot {
                 .on_hover_text("Reset to the default audio device")
                 .clicked()
             {
-                self.mixer = Mixer::new();
-                self.mixer.init();
+                self.audio.set_device("");
                 if let Ok(name) = Device::Default.name() {
                     self.env.selected_device = name.clone();
                     self.toasts
@@ -1505,75 +2568,251 @@ impl Bot {
         });
     }
 
-    fn play_noise(&mut self) {
-        let stop_kittyaudio_noise = |noise_sound: &mut Option<SoundHandle>| {
-            if let Some(noise_sound) = noise_sound {
-                noise_sound.set_playback_rate(PlaybackRate::Factor(1.0));
-                noise_sound.set_loop_enabled(false);
-                noise_sound.seek_to_end();
-            }
-            *noise_sound = None;
-        };
-        let stop_fmod_noise = |fmodn: &mut *mut FMOD_CHANNEL| {
-            unsafe { FMOD_Channel_Stop(*fmodn) };
-            *fmodn = std::ptr::null_mut();
-        };
-        let mut start_kittyaudio_noise = |noise_sound: &mut Option<SoundHandle>| {
-            if let Some(mut noise) = self.clickpack.noise.clone() {
-                noise.set_volume(self.conf.noise_volume as f32);
-                noise.set_loop_enabled(true);
-                let frames = noise.frames.len().saturating_sub(1);
-                noise.set_loop_index(0..=frames);
-                noise.set_playback_rate(PlaybackRate::Factor(self.conf.noise_speedhack));
-                *noise_sound = Some(self.mixer.play(noise.sound));
+    fn should_play_noise(&self) -> bool {
+        self.conf.play_noise
+            && (self.conf.enabled || self.conf.play_noise_when_disabled)
+            && (self.is_in_level || !self.conf.noise.only_in_levels)
+            && self.clickpack.has_noise()
+    }
+
+    /// Restarts the noise with the current settings, or fades it out if it shouldn't
+    /// play.
+    pub fn play_noise(&mut self) {
+        let noise = &self.conf.noise;
+        if !self.should_play_noise() {
+            if self.noise_fade.playing && noise.fade_out > 0.0 {
+                self.noise_fade.active = false; // stopped by update_noise_fade
+            } else {
+                self.stop_noise();
             }
-        };
-        let start_fmod_noise = |fmodn: &mut *mut FMOD_CHANNEL| unsafe {
-            if let Some(noise) = self.clickpack.noise.clone() {
-                // get sound length
-                // let mut length = 0u32;
-                // FMOD_Sound_GetLength(noise.fmod_sound, &mut length, FMOD_TIMEUNIT_PCM);
-
-                // set loop points for sound
-                FMOD_Sound_SetLoopCount(noise.fmod_sound, i32::MAX);
-                // FMOD_Sound_SetLoopPoints(
-                //     noise.fmod_sound,
-                //     0,
-                //     FMOD_TIMEUNIT_PCM,
-                //     length,
-                //     FMOD_TIMEUNIT_PCM,
-                // );
-
-                // play the sound
-                FMOD_System_PlaySound(
-                    *self.system,
-                    noise.fmod_sound,
-                    std::ptr::null_mut(),
-                    0,
-                    fmodn,
-                );
+            return;
+        }
 
-                // update channel
-                FMOD_Channel_SetVolume(*fmodn, self.conf.noise_volume as _);
-                // FMOD_Channel_SetLoopPoints(*fmodn, 0, FMOD_TIMEUNIT_PCM, 1024, FMOD_TIMEUNIT_PCM);
-                FMOD_Channel_SetLoopCount(*fmodn, i32::MAX);
-                FMOD_Channel_SetPitch(*fmodn, self.conf.noise_speedhack as f32);
-                FMOD_System_Update(*self.system);
+        // keep the volume of a fade that's in progress
+        if !self.noise_fade.playing {
+            self.noise_fade.gain = if noise.fade_in > 0.0 { 0.0 } else { 1.0 };
+        }
+        self.audio.stop_noise();
+        self.audio.set_noise_gain(self.noise_gain());
+        for layer in &self.clickpack.noise {
+            let settings = noise.layer(&layer.name);
+            if !settings.enabled {
+                continue;
             }
-        };
+            let len = layer.sound.frames.len();
+            let start = if noise.random_start && len > 0 {
+                fastrand::usize(..len)
+            } else {
+                0
+            };
+            self.audio.start_noise(
+                &layer.sound,
+                self.conf.noise_speedhack,
+                self.conf.effective_noise_volume() * settings.volume,
+                start,
+            );
+        }
+        self.noise_fade.active = true;
+        self.noise_fade.playing = true;
+        self.last_noise_update = Instant::now();
+    }
+
+    /// Starts or stops the noise when entering or leaving a level, without restarting
+    /// noise that is already playing.
+    fn update_noise_for_level(&mut self) {
+        if !self.conf.noise.only_in_levels {
+            return;
+        }
+        if self.noise_fade.playing && self.should_play_noise() {
+            self.noise_fade.active = true;
+        } else {
+            self.play_noise();
+        }
+    }
+
+    /// Volume the noise should be at for the current game state, before fades.
+    fn noise_state_volume(&self) -> f64 {
+        if !self.is_in_level {
+            self.conf.noise.menu_volume
+        } else if self.is_dead {
+            self.conf.noise.death_volume
+        } else {
+            1.0
+        }
+    }
 
-        stop_kittyaudio_noise(&mut self.noise_sound);
-        stop_fmod_noise(&mut self.fmod_noise_sound);
+    /// Moves the noise volume towards the one for the current game state.
+    fn update_noise_duck(&mut self) {
+        let target = self.noise_state_volume();
+        self.noise_duck
+            .duck_to(target, self.conf.noise.ducking_time);
+    }
 
-        if self.conf.play_noise && (self.conf.enabled || self.conf.play_noise_when_disabled) {
-            if self.conf.use_fmod {
-                start_fmod_noise(&mut self.fmod_noise_sound);
-            } else {
-                start_kittyaudio_noise(&mut self.noise_sound);
-            }
+    /// Called when an attempt starts (entering a level or resetting it), fades the noise
+    /// in if enabled. The new volume is applied by [`Bot::update_noise_fade`].
+    fn start_noise_attempt(&mut self) {
+        self.is_dead = false;
+        let attempt_fade_in = self.conf.noise.attempt_fade_in;
+        if attempt_fade_in > 0.0 {
+            self.noise_duck.gain = 0.0;
+            self.noise_duck
+                .duck_to(self.noise_state_volume(), attempt_fade_in);
+        } else {
+            self.update_noise_duck();
+        }
+    }
+
+    /// Ducks the noise under a click that was just played.
+    fn duck_noise_for_click(&mut self) {
+        if self.conf.noise.click_duck <= 0.0 || !self.noise_fade.playing {
+            return;
+        }
+        self.noise_duck.click = self.conf.noise.click_duck.min(1.0);
+        self.apply_noise_gain();
+    }
+
+    fn noise_gain(&self) -> f64 {
+        self.noise_fade.gain * self.noise_duck.volume()
+    }
+
+    fn apply_noise_gain(&mut self) {
+        if self.noise_fade.playing {
+            self.audio.set_noise_gain(self.noise_gain());
         }
     }
 
+    /// Advances noise fades and ducking by `dt` seconds, stopping the noise once it has
+    /// faded out.
+    pub fn update_noise_fade(&mut self, dt: f64) {
+        if !self.noise_fade.playing {
+            return;
+        }
+        let prev_gain = self.noise_gain();
+        // follows changes to the volumes in the menu
+        self.noise_duck.target = self.noise_state_volume();
+        self.noise_fade.advance(dt, &self.conf.noise);
+        self.noise_duck.advance(dt, &self.conf.noise);
+        if self.noise_gain() != prev_gain {
+            self.audio.set_noise_gain(self.noise_gain());
+        }
+        if !self.noise_fade.active && self.noise_fade.gain <= 0.0 {
+            self.stop_noise();
+        }
+    }
+
+    fn show_noise_layers(&mut self, ui: &mut egui::Ui) {
+        ui.collapsing("Noise layers", |ui| {
+            let mut restart = false;
+            for layer in &self.clickpack.noise {
+                let settings = self
+                    .conf
+                    .noise
+                    .layers
+                    .entry(layer.name.clone())
+                    .or_default();
+                ui.push_id(&layer.name, |ui| {
+                    ui.horizontal(|ui| {
+                        restart |= ui.checkbox(&mut settings.enabled, &layer.name).changed();
+                        restart |=
+                            drag_value(ui, &mut settings.volume, "Volume", 0.0..=f64::INFINITY, "")
+                                .drag_stopped();
+                    });
+                });
+            }
+            ui.separator();
+
+            let noise = &mut self.conf.noise;
+            help_text(
+                ui,
+                "Start every layer at a random point instead of the beginning",
+                |ui| {
+                    restart |= ui
+                        .checkbox(&mut noise.random_start, "Random start")
+                        .changed()
+                },
+            );
+            help_text(
+                ui,
+                "Stop the noise outside of levels, fading it in and out\n\
+                when a level starts and ends",
+                |ui| {
+                    restart |= ui
+                        .checkbox(&mut noise.only_in_levels, "Only in levels")
+                        .changed()
+                },
+            );
+            drag_value(
+                ui,
+                &mut noise.fade_in,
+                "Fade in (s)",
+                0.0..=10.0,
+                "Seconds to fade the noise in when it starts playing",
+            );
+            drag_value(
+                ui,
+                &mut noise.fade_out,
+                "Fade out (s)",
+                0.0..=10.0,
+                "Seconds to fade the noise out when the bot or the noise is turned off",
+            );
+            drag_value(
+                ui,
+                &mut noise.menu_volume,
+                "Menu volume",
+                0.0..=1.0,
+                "Volume of the noise outside of levels, 0 silences it\n\
+                without stopping it",
+            );
+            drag_value(
+                ui,
+                &mut noise.death_volume,
+                "Death volume",
+                0.0..=1.0,
+                "Volume of the noise after dying, until the level restarts",
+            );
+            drag_value(
+                ui,
+                &mut noise.ducking_time,
+                "Ducking time (s)",
+                0.0..=5.0,
+                "Seconds to move between the menu, level and death volumes",
+            );
+            drag_value(
+                ui,
+                &mut noise.attempt_fade_in,
+                "Attempt fade in (s)",
+                0.0..=10.0,
+                "Seconds to fade the noise in at the start of every attempt",
+            );
+            drag_value(
+                ui,
+                &mut noise.click_duck,
+                "Click ducking",
+                0.0..=1.0,
+                "How much quieter the noise gets under each click,\n\
+                so the clicks stay audible in recordings",
+            );
+            drag_value(
+                ui,
+                &mut noise.click_duck_release,
+                "Click ducking release (s)",
+                0.0..=1.0,
+                "Seconds for the noise to come back after a click",
+            );
+            drag_value(
+                ui,
+                &mut self.conf.sample_settings.loop_crossfade,
+                "Loop crossfade (s)",
+                0.0..=5.0,
+                "Seconds of the end of each layer blended into its start,\n\
+                so the loop has no seam. Applied when a clickpack is loaded",
+            );
+            if restart {
+                self.play_noise();
+            }
+        });
+    }
+
     fn open_noise_toggle_toast(&self) {
         self.toasts.lock().add(Toast::info(if self.conf.play_noise {
             "Playing noise"
@@ -1587,6 +2826,41 @@ impl Bot {
         self.is_loading_clickpack.load(Ordering::Relaxed)
     }
 
+    fn show_profile_selector(&mut self, ui: &mut egui::Ui) {
+        help_text(
+            ui,
+            "Timings, pitch and volume below apply to this profile.\n\
+            Profiles without their own settings use player 1 jump settings\n\
+            (player 2 left/right uses player 1 left/right)",
+            |ui| {
+                egui::ComboBox::from_label("Profile")
+                    .selected_text(self.profile_tab.text())
+                    .show_ui(ui, |ui| {
+                        for kind in ProfileKind::ALL {
+                            ui.selectable_value(&mut self.profile_tab, kind, kind.text());
+                        }
+                    });
+            },
+        );
+        if self.profile_tab != ProfileKind::Player1Jump {
+            let kind = self.profile_tab;
+            let mut enabled = self.conf.resolve_profile(kind) == kind;
+            if ui
+                .checkbox(&mut enabled, "Separate settings")
+                .on_hover_text("Copies the current settings when enabled")
+                .changed()
+            {
+                self.conf.set_profile_enabled(kind, enabled);
+            }
+            if !enabled {
+                ui.label(format!(
+                    "Editing {} settings",
+                    self.conf.resolve_profile(kind).text().to_lowercase()
+                ));
+            }
+        }
+    }
+
     fn show_audio_window(&mut self, ui: &mut egui::Ui) {
         ui.add_enabled_ui(
             self.clickpack.has_noise() && !self.is_loading_clickpack(),
@@ -1614,6 +2888,7 @@ impl Bot {
                         self.play_noise(); // restart noise
                     }
                 });
+                self.show_noise_layers(ui);
             },
         );
 
@@ -1622,12 +2897,7 @@ impl Bot {
             "Use the internal audio engine for integration with internal recorders",
             |ui| {
                 if ui.checkbox(&mut self.conf.use_fmod, "Use FMOD").changed() {
-                    if self.conf.use_fmod {
-                        log::info!("destroying kittyaudio mixer");
-                        self.mixer = Mixer::new();
-                    } else {
-                        self.maybe_init_kittyaudio();
-                    }
+                    self.init_audio_backend();
                     self.play_noise();
                 }
             },
@@ -1636,10 +2906,9 @@ impl Bot {
 
         ui.separator();
 
-        ui.collapsing("Timings", |ui| {
-            let timings_copy = self.conf.timings.clone();
-            let timings = &mut self.conf.timings;
+        self.show_profile_selector(ui);
 
+        ui.collapsing("Timings", |ui| {
             help_text(
                 ui,
                 "Use in-game level time instead of real time.\n\
@@ -1680,33 +2949,72 @@ impl Bot {
                 },
             );
 
-            ui.separator();
-
-            drag_value(
+            help_text(
                 ui,
-                &mut timings.hard,
-                "Hard timing",
-                timings_copy.regular..=f64::INFINITY,
-                "Anything above this time between clicks plays hardclicks/hardreleases",
+                "Classifies releases by how long the button was held and clicks by the \
+                time since the previous release, with separate timings for each",
+                |ui| {
+                    ui.checkbox(
+                        &mut self.conf.hold_aware_timings,
+                        "Separate click and release timings",
+                    );
+                },
             );
-            drag_value(
+            if self.conf.hold_aware_timings {
+                help_text(
+                    ui,
+                    "Time clicks from the previous click instead of the previous release",
+                    |ui| {
+                        ui.checkbox(
+                            &mut self.conf.click_gap_from_click,
+                            "Time clicks from previous click",
+                        );
+                    },
+                );
+            }
+
+            ui.separator();
+
+            let extra_tiers = self.clickpack.extra_tiers();
+            if !self.conf.hold_aware_timings {
+                timings_edit(
+                    ui,
+                    &mut self.conf.profile_mut(self.profile_tab).timings,
+                    "time between clicks",
+                    [
+                        "hardclicks/hardreleases",
+                        "clicks/releases",
+                        "softclicks/softreleases",
+                        "microclicks/microreleases",
+                    ],
+                    &extra_tiers,
+                );
+                return;
+            }
+
+            ui.label("Click timings");
+            let gap = if self.conf.click_gap_from_click {
+                "time since the previous click"
+            } else {
+                "time since the previous release"
+            };
+            let settings = self.conf.profile_mut(self.profile_tab);
+            timings_edit(
                 ui,
-                &mut timings.regular,
-                "Regular timing",
-                timings_copy.soft..=timings_copy.hard,
-                "Anything above this time between clicks plays clicks/releases",
+                &mut settings.timings,
+                gap,
+                ["hardclicks", "clicks", "softclicks", "microclicks"],
+                &extra_tiers,
             );
-            drag_value(
+            ui.separator();
+            ui.label("Release timings");
+            timings_edit(
                 ui,
-                &mut timings.soft,
-                "Soft timing",
-                0.0..=timings_copy.regular,
-                "Anything above this time between clicks plays softclicks/softreleases",
+                &mut settings.release_timings,
+                "hold duration",
+                ["hardreleases", "releases", "softreleases", "microreleases"],
+                &extra_tiers,
             );
-            ui.label(format!(
-                "Any value smaller than {:.2?} plays microclicks/microreleases",
-                Duration::from_secs_f64(timings.soft),
-            ))
         });
 
         ui.collapsing("Ignored click types", |ui| {
@@ -1728,14 +3036,84 @@ impl Bot {
             }
         });
 
+        ui.collapsing("Missing click types", |ui| {
+            ui.label("What to play when the clickpack doesn't have sounds for a click type");
+            let fallbacks = &mut self.conf.fallbacks;
+            for typ in ClickType::ALL {
+                let fallback = fallbacks.get_mut(typ);
+                egui::ComboBox::new(typ.text(), typ.text())
+                    .selected_text(fallback.text())
+                    .show_ui(ui, |ui| {
+                        for option in [
+                            Fallback::Nearest,
+                            Fallback::Order(vec![]),
+                            Fallback::Nothing,
+                            Fallback::Synthesize,
+                        ] {
+                            let selected =
+                                std::mem::discriminant(fallback) == std::mem::discriminant(&option);
+                            if ui.selectable_label(selected, option.text()).clicked() && !selected {
+                                *fallback = option;
+                            }
+                        }
+                    });
+                if let Fallback::Order(types) = fallback {
+                    ui.indent(typ.text(), |ui| fallback_order_edit(ui, typ, types));
+                }
+            }
+
+            let any_synthesized = ClickType::ALL
+                .iter()
+                .any(|typ| *fallbacks.get(*typ) == Fallback::Synthesize);
+            if any_synthesized {
+                ui.separator();
+                drag_value(
+                    ui,
+                    &mut fallbacks.synth_volume_step,
+                    "Volume change per tier",
+                    0.0..=1.0,
+                    "How much quieter a sound gets for each tier it's shifted softer",
+                );
+                drag_value(
+                    ui,
+                    &mut fallbacks.synth_pitch_step,
+                    "Pitch change per tier",
+                    -1.0..=1.0,
+                    "How much higher a sound gets for each tier it's shifted softer",
+                );
+            }
+            if *fallbacks != ClickFallbacks::default() && ui.button("Reset").clicked() {
+                *fallbacks = ClickFallbacks::default();
+            }
+        });
+
+        ui.collapsing("Sound selection", |ui| {
+            ui.label("How a sound is picked when there are multiple sounds for a click type");
+            egui::ComboBox::from_label("Selection")
+                .selected_text(self.conf.selection_strategy.text())
+                .show_ui(ui, |ui| {
+                    for strategy in SelectionStrategy::ALL {
+                        ui.selectable_value(
+                            &mut self.conf.selection_strategy,
+                            strategy,
+                            strategy.text(),
+                        );
+                    }
+                });
+            if self.conf.selection_strategy == SelectionStrategy::Weighted {
+                ui.label("Weights are set in the clickpack's zcb.json");
+            }
+        });
+
         ui.collapsing("Pitch variation", |ui| {
             ui.label(
                 "Pitch variation can make clicks sound more realistic by \
                     changing their pitch randomly.",
             );
-            ui.checkbox(&mut self.conf.pitch_enabled, "Enable pitch variation");
-            ui.add_enabled_ui(self.conf.pitch_enabled, |ui| {
-                let p = &mut self.conf.pitch;
+            let settings = self.conf.profile_mut(self.profile_tab);
+            ui.checkbox(&mut settings.pitch_enabled, "Enable pitch variation");
+            ui.add_enabled_ui(settings.pitch_enabled, |ui| {
+                let p = &mut settings.pitch;
                 drag_value(
                     ui,
                     &mut p.from,
@@ -1765,7 +3143,7 @@ impl Bot {
                     ui.checkbox(&mut self.conf.cut_by_releases, "Cut by releases")
                 });
             }
-            let vol = &mut self.conf.volume_settings;
+            let vol = &mut self.conf.profile_mut(self.profile_tab).volume_settings;
             drag_value(
                 ui,
                 &mut vol.global_volume,
@@ -1792,7 +3170,7 @@ impl Bot {
 
         ui.collapsing("Spam volume changes", |ui| {
             ui.label("This can be used to lower volume in spams");
-            let vol = &mut self.conf.volume_settings;
+            let vol = &mut self.conf.profile_mut(self.profile_tab).volume_settings;
             help_text(ui, "Apply this logic to releases", |ui| {
                 ui.checkbox(&mut vol.change_releases_volume, "Change releases volume")
             });
@@ -1803,20 +3181,208 @@ impl Bot {
                 0.0..=f64::INFINITY,
                 "Time between clicks which are considered spam clicks",
             );
+            help_text(
+                ui,
+                "Set volume and pitch by the time between clicks with editable curves",
+                |ui| ui.checkbox(&mut vol.spam_curves, "Use curves"),
+            );
+            if !vol.spam_curves {
+                drag_value(
+                    ui,
+                    &mut vol.spam_vol_offset_factor,
+                    "Spam volume offset factor",
+                    f64::NEG_INFINITY..=f64::INFINITY,
+                    "The value which the volume offset factor is multiplied by",
+                );
+                drag_value(
+                    ui,
+                    &mut vol.max_spam_vol_offset,
+                    "Maximum volume offset",
+                    f64::NEG_INFINITY..=f64::INFINITY,
+                    "The maximum value of the volume offset",
+                );
+                return;
+            }
+
+            let spam_time = vol.spam_time.max(0.001);
+            ui.label("Volume multiplier");
+            ui.push_id("spam_volume_curve", |ui| {
+                curve_edit(
+                    ui,
+                    &mut vol.spam_volume_curve,
+                    0.0..=spam_time,
+                    0.0..=1.5,
+                    |dt, v| {
+                        format!(
+                            "{:.0?} between clicks: {v:.2}x volume",
+                            Duration::from_secs_f64(dt)
+                        )
+                    },
+                );
+            });
+            ui.label("Pitch multiplier");
+            ui.push_id("spam_pitch_curve", |ui| {
+                curve_edit(
+                    ui,
+                    &mut vol.spam_pitch_curve,
+                    0.0..=spam_time,
+                    0.8..=1.2,
+                    |dt, p| {
+                        format!(
+                            "{:.0?} between clicks: {p:.3}x pitch",
+                            Duration::from_secs_f64(dt)
+                        )
+                    },
+                );
+            });
+            if ui.button("Reset curves").clicked() {
+                let default = VolumeSettings::default();
+                vol.spam_volume_curve = default.spam_volume_curve;
+                vol.spam_pitch_curve = default.spam_pitch_curve;
+            }
+        });
+
+        ui.collapsing("Stereo panning", |ui| {
+            let pan = &mut self.conf.panning;
+            help_text(
+                ui,
+                "Places player 1 and player 2 sounds on different sides,\n\
+                and platformer left/right sounds towards their side",
+                |ui| ui.checkbox(&mut pan.enabled, "Enable panning"),
+            );
+            ui.add_enabled_ui(pan.enabled, |ui| {
+                for (value, text, help) in [
+                    (&mut pan.player1, "Player 1", "Pan of player 1 sounds"),
+                    (&mut pan.player2, "Player 2", "Pan of player 2 sounds"),
+                    (
+                        &mut pan.left,
+                        "Left",
+                        "Added to the player pan for left button sounds",
+                    ),
+                    (
+                        &mut pan.right,
+                        "Right",
+                        "Added to the player pan for right button sounds",
+                    ),
+                ] {
+                    drag_value(
+                        ui,
+                        value,
+                        text,
+                        -1.0..=1.0,
+                        &format!("{help}. -1 is fully left, 1 is fully right"),
+                    );
+                }
+                drag_value(
+                    ui,
+                    &mut pan.jitter,
+                    "Pan jitter",
+                    0.0..=1.0,
+                    "Random pan variation (+/-)",
+                );
+                if ui.button("Reset").clicked() {
+                    *pan = Panning {
+                        enabled: true,
+                        ..Default::default()
+                    };
+                }
+            });
+        });
+
+        ui.collapsing("Click rate", |ui| {
+            let rate = &mut self.conf.click_rate;
+            help_text(
+                ui,
+                "Changes pitch, volume and brightness of clicks by how fast\n\
+                a button is being clicked, so long spams sound tighter",
+                |ui| ui.checkbox(&mut rate.enabled, "Enable click rate changes"),
+            );
+            ui.add_enabled_ui(rate.enabled, |ui| {
+                drag_value(
+                    ui,
+                    &mut rate.window,
+                    "Averaging window",
+                    0.0..=f64::INFINITY,
+                    "Time in seconds that the clicks per second are averaged over",
+                );
+                let max_rate = rate.max_rate;
+                drag_value(
+                    ui,
+                    &mut rate.min_rate,
+                    "Start rate",
+                    0.0..=max_rate,
+                    "Clicks per second where the changes start",
+                );
+                let min_rate = rate.min_rate;
+                drag_value(
+                    ui,
+                    &mut rate.max_rate,
+                    "Full rate",
+                    min_rate..=f64::INFINITY,
+                    "Clicks per second where the changes are fully applied",
+                );
+                drag_value(
+                    ui,
+                    &mut rate.pitch,
+                    "Pitch multiplier",
+                    0.0..=f64::INFINITY,
+                    "Pitch multiplier at the full rate",
+                );
+                drag_value(
+                    ui,
+                    &mut rate.volume,
+                    "Volume multiplier",
+                    0.0..=f64::INFINITY,
+                    "Volume multiplier at the full rate",
+                );
+                drag_value(
+                    ui,
+                    &mut rate.lowpass,
+                    "Low-pass amount",
+                    0.0..=1.0,
+                    "How much high frequencies are cut at the full rate.\n\
+                    0 is unfiltered, 1 leaves only the lowest frequencies",
+                );
+                if ui.button("Reset").clicked() {
+                    *rate = ClickRateSettings {
+                        enabled: true,
+                        ..Default::default()
+                    };
+                }
+            });
+        });
+
+        ui.collapsing("Release on death", |ui| {
+            ui.label("Play releases for held buttons when the player dies");
             drag_value(
                 ui,
-                &mut vol.spam_vol_offset_factor,
-                "Spam volume offset factor",
-                f64::NEG_INFINITY..=f64::INFINITY,
-                "The value which the volume offset factor is multiplied by",
+                &mut self.conf.death_release_delay,
+                "Release delay",
+                0.0..=f64::INFINITY,
+                "Time in seconds after death before the buttons are released",
             );
             drag_value(
                 ui,
-                &mut vol.max_spam_vol_offset,
-                "Maximum volume offset",
-                f64::NEG_INFINITY..=f64::INFINITY,
-                "The maximum value of the volume offset",
+                &mut self.conf.death_release_delay_offset,
+                "Random offset",
+                0.0..=f64::INFINITY,
+                "Random time in seconds added to the release delay",
+            );
+            help_text(
+                ui,
+                "Allow the random offset to be negative, so releases \
+                can happen before the release delay",
+                |ui| ui.checkbox(&mut self.conf.death_release_delay_neg, "Negative offset"),
             );
+            if (self.conf.death_release_delay != death_release_delay_default()
+                || self.conf.death_release_delay_offset != death_release_delay_offset_default()
+                || self.conf.death_release_delay_neg)
+                && ui.button("Reset").clicked()
+            {
+                self.conf.death_release_delay = death_release_delay_default();
+                self.conf.death_release_delay_offset = death_release_delay_offset_default();
+                self.conf.death_release_delay_neg = false;
+            }
         });
 
         ui.collapsing("Speed", |ui| {
@@ -1855,9 +3421,39 @@ impl Bot {
             // });
         });
 
+        ui.collapsing("Random seed", |ui| {
+            ui.label(
+                "With a fixed seed, the same inputs always play the same \
+                sounds with the same pitch and volume",
+            );
+            let mut fixed_seed = self.conf.rng_seed.is_some();
+            if ui.checkbox(&mut fixed_seed, "Fixed seed").changed() {
+                // seeds past 2^53 can't be shown exactly by the drag value
+                self.conf.rng_seed = fixed_seed.then(|| self.click_state.rng.u32(..) as u64);
+                self.click_state.reseed(&self.conf);
+            }
+            if let Some(seed) = &mut self.conf.rng_seed {
+                ui.horizontal(|ui| {
+                    if ui
+                        .add(DragValue::new(seed).clamp_range(0..=u32::MAX as u64))
+                        .changed()
+                    {
+                        self.click_state.rng.seed(*seed);
+                    }
+                    ui.label("Seed");
+                });
+                help_text(
+                    ui,
+                    "Start from the seed on every attempt instead of only when entering a level",
+                    |ui| ui.checkbox(&mut self.conf.reseed_every_attempt, "Reseed every attempt"),
+                );
+            }
+        });
+
         ui.collapsing("Advanced", |ui| {
-            let last_bufsize = self.mixer.renderer.guard().last_buffer_size;
-            ui.label(format!("Real buffer size (kittyaudio): {last_bufsize}"));
+            if let Some(last_bufsize) = self.audio.real_buffer_size() {
+                ui.label(format!("Real buffer size: {last_bufsize}"));
+            }
             help_text(
                 ui,
                 "Keep playing noise even if the clickbot is disabled",
@@ -1886,8 +3482,7 @@ impl Bot {
                         .on_hover_text("Apply buffer size changes")
                         .clicked()
                     {
-                        if !self.conf.use_fmod {
-                            self.maybe_init_kittyaudio();
+                        if self.audio.set_buffer_size(self.conf.buffer_size) {
                             self.play_noise();
                         } else {
                             self.show_fmod_buffersize_warn = true;
@@ -1921,7 +3516,7 @@ impl Bot {
     }
 
     fn apply_config(&mut self) {
-        self.maybe_init_kittyaudio();
+        self.init_audio_backend();
         self.play_noise();
 
         #[cfg(not(feature = "geode"))]
@@ -1930,14 +3525,15 @@ impl Bot {
 
     fn unload_clickpack(&mut self) {
         self.clickpack = Clickpack::default();
+        self.conf.manifest = None;
         self.stop_noise();
     }
 
+    /// Stops the noise right away, without fading out.
     fn stop_noise(&mut self) {
-        let prev_play_noise = self.conf.play_noise;
-        self.conf.play_noise = false;
-        self.play_noise();
-        self.conf.play_noise = prev_play_noise;
+        self.audio.stop_noise();
+        self.noise_fade.active = false;
+        self.noise_fade.playing = false;
     }
 
     fn load_clickpack_thread(
@@ -1954,13 +3550,28 @@ impl Bot {
             } else {
                 BOT.stop_noise();
             }
+            // never decode on the game thread while playing
+            BOT.clickpack.background_reloads = true;
             let _ = BOT
                 .clickpack
-                .load_from_path(dir, load_for, system)
+                .load_from_path(dir, load_for, system, &BOT.sample_settings())
                 .map_err(|e| {
                     log::error!("failed to load clickpack: {e}");
                     err_fn(e);
                 });
+            let synthesized = BOT
+                .clickpack
+                .synthesize_releases(&BOT.conf.release_synth, system);
+            if synthesized != 0 {
+                log::info!("generated {synthesized} releases from clicks");
+            }
+            BOT.clickpack.normalize(&BOT.conf.normalization);
+            if load_for == LoadClickpackFor::All {
+                if let Some(manifest) = BOT.clickpack.manifest.clone() {
+                    log::info!("using clickpack manifest settings");
+                    BOT.conf.manifest = Some(manifest);
+                }
+            }
             BOT.play_noise();
             is_loading_clickpack.store(false, Ordering::Relaxed);
         }
@@ -2013,16 +3624,13 @@ impl Bot {
             return true;
         }
         ui.horizontal(|ui| {
-            if ui
-                .button("Select clickpack")
-                .on_disabled_hover_text("Please wait…")
-                .clicked()
-            {
+            let pick_clickpack = |pick: fn() -> Option<PathBuf>| {
                 let is_loading_clickpack = self.is_loading_clickpack.clone();
                 let load_for = self.conf.load_clickpack_for;
                 let system = self.system;
+                let modal = modal.clone();
                 std::thread::spawn(move || {
-                    let Some(dir) = FileDialog::new().pick_folder() else {
+                    let Some(dir) = pick() else {
                         return;
                     };
                     log::debug!("selected clickpack {dir:?}");
@@ -2043,6 +3651,24 @@ impl Bot {
                         BOT.env.update(ClickpackEnv::Path(dir), load_for);
                     }
                 });
+            };
+            if ui
+                .button("Select clickpack")
+                .on_disabled_hover_text("Please wait…")
+                .clicked()
+            {
+                pick_clickpack(|| FileDialog::new().pick_folder());
+            }
+            if ui
+                .button("Select .zip")
+                .on_hover_text("Load a clickpack from a zip archive")
+                .clicked()
+            {
+                pick_clickpack(|| {
+                    FileDialog::new()
+                        .add_filter("Zip archive", &["zip"])
+                        .pick_file()
+                });
             }
             if self.clickpack.num_sounds != 0 {
                 ui.label(format!("Selected clickpack: \"{}\"", self.clickpack.name));
@@ -2098,7 +3724,8 @@ impl Bot {
             if !self.clickpacks.is_empty() {
                 help_text(
                     ui,
-                    "If there's no folders inside .zcb/clickpacks,\n\
+                    "Clickpacks can be folders or .zip archives.\n\
+                    If there's no clickpacks inside .zcb/clickpacks,\n\
                     there will be an option to choose the clickpack manually",
                     |ui| {
                         ui.label("Put clickpacks in .zcb/clickpacks");
@@ -2154,6 +3781,19 @@ impl Bot {
                 },
             );
         }
+        if let Some(manifest) = self.clickpack.manifest.clone() {
+            self.show_clickpack_manifest(ui, &manifest);
+        }
+        if !is_loading_clickpack {
+            self.show_sample_settings(ui);
+        }
+        if self.clickpack.num_sounds != 0 && !is_loading_clickpack {
+            self.show_release_synth(ui);
+            self.show_sample_tags(ui);
+        }
+        if self.clickpack.num_sounds != 0 && !is_loading_clickpack {
+            self.show_normalization(ui);
+        }
         ui.separator();
         ui.collapsing("ClickpackDB", |ui| {
             ui.label(
@@ -2186,9 +3826,9 @@ impl Bot {
                     .striped(true)
                     .show(ui, |ui| {
                         for times in [
-                            self.prev_times.jump,
-                            self.prev_times.left,
-                            self.prev_times.right,
+                            self.click_state.prev_times.jump,
+                            self.click_state.prev_times.left,
+                            self.click_state.prev_times.right,
                         ] {
                             for t in times {
                                 ui.label(format!("{:.3?} | {:?}", t.time, t.typ));
@@ -2196,21 +3836,67 @@ impl Bot {
                             ui.end_row();
                         }
                     });
+                let settings = self.conf.sound_settings(ProfileKind::Player1Jump);
                 ui.label(format!(
                     "Last pitch: {:.4} ({}..={})",
-                    self.prev_pitch, self.conf.pitch.from, self.conf.pitch.to
+                    self.click_state.prev_pitch, settings.pitch.from, settings.pitch.to
                 ));
                 ui.label(format!(
                     "Last volume: {:.4} (+/- {} * {})",
-                    self.prev_volume,
-                    self.conf.volume_settings.volume_var,
-                    self.conf.volume_settings.global_volume
+                    self.click_state.prev_volume,
+                    settings.volume_settings.volume_var,
+                    settings.volume_settings.global_volume
                 ));
                 ui.label(format!(
                     "Last spam volume offset: -{:.4}",
-                    self.prev_spam_offset
+                    self.click_state.prev_spam_offset
+                ));
+                ui.label(format!(
+                    "Jump click rate: {:.2} / {:.2} clicks per second",
+                    self.click_state
+                        .prev_times
+                        .click_rate(Button::Jump, false, false),
+                    self.click_state
+                        .prev_times
+                        .click_rate(Button::Jump, true, false),
                 ));
 
+                let trimmed: Vec<_> = (0..6)
+                    .flat_map(|slot| &self.clickpack[slot].tiers)
+                    .flat_map(|tier| tier.clicks.iter().chain(&tier.releases))
+                    .map(|sound| sound.trimmed.0 + sound.trimmed.1)
+                    .collect();
+                if trimmed.iter().any(|&t| t > 0.0) {
+                    ui.label(format!(
+                        "Trimmed silence: {:.1}ms on average, {:.1}ms at most",
+                        trimmed.iter().sum::<f64>() / trimmed.len() as f64 * 1000.0,
+                        trimmed.iter().fold(0.0f64, |a, &b| a.max(b)) * 1000.0,
+                    ));
+                }
+
+                if let Some(loudness) = self.clickpack.loudness {
+                    ui.label(format!(
+                        "Clickpack loudness: {:.1} dB RMS, {:.1} dB peak",
+                        loudness.rms, loudness.peak
+                    ));
+                    egui::Grid::new("loudness_grid")
+                        .num_columns(4)
+                        .striped(true)
+                        .show(ui, |ui| {
+                            for text in ["Folder", "RMS", "Peak", "Gain"] {
+                                ui.label(text);
+                            }
+                            ui.end_row();
+                            for folder in &self.clickpack.folder_loudness {
+                                ui.label(&folder.name);
+                                ui.label(format!("{:.1} dB", folder.loudness.rms));
+                                ui.label(format!("{:.1} dB", folder.loudness.peak));
+                                ui.label(format!("{:+.1} dB", folder.gain));
+                                ui.end_row();
+                            }
+                        });
+                }
+
                 let format_path_keep_root = |path: &Path| path.to_string_lossy().replace('\\', "/");
 
                 ui.label(format!(
@@ -2221,6 +3907,349 @@ impl Bot {
         }
     }
 
+    /// Sample settings with the sample rate of the audio backend.
+    fn sample_settings(&self) -> SampleSettings {
+        let mut settings = self.conf.sample_settings.clone();
+        if let Some(sample_rate) = self.audio.sample_rate() {
+            settings.sample_rate = sample_rate;
+        }
+        settings
+    }
+
+    fn show_sample_settings(&mut self, ui: &mut egui::Ui) {
+        ui.collapsing("Sample processing", |ui| {
+            ui.label("Applied when a clickpack is loaded");
+            let settings = &mut self.conf.sample_settings;
+            help_text(
+                ui,
+                "Cuts the silence before and after every click,\n\
+                so all clicks start on their attack",
+                |ui| ui.checkbox(&mut settings.trim_silence, "Trim silence"),
+            );
+            ui.add_enabled_ui(settings.trim_silence, |ui| {
+                drag_value(
+                    ui,
+                    &mut settings.trim_threshold,
+                    "Silence threshold (dB)",
+                    -120.0..=0.0,
+                    "Anything this much quieter than the loudest part of a click is silence",
+                );
+                let mut pre_roll_ms = settings.pre_roll * 1000.0;
+                if drag_value(
+                    ui,
+                    &mut pre_roll_ms,
+                    "Pre-roll (ms)",
+                    0.0..=100.0,
+                    "Time kept before the start and after the end of each click",
+                )
+                .changed()
+                {
+                    settings.pre_roll = pre_roll_ms / 1000.0;
+                }
+            });
+
+            let mut restart_audio = false;
+            help_text(
+                ui,
+                "Converts every click to the output sample rate when loading,\n\
+                so it doesn't have to be done while playing",
+                |ui| {
+                    restart_audio |= ui
+                        .checkbox(&mut settings.resample, "Resample to output rate")
+                        .changed();
+                },
+            );
+            if settings.resample && !self.conf.use_fmod {
+                restart_audio |= drag_value(
+                    ui,
+                    &mut settings.sample_rate,
+                    "Output sample rate",
+                    8000..=192_000,
+                    "Sample rate the audio device is opened with",
+                )
+                .drag_stopped();
+            }
+            if restart_audio && !self.conf.use_fmod {
+                self.init_audio_backend();
+                self.play_noise();
+            }
+
+            let settings = &mut self.conf.sample_settings;
+            ui.horizontal(|ui| {
+                help_text(
+                    ui,
+                    "Stores decoded clicks in .zcb/cache, so clickpacks load\n\
+                    faster next time. Changed files are decoded again",
+                    |ui| ui.checkbox(&mut settings.cache, "Cache decoded clicks"),
+                );
+                if ui
+                    .button("Clear cache")
+                    .on_hover_text("Delete the cached clicks in .zcb/cache")
+                    .clicked()
+                {
+                    let mut toasts = self.toasts.lock();
+                    match SoundCache::default().clear() {
+                        Ok(freed) => toasts.add(Toast::success(format!(
+                            "Cleared {:.1} MB of cached clicks",
+                            freed as f64 / 1_000_000.0
+                        ))),
+                        Err(e) => toasts.add(Toast::error(format!("Failed to clear cache: {e}"))),
+                    };
+                }
+            });
+
+            if drag_value(
+                ui,
+                &mut settings.memory_budget,
+                "Memory budget (MB)",
+                0.0..=4096.0,
+                "Megabytes of decoded clicks to keep in memory, 0 for no limit.\n\
+                Rarely used clicks are unloaded and decoded again when they're played.\n\
+                Applied right away, except for zip clickpacks loaded without a budget",
+            )
+            .changed()
+            {
+                self.clickpack.memory_budget = settings.memory_budget_bytes();
+                self.clickpack.apply_memory_budget();
+            }
+            ui.label(format!(
+                "Clicks in memory: {:.1} MB ({} unloaded)",
+                self.clickpack.memory_usage() as f64 / 1_000_000.0,
+                self.clickpack.num_unloaded()
+            ));
+        });
+    }
+
+    fn show_release_synth(&mut self, ui: &mut egui::Ui) {
+        ui.collapsing("Release synthesis", |ui| {
+            let synth = &mut self.conf.release_synth;
+            let mut changed = false;
+            help_text(
+                ui,
+                "Makes releases from the clicks of players that don't have any,\n\
+                by reversing and filtering the end of each click",
+                |ui| {
+                    changed |= ui
+                        .checkbox(&mut synth.enabled, "Generate missing releases")
+                        .changed();
+                },
+            );
+            let mut tail_ms = synth.tail * 1000.0;
+            let resp = drag_value(
+                ui,
+                &mut tail_ms,
+                "Tail (ms)",
+                1.0..=500.0,
+                "Length of the end of each click that is turned into a release",
+            );
+            if resp.changed() {
+                synth.tail = tail_ms / 1000.0;
+            }
+            changed |= resp.drag_stopped();
+            changed |= drag_value(
+                ui,
+                &mut synth.low_cut,
+                "Low cut (Hz)",
+                20.0..=20000.0,
+                "Frequencies below this are filtered out",
+            )
+            .drag_stopped();
+            changed |= drag_value(
+                ui,
+                &mut synth.high_cut,
+                "High cut (Hz)",
+                20.0..=20000.0,
+                "Frequencies above this are filtered out",
+            )
+            .drag_stopped();
+            changed |= drag_value(
+                ui,
+                &mut synth.pitch,
+                "Pitch",
+                0.25..=4.0,
+                "Speed of the release, higher values make it higher and shorter",
+            )
+            .drag_stopped();
+            changed |= drag_value(
+                ui,
+                &mut synth.volume,
+                "Volume",
+                0.0..=2.0,
+                "Volume of the release relative to the click it's made from",
+            )
+            .drag_stopped();
+
+            let num_synthesized = self.clickpack.num_synthesized();
+            ui.horizontal(|ui| {
+                if ui
+                    .button("Preview")
+                    .on_hover_text("Play a release made from a random click")
+                    .clicked()
+                {
+                    self.preview_release();
+                }
+                if ui
+                    .add_enabled(num_synthesized != 0, egui::Button::new("Save to clickpack"))
+                    .on_hover_text("Write the generated releases into the clickpack folder")
+                    .clicked()
+                {
+                    let result = self.clickpack.save_synthesized_releases();
+                    let mut toasts = self.toasts.lock();
+                    match result {
+                        Ok(saved) => toasts.add(Toast::success(format!(
+                            "Saved {saved} releases to the clickpack"
+                        ))),
+                        Err(e) => {
+                            log::error!("failed to save releases: {e}");
+                            toasts.add(Toast::error(format!("Failed to save releases: {e}")))
+                        }
+                    };
+                }
+            });
+            if num_synthesized != 0 {
+                ui.label(format!("{num_synthesized} generated releases"));
+            }
+
+            if changed {
+                self.clickpack
+                    .synthesize_releases(&self.conf.release_synth, *self.system);
+                self.clickpack.normalize(&self.conf.normalization);
+            }
+        });
+    }
+
+    fn show_sample_tags(&mut self, ui: &mut egui::Ui) {
+        let tags = self.clickpack.sample_tags();
+        if tags.is_empty() {
+            return;
+        }
+        ui.collapsing("Sample tags", |ui| {
+            ui.label("Samples with unchecked tags are never played");
+            let excluded = &mut self.conf.excluded_sample_tags;
+            for tag in tags {
+                let mut play = !excluded.contains(&tag);
+                if ui.checkbox(&mut play, &tag).changed() {
+                    if play {
+                        excluded.retain(|t| *t != tag);
+                    } else {
+                        excluded.push(tag);
+                    }
+                }
+            }
+        });
+    }
+
+    /// Plays a release made from a random click with the current settings, even if
+    /// release synthesis is disabled.
+    fn preview_release(&mut self) {
+        let clicks: Vec<&SoundWrapper> = (0..6)
+            .flat_map(|slot| &self.clickpack[slot].tiers)
+            .flat_map(|tier| &tier.clicks)
+            .filter(|click| click.is_loaded())
+            .collect();
+        if clicks.is_empty() {
+            return;
+        }
+        let click = clicks[fastrand::usize(..clicks.len())];
+        // the preview isn't normalized, the gain of its click is close enough
+        let volume = self
+            .conf
+            .sound_settings(ProfileKind::Player1Jump)
+            .volume_settings
+            .global_volume
+            * click.gain;
+        let release = synth::release_from_click(click, *self.system, &self.conf.release_synth);
+        if let Some(mut prev) = self.release_preview.replace(release.clone()) {
+            prev.free();
+        }
+        self.audio.play(release, 1.0, volume, 0.0, 0.0);
+    }
+
+    fn show_normalization(&mut self, ui: &mut egui::Ui) {
+        ui.collapsing("Loudness normalization", |ui| {
+            let norm = &mut self.conf.normalization;
+            let mut changed = false;
+            help_text(
+                ui,
+                "Makes clickpacks equally loud, so switching them doesn't need\n\
+                volume changes. \"Per folder\" also evens out hard and soft clicks",
+                |ui| {
+                    egui::ComboBox::from_label("Normalize")
+                        .selected_text(norm.mode.text())
+                        .show_ui(ui, |ui| {
+                            for mode in NormalizeMode::ALL {
+                                changed |= ui
+                                    .selectable_value(&mut norm.mode, mode, mode.text())
+                                    .changed();
+                            }
+                        });
+                },
+            );
+            ui.add_enabled_ui(norm.mode != NormalizeMode::Off, |ui| {
+                changed |= drag_value(
+                    ui,
+                    &mut norm.target,
+                    "Target loudness (dB)",
+                    -60.0..=0.0,
+                    "RMS level of the loudest 50ms of each click, in dBFS",
+                )
+                .changed();
+            });
+            if let Some(loudness) = self.clickpack.loudness {
+                ui.label(format!("Measured loudness: {:.1} dB", loudness.rms));
+            }
+            if changed {
+                self.clickpack.normalize(&self.conf.normalization);
+            }
+        });
+    }
+
+    fn show_clickpack_manifest(&mut self, ui: &mut egui::Ui, manifest: &ClickpackManifest) {
+        ui.collapsing("Clickpack settings", |ui| {
+            if let Some(name) = &manifest.name {
+                ui.label(format!("Name: {name}"));
+            }
+            if let Some(author) = &manifest.author {
+                ui.label(format!("Author: {author}"));
+            }
+            ui.label("Use the settings recommended by the clickpack:")
+                .on_hover_text("Disabled settings keep your own values");
+
+            let use_defaults = &mut self.conf.use_clickpack_defaults;
+            let mut changed = false;
+            for (has, value, text) in [
+                (
+                    manifest.timings.is_some() || manifest.release_timings.is_some(),
+                    &mut use_defaults.timings,
+                    "Timings",
+                ),
+                (manifest.pitch.is_some(), &mut use_defaults.pitch, "Pitch"),
+                (
+                    manifest.volume_settings.is_some(),
+                    &mut use_defaults.volume_settings,
+                    "Volume settings",
+                ),
+                (
+                    manifest.noise_volume.is_some(),
+                    &mut use_defaults.noise_volume,
+                    "Noise volume",
+                ),
+                (
+                    manifest.selection_strategy.is_some(),
+                    &mut use_defaults.selection_strategy,
+                    "Sound selection",
+                ),
+            ] {
+                if has {
+                    changed |= ui.checkbox(value, text).changed();
+                }
+            }
+            if changed {
+                self.play_noise(); // noise volume might have changed
+            }
+        });
+    }
+
     fn show_clickpackdb_window(&mut self, ctx: &egui::Context, modal: Arc<Mutex<Modal>>) {
         if !self.clickpack_db_open {
             return;
@@ -2277,6 +4306,8 @@ impl Bot {
 impl Drop for Bot {
     fn drop(&mut self) {
         self.unload_clickpack();
+        // stop using the fmod system before releasing it
+        self.audio = Box::<NullBackend>::default();
         self.release_fmod()
     }
 }
@@ -0,0 +1,399 @@
+//! On-disk cache of decoded clicks, so large clickpacks don't have to be decoded every
+//! time they're loaded.
+
+use crate::{
+    clickpack::{Loudness, SampleSettings, SoundWrapper},
+    vfs::{ClickpackFs, SoundFile},
+};
+use anyhow::{Context, Result};
+use gfmod::FMOD_SYSTEM;
+use kittyaudio::{Frame, Sound};
+use std::{
+    cell::Cell,
+    hash::{DefaultHasher, Hash, Hasher},
+    io::Read,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+/// Where the clickbot keeps its cache.
+pub const CACHE_DIR: &str = ".zcb/cache";
+
+const MAGIC: &[u8; 8] = b"ZCBCACHE";
+/// Bump this when the file format or the way sounds are processed changes.
+const VERSION: u32 = 1;
+/// The oldest entries are removed when the cache grows past this many bytes.
+pub const MAX_CACHE_SIZE: u64 = 1024 * 1024 * 1024;
+
+/// Identifies the contents of a file without reading it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileId {
+    pub path: PathBuf,
+    pub size: u64,
+    /// Modification time in nanoseconds since the unix epoch
+    pub mtime: u128,
+}
+
+impl FileId {
+    pub fn of(path: &Path) -> Option<Self> {
+        let meta = std::fs::metadata(path).ok()?;
+        let mtime = meta
+            .modified()
+            .ok()?
+            .duration_since(UNIX_EPOCH)
+            .ok()?
+            .as_nanos();
+        Some(Self {
+            path: path.canonicalize().unwrap_or_else(|_| path.to_path_buf()),
+            size: meta.len(),
+            mtime,
+        })
+    }
+}
+
+/// Decoded and processed sounds, one file per sound.
+///
+/// Entries are keyed by the path, size and modification time of the source file and
+/// by the [`SampleSettings`] that change the decoded frames, so a changed file or
+/// different settings are simply a cache miss. Stale entries are removed by
+/// [`SoundCache::prune`].
+pub struct SoundCache {
+    dir: PathBuf,
+    max_size: u64,
+}
+
+impl Default for SoundCache {
+    fn default() -> Self {
+        Self::new(CACHE_DIR)
+    }
+}
+
+fn read_bytes<const N: usize>(r: &mut impl Read) -> Result<[u8; N]> {
+    let mut buf = [0; N];
+    r.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn read_u32(r: &mut impl Read) -> Result<u32> {
+    Ok(u32::from_le_bytes(read_bytes(r)?))
+}
+
+fn read_u64(r: &mut impl Read) -> Result<u64> {
+    Ok(u64::from_le_bytes(read_bytes(r)?))
+}
+
+fn read_f64(r: &mut impl Read) -> Result<f64> {
+    Ok(f64::from_le_bytes(read_bytes(r)?))
+}
+
+impl SoundCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            max_size: MAX_CACHE_SIZE,
+        }
+    }
+
+    pub fn with_max_size(mut self, max_size: u64) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Everything that affects the cached frames of a file.
+    fn key(id: &FileId, settings: &SampleSettings) -> String {
+        let mut key = format!("{}|{}|{}", id.path.display(), id.size, id.mtime);
+        if settings.trim_silence {
+            key += &format!("|trim:{}:{}", settings.trim_threshold, settings.pre_roll);
+        }
+        if let Some(rate) = settings.resample_rate() {
+            key += &format!("|rate:{rate}");
+        }
+        key
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        self.dir.join(format!("{:016x}.pcm", hasher.finish()))
+    }
+
+    /// Loads a sound if the cache has an up to date entry for it.
+    pub fn load(
+        &self,
+        id: &FileId,
+        settings: &SampleSettings,
+        system: *mut FMOD_SYSTEM,
+    ) -> Option<SoundWrapper> {
+        let key = Self::key(id, settings);
+        let data = std::fs::read(self.entry_path(&key)).ok()?;
+        Self::decode(&data, &key)
+            .map_err(|e| log::debug!("ignoring cache entry for {:?}: {e}", id.path))
+            .ok()
+            .map(|(sound, loudness, trimmed)| {
+                SoundWrapper::from_processed(system, sound, loudness, trimmed)
+            })
+    }
+
+    fn decode(mut data: &[u8], key: &str) -> Result<(Sound, Loudness, (f64, f64))> {
+        let r = &mut data;
+        if &read_bytes::<8>(r)? != MAGIC || read_u32(r)? != VERSION {
+            anyhow::bail!("written by a different version");
+        }
+        let key_len = read_u32(r)? as usize;
+        if r.get(..key_len) != Some(key.as_bytes()) {
+            anyhow::bail!("hash collision");
+        }
+        *r = &r[key_len..];
+
+        let sample_rate = read_u32(r)?;
+        let trimmed = (read_f64(r)?, read_f64(r)?);
+        let loudness = Loudness {
+            peak: read_f64(r)?,
+            rms: read_f64(r)?,
+        };
+        let num_frames = read_u64(r)?;
+        if num_frames.checked_mul(8) != Some(r.len() as u64) {
+            anyhow::bail!("truncated, expected {num_frames} frames");
+        }
+        let frames: Vec<Frame> = r
+            .chunks_exact(8)
+            .map(|b| Frame {
+                left: f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+                right: f32::from_le_bytes([b[4], b[5], b[6], b[7]]),
+            })
+            .collect();
+        Ok((Sound::from_frames(sample_rate, &frames), loudness, trimmed))
+    }
+
+    /// Writes a decoded sound to the cache. Errors are logged, a failed write only
+    /// means the file is decoded again next time.
+    pub fn store(&self, id: &FileId, settings: &SampleSettings, sound: &SoundWrapper) {
+        let key = Self::key(id, settings);
+        let mut data = Vec::with_capacity(64 + key.len() + sound.frames.len() * 8);
+        data.extend_from_slice(MAGIC);
+        data.extend_from_slice(&VERSION.to_le_bytes());
+        data.extend_from_slice(&(key.len() as u32).to_le_bytes());
+        data.extend_from_slice(key.as_bytes());
+        data.extend_from_slice(&sound.sample_rate().to_le_bytes());
+        for value in [
+            sound.trimmed.0,
+            sound.trimmed.1,
+            sound.loudness.peak,
+            sound.loudness.rms,
+        ] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(&(sound.frames.len() as u64).to_le_bytes());
+        for frame in sound.frames.iter() {
+            data.extend_from_slice(&frame.left.to_le_bytes());
+            data.extend_from_slice(&frame.right.to_le_bytes());
+        }
+
+        // write to a temporary file first, so a crash can't leave a broken entry
+        let path = self.entry_path(&key);
+        let tmp_path = path.with_extension("tmp");
+        let _ = std::fs::create_dir_all(&self.dir)
+            .and_then(|_| std::fs::write(&tmp_path, data))
+            .and_then(|_| std::fs::rename(&tmp_path, &path))
+            .map_err(|e| log::warn!("failed to write cache entry {path:?}: {e}"));
+    }
+
+    /// Loads a sound from the cache, or decodes it with `decode` and caches it. The
+    /// returned bool is whether it was a cache hit.
+    pub(crate) fn load_or_decode(
+        &self,
+        id: &FileId,
+        settings: &SampleSettings,
+        system: *mut FMOD_SYSTEM,
+        decode: impl FnOnce() -> Result<SoundWrapper>,
+    ) -> Result<(SoundWrapper, bool)> {
+        if let Some(sound) = self.load(id, settings, system) {
+            return Ok((sound, true));
+        }
+        let sound = decode()?;
+        self.store(id, settings, &sound);
+        Ok((sound, false))
+    }
+
+    /// Cache entries and leftover temporary files. Anything else in the directory
+    /// isn't ours and is left alone.
+    fn entries(&self) -> Result<Vec<(PathBuf, std::fs::Metadata)>> {
+        let Ok(entries) = self.dir.read_dir() else {
+            return Ok(vec![]); // nothing cached yet
+        };
+        let mut files = vec![];
+        for entry in entries {
+            let entry = entry?;
+            let path = entry.path();
+            let ours = path
+                .extension()
+                .is_some_and(|ext| ext == "pcm" || ext == "tmp");
+            let meta = entry.metadata()?;
+            if ours && meta.is_file() {
+                files.push((path, meta));
+            }
+        }
+        Ok(files)
+    }
+
+    /// Removes every entry, returns the number of bytes freed.
+    pub fn clear(&self) -> Result<u64> {
+        let mut freed = 0;
+        for (path, meta) in self.entries()? {
+            std::fs::remove_file(path)?;
+            freed += meta.len();
+        }
+        log::info!("cleared {freed} bytes of cached sounds from {:?}", self.dir);
+        Ok(freed)
+    }
+
+    /// Whether the file an entry was decoded from has changed or is gone. Sounds from
+    /// archives are checked against the archive.
+    fn is_stale(path: &Path) -> bool {
+        let Ok(source) = Self::read_source(path) else {
+            return true;
+        };
+        let current = source
+            .path
+            .ancestors()
+            .find(|p| p.is_file())
+            .and_then(FileId::of);
+        current.is_none_or(|id| (id.size, id.mtime) != (source.size, source.mtime))
+    }
+
+    /// Reads the source file of an entry from its header, without the frames.
+    fn read_source(path: &Path) -> Result<FileId> {
+        let r = &mut std::fs::File::open(path)?;
+        if &read_bytes::<8>(r)? != MAGIC || read_u32(r)? != VERSION {
+            anyhow::bail!("written by a different version");
+        }
+        let key_len = read_u32(r)? as usize;
+        if key_len > 1 << 16 {
+            anyhow::bail!("key is too long");
+        }
+        let mut key = vec![0; key_len];
+        r.read_exact(&mut key)?;
+        Self::source_of(&String::from_utf8(key)?).context("invalid key")
+    }
+
+    /// Parses the source file back out of a [`SoundCache::key`].
+    fn source_of(key: &str) -> Option<FileId> {
+        let mut rest = key;
+        let (mut head, mut last) = rest.rsplit_once('|')?;
+        while last.starts_with("trim:") || last.starts_with("rate:") {
+            rest = head;
+            (head, last) = rest.rsplit_once('|')?;
+        }
+        let mtime = last.parse().ok()?;
+        let (path, size) = head.rsplit_once('|')?;
+        Some(FileId {
+            path: path.into(),
+            size: size.parse().ok()?,
+            mtime,
+        })
+    }
+
+    /// Removes entries whose source file changed or was deleted, and then the oldest
+    /// entries until the cache is smaller than its size limit. Returns the number of
+    /// bytes freed.
+    pub fn prune(&self) -> Result<u64> {
+        let mut entries = self.entries()?;
+        let mut freed = 0;
+        let mut remove = |path: &Path, meta: &std::fs::Metadata| {
+            std::fs::remove_file(path).map(|_| freed += meta.len())
+        };
+
+        let mut kept = Vec::with_capacity(entries.len());
+        for (path, meta) in entries.drain(..) {
+            if Self::is_stale(&path) {
+                remove(&path, &meta)?;
+            } else {
+                kept.push((path, meta));
+            }
+        }
+
+        // oldest first
+        kept.sort_by_key(|(_, meta)| meta.modified().ok());
+        let mut size: u64 = kept.iter().map(|(_, meta)| meta.len()).sum();
+        for (path, meta) in &kept {
+            if size <= self.max_size {
+                break;
+            }
+            remove(path, meta)?;
+            size -= meta.len();
+        }
+
+        if freed > 0 {
+            log::info!("pruned {freed} bytes of cached sounds from {:?}", self.dir);
+        }
+        Ok(freed)
+    }
+}
+
+/// Wraps a [`ClickpackFs`], loading sounds from a [`SoundCache`] if their files haven't
+/// changed and caching the ones that had to be decoded.
+pub(crate) struct CachedFs<'a> {
+    fs: &'a dyn ClickpackFs,
+    cache: &'a SoundCache,
+    hits: Cell<usize>,
+    misses: Cell<usize>,
+}
+
+impl<'a> CachedFs<'a> {
+    pub fn new(fs: &'a dyn ClickpackFs, cache: &'a SoundCache) -> Self {
+        Self {
+            fs,
+            cache,
+            hits: Cell::new(0),
+            misses: Cell::new(0),
+        }
+    }
+
+    /// Number of sounds that were loaded from the cache and that had to be decoded.
+    pub fn stats(&self) -> (usize, usize) {
+        (self.hits.get(), self.misses.get())
+    }
+}
+
+impl ClickpackFs for CachedFs<'_> {
+    fn dirs(&self, dir: &Path) -> Vec<PathBuf> {
+        self.fs.dirs(dir)
+    }
+
+    fn files(&self, dir: &Path) -> Vec<PathBuf> {
+        self.fs.files(dir)
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        self.fs.read(path)
+    }
+
+    fn file_id(&self, path: &Path) -> Option<FileId> {
+        self.fs.file_id(path)
+    }
+
+    fn keep(&self, path: &Path) -> Result<SoundFile> {
+        self.fs.keep(path)
+    }
+
+    fn keep_copies(&self) -> bool {
+        self.fs.keep_copies()
+    }
+
+    fn load_sound(
+        &self,
+        path: &Path,
+        system: *mut FMOD_SYSTEM,
+        settings: &SampleSettings,
+    ) -> Result<SoundWrapper> {
+        let Some(id) = self.fs.file_id(path) else {
+            return self.fs.load_sound(path, system, settings);
+        };
+        let (sound, hit) = self.cache.load_or_decode(&id, settings, system, || {
+            self.fs.load_sound(path, system, settings)
+        })?;
+        let counter = if hit { &self.hits } else { &self.misses };
+        counter.set(counter.get() + 1);
+        Ok(sound)
+    }
+}
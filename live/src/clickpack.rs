@@ -1,10 +1,24 @@
+use crate::{
+    audio,
+    cache::{CachedFs, FileId, SoundCache},
+    render,
+    synth::{self, ReleaseSynth},
+    vfs::{self, ClickpackFs, DirFs, SoundFile, ZipFs},
+};
 use anyhow::Result;
+use fastrand::Rng;
 use gfmod::*;
-use kittyaudio::Sound;
+use kittyaudio::{Frame, Sound};
+use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
-use std::path::{Path, PathBuf};
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::Cursor,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub enum Button {
     Jump = 1,
     Left = 2,
@@ -13,11 +27,15 @@ pub enum Button {
 
 impl Button {
     pub fn from_u8(b: u8) -> Self {
+        Self::try_from_u8(b).unwrap_or_else(|| panic!("invalid button value {b}, expected 1..=3"))
+    }
+
+    pub const fn try_from_u8(b: u8) -> Option<Self> {
         match b {
-            1 => Self::Jump,
-            2 => Self::Left,
-            3 => Self::Right,
-            _ => panic!("invalid button value {b}, expected 1..=3"),
+            1 => Some(Self::Jump),
+            2 => Some(Self::Left),
+            3 => Some(Self::Right),
+            _ => None,
         }
     }
 
@@ -28,10 +46,14 @@ impl Button {
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
 pub struct Timings {
     pub hard: f64,
     pub regular: f64,
     pub soft: f64,
+    /// Thresholds of extra tiers like "ultrasoft" or "hardest", by tier name
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub tiers: BTreeMap<String, f64>,
 }
 
 impl Default for Timings {
@@ -41,11 +63,44 @@ impl Default for Timings {
             regular: 0.15,
             soft: 0.025,
             // lower = microclicks
+            tiers: BTreeMap::new(),
+        }
+    }
+}
+
+/// Folder name prefixes of the tiers in [`ClickType`], hardest first.
+pub const STANDARD_TIERS: [&str; 4] = ["hard", "", "soft", "micro"];
+
+/// Thresholds for common extra tier names, used if the timings don't set them.
+const DEFAULT_TIER_THRESHOLDS: [(&str, f64); 3] =
+    [("hardest", 5.0), ("ultrasoft", 0.012), ("supersoft", 0.012)];
+
+pub fn default_tier_threshold(tier: &str) -> Option<f64> {
+    DEFAULT_TIER_THRESHOLDS
+        .iter()
+        .find(|(name, _)| *name == tier)
+        .map(|(_, threshold)| *threshold)
+}
+
+impl Timings {
+    /// Time above which a tier is played. `None` if the tier doesn't have a threshold.
+    pub fn threshold(&self, tier: &str) -> Option<f64> {
+        match tier {
+            "hard" => Some(self.hard),
+            "" => Some(self.regular),
+            "soft" => Some(self.soft),
+            "micro" => Some(0.0),
+            _ => self
+                .tiers
+                .get(tier)
+                .copied()
+                .or_else(|| default_tier_threshold(tier)),
         }
     }
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
 pub struct Pitch {
     pub from: f64,
     pub to: f64,
@@ -60,7 +115,47 @@ impl Default for Pitch {
     }
 }
 
+/// Piecewise-linear curve through `(x, y)` points, sorted by `x`. It stays flat before
+/// the first and after the last point, and an empty curve is 1 everywhere.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+#[serde(transparent)]
+pub struct Curve {
+    pub points: Vec<(f64, f64)>,
+}
+
+impl Curve {
+    pub fn new(points: &[(f64, f64)]) -> Self {
+        Self {
+            points: points.to_vec(),
+        }
+    }
+
+    pub fn eval(&self, x: f64) -> f64 {
+        let (Some(first), Some(last)) = (self.points.first(), self.points.last()) else {
+            return 1.0;
+        };
+        if x <= first.0 {
+            return first.1;
+        }
+        for w in self.points.windows(2) {
+            let ((x0, y0), (x1, y1)) = (w[0], w[1]);
+            if x <= x1 {
+                let t = if x1 > x0 { (x - x0) / (x1 - x0) } else { 1.0 };
+                return y0 + (y1 - y0) * t;
+            }
+        }
+        last.1
+    }
+
+    /// Inserts a point, keeping the points sorted.
+    pub fn insert(&mut self, point: (f64, f64)) {
+        let idx = self.points.partition_point(|p| p.0 < point.0);
+        self.points.insert(idx, point);
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
 pub struct VolumeSettings {
     pub enabled: bool,
     pub spam_time: f64,
@@ -70,6 +165,12 @@ pub struct VolumeSettings {
     pub global_volume: f64,
     pub volume_var: f64,
     pub platformer_volume_factor: f64,
+    /// Use `spam_volume_curve` and `spam_pitch_curve` instead of the linear offset
+    pub spam_curves: bool,
+    /// Volume multiplier by time between clicks, in seconds
+    pub spam_volume_curve: Curve,
+    /// Pitch multiplier by time between clicks, in seconds
+    pub spam_pitch_curve: Curve,
 }
 
 impl Default for VolumeSettings {
@@ -83,11 +184,109 @@ impl Default for VolumeSettings {
             global_volume: 1.0,
             volume_var: 0.2,
             platformer_volume_factor: 1.0,
+            spam_curves: false,
+            // same as the default linear offset
+            spam_volume_curve: Curve::new(&[(0.0, 0.61), (0.3, 1.0)]),
+            spam_pitch_curve: Curve::new(&[(0.0, 1.0), (0.3, 1.0)]),
         }
     }
 }
 
-#[derive(Default, Clone, Copy, Debug, PartialEq)]
+/// How a sound is picked when a click type has more than one.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum SelectionStrategy {
+    /// Any sound, every time
+    #[default]
+    Uniform,
+    /// Any sound except the one that played last
+    NoRepeat,
+    /// Every sound plays once, in random order, before any of them plays again
+    ShuffleBag,
+    /// Sounds with a higher weight play more often, see [`ClickpackManifest::weights`]
+    Weighted,
+}
+
+impl SelectionStrategy {
+    pub const ALL: [Self; 4] = [
+        Self::Uniform,
+        Self::NoRepeat,
+        Self::ShuffleBag,
+        Self::Weighted,
+    ];
+
+    #[inline]
+    pub const fn text(self) -> &'static str {
+        match self {
+            Self::Uniform => "Random",
+            Self::NoRepeat => "Random, no repeats",
+            Self::ShuffleBag => "Shuffle",
+            Self::Weighted => "Weighted",
+        }
+    }
+}
+
+/// Optional `zcb.json` file in the clickpack root, declaring info about the clickpack and
+/// the settings it's meant to be used with.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(default)]
+pub struct ClickpackManifest {
+    pub name: Option<String>,
+    pub author: Option<String>,
+    pub timings: Option<Timings>,
+    /// Hold durations for releases, see `Config::hold_aware_timings`
+    pub release_timings: Option<Timings>,
+    pub pitch: Option<Pitch>,
+    pub volume_settings: Option<VolumeSettings>,
+    pub noise_volume: Option<f64>,
+    pub selection_strategy: Option<SelectionStrategy>,
+    /// Extra folder names, e.g. `{"hardclicks": ["loud"], "player2": ["p2"]}`
+    pub aliases: HashMap<String, Vec<String>>,
+    /// Weights for [`SelectionStrategy::Weighted`] by file name, e.g. `{"click3.wav": 0.5}`.
+    /// Files that aren't listed have a weight of 1
+    pub weights: HashMap<String, f64>,
+}
+
+const MANIFEST_FILENAME: &str = "zcb.json";
+
+impl ClickpackManifest {
+    fn load(fs: &dyn ClickpackFs, dir: &Path) -> Option<Self> {
+        let path = fs.files(dir).into_iter().find(|path| {
+            path.file_name()
+                .is_some_and(|name| name.eq_ignore_ascii_case(MANIFEST_FILENAME))
+        })?;
+        log::info!("loading clickpack manifest {path:?}");
+        fs.read(&path)
+            .and_then(|data| Ok(serde_json::from_slice(&data)?))
+            .map_err(|e| log::error!("failed to load clickpack manifest {path:?}: {e}"))
+            .ok()
+    }
+
+    /// Maps an aliased directory name to the name it stands for, e.g. "loud" to
+    /// "hardclicks". Both names are normalized with `normalize`.
+    fn resolve_alias(&self, name: String, normalize: fn(&str) -> String) -> String {
+        for (target, aliases) in &self.aliases {
+            if aliases.iter().any(|alias| normalize(alias) == name) {
+                return normalize(target);
+            }
+        }
+        name
+    }
+
+    /// Whether the manifest gives a threshold for an extra tier.
+    fn has_tier(&self, tier: &str) -> bool {
+        self.timings
+            .iter()
+            .chain(&self.release_timings)
+            .any(|timings| timings.tiers.contains_key(tier))
+    }
+
+    fn weight(&self, path: &Path) -> f64 {
+        let filename = path.file_name().unwrap_or_default().to_string_lossy();
+        self.weights.get(filename.as_ref()).copied().unwrap_or(1.0)
+    }
+}
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ClickType {
     HardClick,
     HardRelease,
@@ -102,6 +301,17 @@ pub enum ClickType {
 }
 
 impl ClickType {
+    pub const ALL: [Self; 8] = [
+        Self::HardClick,
+        Self::HardRelease,
+        Self::Click,
+        Self::Release,
+        Self::SoftClick,
+        Self::SoftRelease,
+        Self::MicroClick,
+        Self::MicroRelease,
+    ];
+
     pub fn from_time(push: bool, time: f64, timings: &Timings) -> Self {
         if time > timings.hard {
             if push {
@@ -128,100 +338,29 @@ impl ClickType {
         }
     }
 
-    pub fn preferred(self) -> [Self; 8] {
-        use ClickType::*;
-
-        // HardClick => HardClick, Click, SoftClick, MicroClick, HardRelease, Release, SoftRelease, MicroRelease
-        // HardRelease => HardRelease, Release, SoftRelease, MicroRelease, HardClick, Click, SoftClick, MicroClick
-        // Click => Click, HardClick, SoftClick, MicroClick, Release, HardRelease, SoftRelease, MicroRelease
-        // Release => Release, HardRelease, SoftRelease, MicroRelease, Click, HardClick, SoftClick, MicroClick
-        // SoftClick => SoftClick, MicroClick, Click, HardClick, SoftRelease, MicroRelease, Release, HardRelease
-        // SoftRelease => SoftRelease, MicroRelease, Release, HardRelease, SoftClick, MicroClick, Click, HardClick
-        // MicroClick => MicroClick, SoftClick, Click, HardClick, MicroRelease, SoftRelease, Release, HardRelease
-        // MicroRelease => MicroRelease, SoftRelease, Release, HardRelease, MicroClick, SoftClick, Click, HardClick
+    pub const fn text(self) -> &'static str {
+        match self {
+            Self::HardClick => "Hardclicks",
+            Self::HardRelease => "Hardreleases",
+            Self::Click => "Clicks",
+            Self::Release => "Releases",
+            Self::SoftClick => "Softclicks",
+            Self::SoftRelease => "Softreleases",
+            Self::MicroClick => "Microclicks",
+            Self::MicroRelease => "Microreleases",
+            Self::None => "None",
+        }
+    }
 
+    /// Index of the click type's tier in [`STANDARD_TIERS`].
+    #[inline]
+    pub const fn tier_index(self) -> Option<usize> {
         match self {
-            HardClick => [
-                HardClick,
-                Click,
-                SoftClick,
-                MicroClick,
-                HardRelease,
-                Release,
-                SoftRelease,
-                MicroRelease,
-            ],
-            HardRelease => [
-                HardRelease,
-                Release,
-                SoftRelease,
-                MicroRelease,
-                HardClick,
-                Click,
-                SoftClick,
-                MicroClick,
-            ],
-            Click => [
-                Click,
-                HardClick,
-                SoftClick,
-                MicroClick,
-                Release,
-                HardRelease,
-                SoftRelease,
-                MicroRelease,
-            ],
-            Release => [
-                Release,
-                HardRelease,
-                SoftRelease,
-                MicroRelease,
-                Click,
-                HardClick,
-                SoftClick,
-                MicroClick,
-            ],
-            SoftClick => [
-                SoftClick,
-                MicroClick,
-                Click,
-                HardClick,
-                SoftRelease,
-                MicroRelease,
-                Release,
-                HardRelease,
-            ],
-            SoftRelease => [
-                SoftRelease,
-                MicroRelease,
-                Release,
-                HardRelease,
-                SoftClick,
-                MicroClick,
-                Click,
-                HardClick,
-            ],
-            MicroClick => [
-                MicroClick,
-                SoftClick,
-                Click,
-                HardClick,
-                MicroRelease,
-                SoftRelease,
-                Release,
-                HardRelease,
-            ],
-            MicroRelease => [
-                MicroRelease,
-                SoftRelease,
-                Release,
-                HardRelease,
-                MicroClick,
-                SoftClick,
-                Click,
-                HardClick,
-            ],
-            None => [None, None, None, None, None, None, None, None],
+            Self::HardClick | Self::HardRelease => Some(0),
+            Self::Click | Self::Release => Some(1),
+            Self::SoftClick | Self::SoftRelease => Some(2),
+            Self::MicroClick | Self::MicroRelease => Some(3),
+            Self::None => None,
         }
     }
 
@@ -245,17 +384,397 @@ impl ClickType {
     }
 }
 
+/// Level of a sound, measured when it's loaded. Both values are in dBFS.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Loudness {
+    pub peak: f64,
+    /// RMS level of the loudest 50ms, so the length of the tail doesn't matter
+    pub rms: f64,
+}
+
+/// Levels below this are treated as silence.
+const SILENCE_DB: f64 = -120.0;
+
+/// Most gain in dB that normalization adds or removes, so quiet folders aren't blown up.
+const MAX_NORMALIZE_GAIN: f64 = 24.0;
+
+fn power_to_db(power: f64) -> f64 {
+    (10.0 * power.log10()).max(SILENCE_DB)
+}
+
+impl Default for Loudness {
+    fn default() -> Self {
+        Self {
+            peak: SILENCE_DB,
+            rms: SILENCE_DB,
+        }
+    }
+}
+
+impl Loudness {
+    pub fn measure(frames: &[Frame], sample_rate: u32) -> Self {
+        if frames.is_empty() {
+            return Self::default();
+        }
+        let peak = frames
+            .iter()
+            .map(|f| f.left.abs().max(f.right.abs()))
+            .fold(0.0f32, f32::max);
+
+        // mean power of every window, from prefix sums
+        let mut sums = Vec::with_capacity(frames.len() + 1);
+        sums.push(0.0f64);
+        for f in frames {
+            let power = (f.left as f64).powi(2) + (f.right as f64).powi(2);
+            sums.push(sums.last().unwrap() + power / 2.0);
+        }
+        let window = ((sample_rate as f64 * 0.05) as usize).clamp(1, frames.len());
+        let max_power = (window..=frames.len())
+            .map(|end| (sums[end] - sums[end - window]) / window as f64)
+            .fold(0.0, f64::max);
+
+        Self {
+            peak: 20.0 * (peak as f64).log10().max(SILENCE_DB / 20.0),
+            rms: power_to_db(max_power),
+        }
+    }
+
+    /// Gain in dB that brings the RMS level to `target`. It's limited to
+    /// [`MAX_NORMALIZE_GAIN`] and to what keeps the peak at or below 0 dBFS, and silence
+    /// isn't changed at all.
+    pub fn gain_to(&self, target: f64) -> f64 {
+        if self.rms <= SILENCE_DB {
+            return 0.0;
+        }
+        (target - self.rms)
+            .clamp(-MAX_NORMALIZE_GAIN, MAX_NORMALIZE_GAIN)
+            .min(-self.peak)
+    }
+
+    /// Highest peak and power average of the RMS levels, or `None` for no sounds.
+    pub fn average<'a>(sounds: impl IntoIterator<Item = &'a SoundWrapper>) -> Option<Self> {
+        let mut count = 0;
+        let mut peak = SILENCE_DB;
+        let mut power = 0.0;
+        for sound in sounds {
+            count += 1;
+            peak = peak.max(sound.loudness.peak);
+            power += 10f64.powf(sound.loudness.rms / 10.0);
+        }
+        (count != 0).then(|| Self {
+            peak,
+            rms: power_to_db(power / count as f64),
+        })
+    }
+}
+
+/// What the clickpack loudness is normalized by.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum NormalizeMode {
+    #[default]
+    Off,
+    /// One gain for the whole clickpack, so softclicks stay quieter than hardclicks
+    Pack,
+    /// Every folder is made equally loud
+    Folder,
+}
+
+impl NormalizeMode {
+    pub const ALL: [Self; 3] = [Self::Off, Self::Pack, Self::Folder];
+
+    pub const fn text(self) -> &'static str {
+        match self {
+            Self::Off => "Off",
+            Self::Pack => "Per clickpack",
+            Self::Folder => "Per folder",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct Normalization {
+    pub mode: NormalizeMode,
+    /// Wanted RMS level in dBFS, see [`Loudness::rms`]
+    pub target: f64,
+}
+
+impl Default for Normalization {
+    fn default() -> Self {
+        Self {
+            mode: NormalizeMode::Off,
+            target: -18.0,
+        }
+    }
+}
+
+/// Measured loudness of one folder of a clickpack, see [`Clickpack::normalize`].
+#[derive(Clone, Debug)]
+pub struct FolderLoudness {
+    /// e.g. "player1/softclicks"
+    pub name: String,
+    pub loudness: Loudness,
+    /// Gain in dB applied by the normalization
+    pub gain: f64,
+}
+
+/// Processing applied to clicks when they're loaded.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct SampleSettings {
+    /// Cut the silence before the onset and after the end of every click
+    pub trim_silence: bool,
+    /// Anything quieter than this, in dB relative to the peak, counts as silence
+    pub trim_threshold: f64,
+    /// Time in seconds kept before the onset and after the end
+    pub pre_roll: f64,
+    /// Resample clicks to `sample_rate` when they're loaded instead of while playing
+    pub resample: bool,
+    /// Output sample rate. With FMOD this is replaced by the rate of the FMOD system
+    pub sample_rate: u32,
+    /// Keep decoded clicks in [`crate::cache::CACHE_DIR`], so they load faster next time
+    pub cache: bool,
+    /// Megabytes of decoded clicks to keep in memory, 0 for no limit. Clicks over the
+    /// budget are decoded again when they're played
+    pub memory_budget: f64,
+    /// Seconds crossfaded from the end of each noise layer into its start, so it loops
+    /// without a seam
+    pub loop_crossfade: f64,
+}
+
+impl Default for SampleSettings {
+    fn default() -> Self {
+        Self {
+            trim_silence: false,
+            trim_threshold: -45.0,
+            pre_roll: 0.001,
+            resample: false,
+            sample_rate: 48000,
+            cache: true,
+            memory_budget: 0.0,
+            loop_crossfade: 0.1,
+        }
+    }
+}
+
+impl SampleSettings {
+    /// Output sample rate to resample to, if resampling is enabled.
+    pub fn resample_rate(&self) -> Option<u32> {
+        self.resample.then_some(self.sample_rate)
+    }
+
+    /// Memory budget in bytes, if there is one.
+    pub fn memory_budget_bytes(&self) -> Option<usize> {
+        (self.memory_budget > 0.0).then_some((self.memory_budget * 1_000_000.0) as usize)
+    }
+
+    /// Settings for the noise, which is looped and shouldn't be trimmed.
+    fn for_noise(&self) -> Self {
+        Self {
+            trim_silence: false,
+            ..self.clone()
+        }
+    }
+}
+
+/// Range of the frames that aren't silence, with `margin` frames around it.
+fn audible_range(frames: &[Frame], threshold_db: f64, margin: usize) -> std::ops::Range<usize> {
+    let level = |f: &Frame| f.left.abs().max(f.right.abs());
+    let peak = frames.iter().map(level).fold(0.0f32, f32::max);
+    let threshold = peak * 10f32.powf(threshold_db as f32 / 20.0);
+    let Some(first) = frames.iter().position(|f| level(f) > threshold) else {
+        return 0..frames.len(); // silent, leave it alone
+    };
+    let last = frames.iter().rposition(|f| level(f) > threshold).unwrap();
+    first.saturating_sub(margin)..(last + 1 + margin).min(frames.len())
+}
+
+/// Settings of a single sample, from a sidecar file next to it (`click1.json` for
+/// `click1.wav`) or from its file name, see [`SampleMeta::from_filename`].
+#[derive(Serialize, Deserialize, Clone, PartialEq, Default, Debug)]
+#[serde(default)]
+pub struct SampleMeta {
+    /// Relative chance of being picked, overrides [`ClickpackManifest::weights`]
+    pub weight: Option<f64>,
+    /// Volume change in dB
+    pub gain: f64,
+    /// Pitch change in semitones
+    pub pitch: f64,
+    /// Samples with tags in `Config::excluded_sample_tags` are never picked
+    pub tags: Vec<String>,
+}
+
+impl SampleMeta {
+    /// Parses the parts of a file name separated by underscores or spaces: `w3` is a
+    /// weight of 3, `+2db` a gain of 2 dB and `-1st` a pitch change of -1 semitones, so
+    /// `click_w3_+2db.wav` plays 3 times as often and 2 dB louder. Gain and pitch need
+    /// a sign. Parts that don't match are ignored.
+    pub fn from_filename(stem: &str) -> Self {
+        let mut meta = Self::default();
+        for part in stem.split(['_', ' ']).map(str::to_lowercase) {
+            let signed = part.starts_with(['+', '-']);
+            if let Some(weight) = part.strip_prefix('w').and_then(|w| w.parse().ok()) {
+                meta.weight = Some(weight);
+            } else if let Some(gain) = part.strip_suffix("db").filter(|_| signed) {
+                meta.gain = gain.parse().unwrap_or(meta.gain);
+            } else if let Some(pitch) = part.strip_suffix("st").filter(|_| signed) {
+                meta.pitch = pitch.parse().unwrap_or(meta.pitch);
+            }
+        }
+        meta
+    }
+
+    /// Volume multiplier from the gain.
+    #[inline]
+    pub fn volume(&self) -> f64 {
+        10f64.powf(self.gain / 20.0)
+    }
+
+    /// Playback speed multiplier from the pitch change.
+    #[inline]
+    pub fn pitch_factor(&self) -> f64 {
+        2f64.powf(self.pitch / 12.0)
+    }
+
+    pub fn has_any_tag(&self, tags: &[String]) -> bool {
+        self.tags.iter().any(|tag| tags.contains(tag))
+    }
+}
+
+/// Everything needed to decode a sound again after it was unloaded.
+pub struct SoundSource {
+    file: SoundFile,
+    id: Option<FileId>,
+    settings: SampleSettings,
+    system: *mut FMOD_SYSTEM,
+}
+
+// sources are decoded on other threads, see `Clickpack::use_sound`. the FMOD system is
+// only passed to FMOD, which can be called from any thread
+unsafe impl Send for SoundSource {}
+unsafe impl Sync for SoundSource {}
+
+impl SoundSource {
+    /// Decodes the sound, without creating an FMOD sound. That's done with
+    /// [`SoundWrapper::set_frames`] on the thread that plays it.
+    fn decode(&self) -> Result<Sound> {
+        let system = std::ptr::null_mut();
+        let decode = || match &self.file {
+            SoundFile::Path(path) => SoundWrapper::from_path(system, path, &self.settings),
+            SoundFile::Bytes(data) => {
+                SoundWrapper::from_bytes(system, data.to_vec(), &self.settings)
+            }
+        };
+        let decoded = match &self.id {
+            Some(id) if self.settings.cache => SoundCache::default()
+                .load_or_decode(id, &self.settings, system, decode)
+                .map(|(sound, _)| sound),
+            _ => decode(),
+        };
+        decoded.map(|sound| sound.sound)
+    }
+
+    /// Bytes of the encoded file kept in memory.
+    fn memory_size(&self) -> usize {
+        match &self.file {
+            SoundFile::Path(_) => 0,
+            SoundFile::Bytes(data) => data.len(),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct SoundWrapper {
     pub sound: Sound,
     //pub pathbuf: PathBuf,
+    /// FMOD sound that plays straight from the frames of `sound`
     pub fmod_sound: *mut FMOD_SOUND,
+    /// Relative chance of being picked with [`SelectionStrategy::Weighted`]
+    pub weight: f64,
+    pub loudness: Loudness,
+    /// Volume multiplier from loudness normalization
+    pub gain: f64,
+    /// Seconds of silence trimmed from the start and the end
+    pub trimmed: (f64, f64),
+    pub meta: SampleMeta,
+    /// Set if the sound can be unloaded to stay within the memory budget
+    pub source: Option<Arc<SoundSource>>,
+    /// When the sound was last played, see [`Clickpack::use_sound`]
+    pub last_used: u64,
 }
 
 impl SoundWrapper {
-    pub fn from_path(system: *mut FMOD_SYSTEM, path: &Path) -> Result<Self> {
-        // load kittyaudio sound
-        let sound = Sound::from_path(path)?;
+    /// Loads a sound from a file. `system` can be NULL if FMOD is not used (e.g. when
+    /// rendering offline), in which case no FMOD sound is created.
+    ///
+    /// Sounds are always stereo, mono files play the same sound on both channels.
+    pub fn from_path(
+        system: *mut FMOD_SYSTEM,
+        path: &Path,
+        settings: &SampleSettings,
+    ) -> Result<Self> {
+        Self::from_sound(system, Sound::from_path(path)?, settings)
+    }
+
+    /// Loads a sound from an in-memory file, see [`SoundWrapper::from_path`].
+    pub fn from_bytes(
+        system: *mut FMOD_SYSTEM,
+        data: Vec<u8>,
+        settings: &SampleSettings,
+    ) -> Result<Self> {
+        Self::from_sound(system, Sound::from_cursor(Cursor::new(data))?, settings)
+    }
+
+    fn from_sound(
+        system: *mut FMOD_SYSTEM,
+        mut sound: Sound,
+        settings: &SampleSettings,
+    ) -> Result<Self> {
+        let mut trimmed = (0.0, 0.0);
+        if settings.trim_silence {
+            let sample_rate = sound.sample_rate();
+            let margin = (settings.pre_roll.max(0.0) * sample_rate as f64) as usize;
+            let range = audible_range(&sound.frames, settings.trim_threshold, margin);
+            trimmed = (
+                range.start as f64 / sample_rate as f64,
+                (sound.frames.len() - range.end) as f64 / sample_rate as f64,
+            );
+            if range.len() != sound.frames.len() {
+                sound = Sound::from_frames(sample_rate, &sound.frames[range]);
+            }
+        }
+        if let Some(rate) = settings.resample_rate() {
+            if rate != sound.sample_rate() {
+                let frames = audio::resample(&sound.frames, sound.sample_rate(), rate);
+                sound = Sound::from_frames(rate, &frames);
+            }
+        }
+
+        let loudness = Loudness::measure(&sound.frames, sound.sample_rate());
+        Ok(Self::from_processed(system, sound, loudness, trimmed))
+    }
+
+    /// Wraps a sound that was already processed by [`SoundWrapper::from_sound`], e.g.
+    /// one that was loaded from the cache.
+    pub(crate) fn from_processed(
+        system: *mut FMOD_SYSTEM,
+        sound: Sound,
+        loudness: Loudness,
+        trimmed: (f64, f64),
+    ) -> Self {
+        if system.is_null() {
+            return Self {
+                sound,
+                fmod_sound: std::ptr::null_mut(),
+                weight: 1.0,
+                loudness,
+                gain: 1.0,
+                trimmed,
+                meta: SampleMeta::default(),
+                source: None,
+                last_used: 0,
+            };
+        }
 
         // create fmod sound exinfo, we want to load the sound from memory
         let mut exinfo: FMOD_CREATESOUNDEXINFO = unsafe { std::mem::zeroed() };
@@ -265,14 +784,15 @@ impl SoundWrapper {
         exinfo.defaultfrequency = sound.sample_rate() as i32;
         exinfo.length = sound.frames.len() as u32 * std::mem::size_of::<f32>() as u32 * 2;
 
-        // create fmod sound
+        // create fmod sound. it points to the kittyaudio frames instead of copying them,
+        // they stay alive for as long as this sound (or a clone of it) does
         let mut fmod_sound: *mut FMOD_SOUND = std::ptr::null_mut();
         unsafe {
             // we ignore this error because it doesn't matter if you use the kittyaudio backend
             let _ = FMOD_System_CreateSound(
                 system,
                 sound.frames.as_ptr() as *const i8,
-                FMOD_OPENMEMORY | FMOD_OPENRAW | FMOD_LOOP_OFF,
+                FMOD_OPENMEMORY_POINT | FMOD_OPENRAW | FMOD_LOOP_OFF,
                 &mut exinfo,
                 &mut fmod_sound,
             )
@@ -280,10 +800,84 @@ impl SoundWrapper {
             .map_err(|e| log::error!("failed to create fmod sound: {e}"));
         };
 
-        Ok(Self { sound, fmod_sound })
+        Self {
+            sound,
+            fmod_sound,
+            weight: 1.0,
+            loudness,
+            gain: 1.0,
+            trimmed,
+            meta: SampleMeta::default(),
+            source: None,
+            last_used: 0,
+        }
     }
 
-    fn free(&mut self) {
+    /// Crossfades the end of the sound into its start, see [`audio::crossfade_loop`].
+    fn crossfade_loop(mut self, system: *mut FMOD_SYSTEM, seconds: f64) -> Self {
+        let len = (seconds.max(0.0) * self.sample_rate() as f64) as usize;
+        if len == 0 {
+            return self;
+        }
+        let frames = audio::crossfade_loop(&self.frames, len);
+        self.free();
+        let sound = Sound::from_frames(self.sample_rate(), &frames);
+        Self {
+            weight: self.weight,
+            ..Self::from_processed(system, sound, self.loudness, self.trimmed)
+        }
+    }
+
+    /// Whether the frames are in memory. Sounds without frames are decoded again by
+    /// [`Clickpack::use_sound`].
+    #[inline]
+    pub fn is_loaded(&self) -> bool {
+        !self.frames.is_empty()
+    }
+
+    /// Bytes of decoded frames. FMOD shares them, so this is all the memory it takes.
+    #[inline]
+    pub fn memory_size(&self) -> usize {
+        self.frames.len() * std::mem::size_of::<Frame>()
+    }
+
+    /// Frees the frames of a sound that can be decoded again. Everything else, like
+    /// the loudness and the gain, is kept.
+    fn unload(&mut self) -> bool {
+        if self.source.is_none() || !self.is_loaded() {
+            return false;
+        }
+        self.free();
+        self.sound = Sound::from_frames(self.sample_rate(), &[]);
+        true
+    }
+
+    /// Decodes an unloaded sound again.
+    fn reload(&mut self) -> Result<()> {
+        let Some(source) = &self.source else {
+            anyhow::bail!("sound can't be decoded again");
+        };
+        let sound = source.decode()?;
+        self.set_frames(sound);
+        Ok(())
+    }
+
+    /// Replaces the frames of an unloaded sound with ones decoded from its source.
+    fn set_frames(&mut self, sound: Sound) {
+        let system = self
+            .source
+            .as_ref()
+            .map_or(std::ptr::null_mut(), |source| source.system);
+        let decoded = Self::from_processed(system, sound, self.loudness, self.trimmed);
+        self.free();
+        self.sound = decoded.sound;
+        self.fmod_sound = decoded.fmod_sound;
+    }
+
+    pub(crate) fn free(&mut self) {
+        if self.fmod_sound.is_null() {
+            return;
+        }
         let _ = unsafe {
             FMOD_Sound_Release(self.fmod_sound)
                 .fmod_result()
@@ -307,148 +901,607 @@ impl std::ops::DerefMut for SoundWrapper {
     }
 }
 
+/// What to play when a clickpack doesn't have sounds for a click type.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Default, Debug)]
+pub enum Fallback {
+    /// The nearest tier with sounds, clicks for clicks and releases for releases first
+    #[default]
+    Nearest,
+    /// The first of these click types that has sounds, or nothing
+    Order(Vec<ClickType>),
+    /// Don't play anything
+    Nothing,
+    /// Like [`Fallback::Nearest`], but the pitch and volume are shifted towards the
+    /// missing tier
+    Synthesize,
+}
+
+impl Fallback {
+    pub const fn text(&self) -> &'static str {
+        match self {
+            Self::Nearest => "Nearest tier",
+            Self::Order(_) => "Custom order",
+            Self::Nothing => "Play nothing",
+            Self::Synthesize => "Shift nearest tier",
+        }
+    }
+}
+
+/// What [`PlayerClicks::random_click`] should pick a sound for.
+pub struct ClickQuery<'a> {
+    pub typ: ClickType,
+    /// Time used to classify the action, in seconds
+    pub dt: f64,
+    pub timings: &'a Timings,
+    /// Used if the tier that `dt` falls into has no sounds
+    pub fallback: &'a Fallback,
+    /// Sounds with any of these tags are skipped, see [`SampleMeta::tags`]
+    pub excluded_tags: &'a [String],
+}
+
+/// Position of a sound in a [`Clickpack`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SoundKey {
+    /// Index of the player clicks, see [`SLOT_NAMES`]
+    pub slot: usize,
+    /// Index in [`PlayerClicks::tiers`]
+    pub tier: usize,
+    pub release: bool,
+    pub index: usize,
+}
+
+/// A sound picked by [`PlayerClicks::random_click`].
+pub struct Pick<'a> {
+    pub sound: &'a SoundWrapper,
+    /// Where the sound is, the slot is only set by [`Clickpack::get_random_click`]
+    pub key: SoundKey,
+    /// How many tiers softer the wanted tier is than the one the sound is from, only
+    /// set for [`Fallback::Synthesize`]
+    pub tier_offset: i32,
+}
+
+/// Clicks and releases of one intensity.
 #[derive(Clone, Default)]
-pub struct PlayerClicks {
-    pub hardclicks: Vec<SoundWrapper>,
-    pub hardreleases: Vec<SoundWrapper>,
+pub struct Tier {
+    /// Folder name without "clicks"/"releases", e.g. "soft" or "ultrasoft". Empty for
+    /// regular clicks
+    pub name: String,
     pub clicks: Vec<SoundWrapper>,
     pub releases: Vec<SoundWrapper>,
-    pub softclicks: Vec<SoundWrapper>,
-    pub softreleases: Vec<SoundWrapper>,
-    pub microclicks: Vec<SoundWrapper>,
-    pub microreleases: Vec<SoundWrapper>,
+    /// Folder the clicks were loaded from
+    pub dir: Option<PathBuf>,
+    /// Whether the releases were generated from the clicks, see [`crate::synth`]
+    pub synthesized: bool,
 }
 
-fn read_clicks_in_directory(dir: &Path, system: *mut FMOD_SYSTEM) -> Vec<SoundWrapper> {
-    let Ok(dir) = dir.read_dir() else {
-        // log::warn!("can't find directory {dir:?}, skipping");
-        return vec![];
-    };
+impl Tier {
+    #[inline]
+    pub fn sounds(&self, release: bool) -> &[SoundWrapper] {
+        if release {
+            &self.releases
+        } else {
+            &self.clicks
+        }
+    }
+
+    #[inline]
+    pub fn sounds_mut(&mut self, release: bool) -> &mut Vec<SoundWrapper> {
+        if release {
+            &mut self.releases
+        } else {
+            &mut self.clicks
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct PlayerClicks {
+    /// Tiers in the order they were found. The order they're played in depends on the
+    /// timings, see [`PlayerClicks::random_click`]
+    pub tiers: Vec<Tier>,
+}
+
+fn read_clicks_in_directory(
+    fs: &dyn ClickpackFs,
+    dir: &Path,
+    manifest: &ClickpackManifest,
+    system: *mut FMOD_SYSTEM,
+    settings: &SampleSettings,
+) -> Vec<SoundWrapper> {
+    let (sidecars, files): (Vec<PathBuf>, Vec<PathBuf>) =
+        fs.files(dir).into_iter().partition(|path| is_sidecar(path));
     let mut sounds = vec![];
-    for entry in dir {
-        let path = entry.unwrap().path();
-        if path.is_file() {
-            let sound = SoundWrapper::from_path(system, &path);
-            if let Ok(sound) = sound {
-                sounds.push(sound);
-            } else if let Err(e) = sound {
-                log::error!("failed to load '{path:?}': {e}");
+    for path in files {
+        let sound = fs.load_sound(&path, system, settings);
+        if let Ok(mut sound) = sound {
+            if settings.trim_silence {
+                let (start, end) = sound.trimmed;
+                log::info!(
+                    "trimmed {:.1}ms from the start and {:.1}ms from the end of {path:?}",
+                    start * 1000.0,
+                    end * 1000.0
+                );
             }
+            sound.meta = read_sample_meta(fs, &path, &sidecars);
+            sound.weight = sound.meta.weight.unwrap_or_else(|| manifest.weight(&path));
+            // files on disk can always be unloaded, in case a budget is set later
+            if settings.memory_budget_bytes().is_some() || !fs.keep_copies() {
+                sound.source = fs
+                    .keep(&path)
+                    .map(|file| {
+                        Arc::new(SoundSource {
+                            file,
+                            id: fs.file_id(&path),
+                            settings: settings.clone(),
+                            system,
+                        })
+                    })
+                    .map_err(|e| log::warn!("can't unload {path:?}: {e}"))
+                    .ok();
+            }
+            sounds.push(sound);
+        } else if let Err(e) = sound {
+            log::error!("failed to load '{path:?}': {e}");
         }
     }
     sounds
 }
 
-impl PlayerClicks {
-    fn load_from_subdirs(&mut self, path: &Path, system: *mut FMOD_SYSTEM) {
-        let Ok(dir) = path
-            .read_dir()
-            .map_err(|e| log::warn!("failed to read directory {path:?}: {e}"))
-        else {
-            return;
+fn is_sidecar(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"))
+}
+
+/// Reads the metadata of a sample from its sidecar file if it has one, or from its file
+/// name otherwise.
+fn read_sample_meta(fs: &dyn ClickpackFs, path: &Path, sidecars: &[PathBuf]) -> SampleMeta {
+    let stem = path.file_stem().unwrap_or_default();
+    let Some(sidecar) = sidecars
+        .iter()
+        .find(|sidecar| sidecar.file_stem() == Some(stem))
+    else {
+        return SampleMeta::from_filename(&stem.to_string_lossy());
+    };
+    fs.read(sidecar)
+        .and_then(|data| Ok(serde_json::from_slice(&data)?))
+        .map_err(|e| log::error!("failed to read sample metadata {sidecar:?}: {e}"))
+        .unwrap_or_default()
+}
+
+/// Lowercase name without non-alphabetic characters, so "Soft Clicks" and "soft_clicks"
+/// are both "softclicks".
+fn normalize_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphabetic())
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+/// Like [`normalize_name`], but keeps digits for numbered folders like "clicks2" or
+/// "player2".
+fn normalize_numbered_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+fn normalize_dirname(path: &Path) -> String {
+    normalize_name(&path.file_name().unwrap_or_default().to_string_lossy())
+}
+
+fn normalize_numbered_dirname(path: &Path) -> String {
+    normalize_numbered_name(&path.file_name().unwrap_or_default().to_string_lossy())
+}
+
+/// Splits a normalized folder name like "ultrasoftclicks" into the tier name and whether
+/// it has releases, e.g. `("ultrasoft", false)`.
+fn parse_tier_name(name: &str) -> Option<(String, bool)> {
+    for (word, release) in [
+        ("releases", true),
+        ("release", true),
+        ("clicks", false),
+        ("click", false),
+    ] {
+        if let Some(pos) = name.find(word) {
+            let tier = format!("{}{}", &name[..pos], &name[pos + word.len()..]);
+            return Some((tier, release));
+        }
+    }
+    None
+}
+
+/// Tier indices ordered by distance from `target`. Ties go towards the end of the list
+/// that `target` is closer to, so regular clicks fall back to hardclicks before
+/// softclicks, and softclicks to microclicks before regular clicks.
+fn nearest_first(target: usize, len: usize) -> Vec<usize> {
+    let harder_first = target * 2 < len;
+    let mut order = vec![target];
+    for dist in 1..len {
+        let harder = target.checked_sub(dist);
+        let softer = Some(target + dist).filter(|&i| i < len);
+        let pair = if harder_first {
+            [harder, softer]
+        } else {
+            [softer, harder]
         };
-        for entry in dir {
-            let Ok(entry) = entry.map_err(|e| log::warn!("error in directory entry: {e}")) else {
-                continue;
-            };
-            self.load_from_dir(&entry.path(), system)
+        order.extend(pair.into_iter().flatten());
+    }
+    order
+}
+
+const CLICK_DIRNAMES: [[&str; 2]; 8] = [
+    ["hardclick", "hardclicks"],
+    ["hardrelease", "hardreleases"],
+    ["click", "clicks"],
+    ["release", "releases"],
+    ["softclick", "softclicks"],
+    ["softrelease", "softreleases"],
+    ["microclick", "microclicks"],
+    ["microrelease", "microreleases"],
+];
+
+/// Whether the directory is one of the folders a clickpack is made of.
+fn is_clickpack_dirname(path: &Path) -> bool {
+    let name = normalize_dirname(path);
+    CLICKPACK_DIRNAMES.contains(&normalize_numbered_dirname(path).as_str())
+        || CLICK_DIRNAMES.iter().flatten().any(|pat| *pat == name)
+}
+
+impl PlayerClicks {
+    fn load_from_subdirs(
+        &mut self,
+        fs: &dyn ClickpackFs,
+        path: &Path,
+        manifest: &ClickpackManifest,
+        system: *mut FMOD_SYSTEM,
+        settings: &SampleSettings,
+    ) {
+        for dir in fs.dirs(path) {
+            self.load_from_dir(fs, &dir, manifest, system, settings)
         }
     }
 
     // parses folders like "softclicks", "soft_clicks", "soft click", "microblablablarelease"
-    fn load_from_dir(&mut self, path: &Path, system: *mut FMOD_SYSTEM) {
+    fn load_from_dir(
+        &mut self,
+        fs: &dyn ClickpackFs,
+        path: &Path,
+        manifest: &ClickpackManifest,
+        system: *mut FMOD_SYSTEM,
+        settings: &SampleSettings,
+    ) {
         log::debug!("trying to match directory {:?}", path);
-        if path.is_file() {
-            log::debug!("skipping matching file {:?}", path);
-            return;
+        let filename = manifest.resolve_alias(normalize_dirname(path), normalize_name);
+        let typ = CLICK_DIRNAMES
+            .iter()
+            .position(|pats| pats.contains(&filename.as_str()))
+            .map(|i| ClickType::ALL[i]);
+        let tier = parse_tier_name(&normalize_numbered_dirname(path))
+            .or_else(|| parse_tier_name(&filename))
+            .filter(|(name, _)| !STANDARD_TIERS.contains(&name.as_str()));
+
+        let (name, release) = match (tier, typ) {
+            // tiers from the manifest win, so "clicks2" can be a tier instead of clicks
+            (Some((name, release)), _) if manifest.has_tier(&name) => (name, release),
+            (_, Some(typ)) => {
+                let name = STANDARD_TIERS[typ.tier_index().unwrap()];
+                (name.to_string(), typ.is_release())
+            }
+            (Some((name, release)), None) => {
+                if default_tier_threshold(&name).is_none() {
+                    log::info!("tier {name:?} has no timing, it won't play until one is set");
+                }
+                (name, release)
+            }
+            (None, None) => {
+                log::warn!("directory {:?} did not match any pattern", path);
+                return;
+            }
+        };
+        log::debug!("directory {path:?} matched tier {name:?} (release: {release})");
+        let tier = self.tier_mut(&name);
+        *tier.sounds_mut(release) = read_clicks_in_directory(fs, path, manifest, system, settings);
+        if !release {
+            tier.dir = Some(path.to_path_buf());
         }
-        let filename: String = path
-            .file_name()
-            .unwrap()
-            .to_string_lossy()
-            .chars()
-            .filter(|c| c.is_alphabetic())
-            .flat_map(|c| c.to_lowercase())
+    }
+
+    pub fn tier(&self, name: &str) -> Option<&Tier> {
+        self.tiers.iter().find(|tier| tier.name == name)
+    }
+
+    /// Returns the tier with the given name, adding it if it doesn't exist.
+    pub fn tier_mut(&mut self, name: &str) -> &mut Tier {
+        let idx = match self.tiers.iter().position(|tier| tier.name == name) {
+            Some(idx) => idx,
+            None => {
+                self.tiers.push(Tier {
+                    name: name.to_string(),
+                    ..Default::default()
+                });
+                self.tiers.len() - 1
+            }
+        };
+        &mut self.tiers[idx]
+    }
+
+    /// Tiers that aren't one of the [`STANDARD_TIERS`].
+    pub fn extra_tiers(&self) -> impl Iterator<Item = &Tier> {
+        self.tiers
+            .iter()
+            .filter(|tier| !STANDARD_TIERS.contains(&tier.name.as_str()))
+    }
+
+    pub fn num_sounds(&self) -> usize {
+        self.tiers
+            .iter()
+            .map(|tier| tier.clicks.len() + tier.releases.len())
+            .sum()
+    }
+
+    /// Sounds of one of the standard tiers.
+    pub fn sounds(&self, click_type: ClickType) -> &[SoundWrapper] {
+        click_type
+            .tier_index()
+            .and_then(|i| self.tier(STANDARD_TIERS[i]))
+            .map_or(&[], |tier| tier.sounds(click_type.is_release()))
+    }
+
+    /// Panics on [`ClickType::None`].
+    pub fn sounds_mut(&mut self, click_type: ClickType) -> &mut Vec<SoundWrapper> {
+        let tier = STANDARD_TIERS[click_type
+            .tier_index()
+            .expect("ClickType::None has no sounds")];
+        self.tier_mut(tier).sounds_mut(click_type.is_release())
+    }
+
+    /// Picks a sound from the tier that `query.dt` falls into. If it has no sounds (or
+    /// all of them are excluded by their tags), the `query.fallback` is used.
+    ///
+    /// `choose` gets the tier index, whether the sounds are releases, the sounds and the
+    /// indices of the ones that aren't excluded, and returns one of those indices.
+    pub fn random_click(
+        &self,
+        query: &ClickQuery,
+        mut choose: impl FnMut(usize, bool, &[SoundWrapper], &[usize]) -> usize,
+    ) -> Option<Pick<'_>> {
+        // standard tiers are always in the list, even if the clickpack doesn't have them,
+        // so the fallback order doesn't depend on which folders exist
+        let mut tiers: Vec<(f64, Option<usize>)> = STANDARD_TIERS
+            .iter()
+            .map(|name| {
+                let idx = self.tiers.iter().position(|tier| tier.name == *name);
+                (query.timings.threshold(name).unwrap_or_default(), idx)
+            })
             .collect();
-        let patterns = [
-            (["hardclick", "hardclicks"], &mut self.hardclicks),
-            (["hardrelease", "hardreleases"], &mut self.hardreleases),
-            (["click", "clicks"], &mut self.clicks),
-            (["release", "releases"], &mut self.releases),
-            (["softclick", "softclicks"], &mut self.softclicks),
-            (["softrelease", "softreleases"], &mut self.softreleases),
-            (["microclick", "microclicks"], &mut self.microclicks),
-            (["microrelease", "microreleases"], &mut self.microreleases),
-        ];
-        let mut matched_any = false;
-        for (pats, clicks) in patterns {
-            if pats.iter().any(|pat| *pat == filename) {
-                log::debug!("directory {path:?} matched patterns {pats:?}");
-                matched_any = true;
-                *clicks = read_clicks_in_directory(path, system);
+        for (idx, tier) in self.tiers.iter().enumerate() {
+            if STANDARD_TIERS.contains(&tier.name.as_str()) {
+                continue;
+            }
+            if let Some(threshold) = query.timings.threshold(&tier.name) {
+                tiers.push((threshold, Some(idx)));
             }
         }
-        if !matched_any {
-            log::warn!("directory {:?} did not match any pattern", path);
+        tiers.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        let mut pick = |idx: Option<usize>, release: bool, tier_offset: i32| {
+            let idx = idx?;
+            let sounds = self.tiers[idx].sounds(release);
+            let allowed: Vec<usize> = (0..sounds.len())
+                .filter(|&i| !sounds[i].meta.has_any_tag(query.excluded_tags))
+                .collect();
+            if allowed.is_empty() {
+                return None;
+            }
+            let index = choose(idx, release, sounds, &allowed);
+            Some(Pick {
+                sound: sounds.get(index)?,
+                key: SoundKey {
+                    slot: 0,
+                    tier: idx,
+                    release,
+                    index,
+                },
+                tier_offset,
+            })
+        };
+
+        let target = tiers
+            .iter()
+            .position(|(threshold, _)| query.dt > *threshold)
+            .unwrap_or(tiers.len() - 1);
+        let release = query.typ.is_release();
+        if let Some(pick) = pick(tiers[target].1, release, 0) {
+            return Some(pick);
         }
-    }
 
-    pub fn num_sounds(&self) -> usize {
-        self.hardclicks.len()
-            + self.hardreleases.len()
-            + self.clicks.len()
-            + self.releases.len()
-            + self.softclicks.len()
-            + self.softreleases.len()
-            + self.microclicks.len()
-            + self.microreleases.len()
-    }
-
-    pub fn random_click(&self, click_type: ClickType) -> Option<&SoundWrapper> {
-        macro_rules! rand_click {
-            ($arr:expr) => {{
-                let len = $arr.len();
-                if len == 0 {
-                    continue;
+        match query.fallback {
+            Fallback::Nothing => None,
+            Fallback::Order(types) => types.iter().find_map(|typ| {
+                let name = STANDARD_TIERS[typ.tier_index()?];
+                let idx = self.tiers.iter().position(|tier| tier.name == name);
+                pick(idx, typ.is_release(), 0)
+            }),
+            Fallback::Nearest | Fallback::Synthesize => {
+                let synthesize = *query.fallback == Fallback::Synthesize;
+                let order = nearest_first(target, tiers.len());
+                for same_kind in [true, false] {
+                    for &i in &order {
+                        // only shift sounds of the same kind, a click doesn't become a
+                        // release by changing its pitch
+                        let tier_offset = if synthesize && same_kind {
+                            target as i32 - i as i32
+                        } else {
+                            0
+                        };
+                        if let Some(pick) = pick(tiers[i].1, release == same_kind, tier_offset) {
+                            return Some(pick);
+                        }
+                    }
                 }
-                $arr.get(fastrand::usize(..len))
-            }};
-        }
-
-        let preferred = click_type.preferred();
-        for typ in preferred {
-            use ClickType::*;
-
-            let click = match typ {
-                HardClick => rand_click!(self.hardclicks),
-                HardRelease => rand_click!(self.hardreleases),
-                Click => rand_click!(self.clicks),
-                Release => rand_click!(self.releases),
-                SoftClick => rand_click!(self.softclicks),
-                SoftRelease => rand_click!(self.softreleases),
-                MicroClick => rand_click!(self.microclicks),
-                MicroRelease => rand_click!(self.microreleases),
-                None => continue,
-            };
-            if let Some(click) = click {
-                return Some(click);
+                None
             }
         }
-        None
+    }
+
+    /// Generates releases for every tier if none of the tiers have releases, replacing
+    /// releases that were generated before. Clicks that are unloaded because of the
+    /// memory budget are skipped. Returns the number of generated releases.
+    fn synthesize_releases(&mut self, settings: &ReleaseSynth, system: *mut FMOD_SYSTEM) -> usize {
+        for tier in self.tiers.iter_mut().filter(|tier| tier.synthesized) {
+            for sound in &mut tier.releases {
+                sound.free();
+            }
+            tier.releases.clear();
+            tier.synthesized = false;
+        }
+        if !settings.enabled || self.tiers.iter().any(|tier| !tier.releases.is_empty()) {
+            return 0;
+        }
+
+        let mut count = 0;
+        for tier in &mut self.tiers {
+            tier.releases = tier
+                .clicks
+                .iter()
+                .filter_map(|click| {
+                    if click.is_loaded() {
+                        return Some(synth::release_from_click(click, system, settings));
+                    }
+                    // clicks unloaded by the memory budget are decoded just for this
+                    let sound = click
+                        .source
+                        .as_ref()?
+                        .decode()
+                        .map_err(|e| log::error!("failed to decode click: {e}"))
+                        .ok()?;
+                    let click = SoundWrapper {
+                        sound,
+                        ..click.clone()
+                    };
+                    Some(synth::release_from_click(&click, system, settings))
+                })
+                .collect();
+            tier.synthesized = !tier.releases.is_empty();
+            count += tier.releases.len();
+        }
+        count
     }
 
     fn clear(&mut self) {
-        self.hardclicks.clear();
-        self.hardreleases.clear();
-        self.clicks.clear();
-        self.releases.clear();
-        self.softclicks.clear();
-        self.softreleases.clear();
-        self.microclicks.clear();
-        self.microreleases.clear();
+        self.free();
+        self.tiers.clear();
+    }
+
+    fn free(&mut self) {
+        for sounds in self
+            .tiers
+            .iter_mut()
+            .flat_map(|tier| [&mut tier.clicks, &mut tier.releases])
+        {
+            for sound in sounds {
+                sound.free();
+            }
+        }
+    }
+}
+
+/// Player clicks index in the [`Clickpack`], tier index and whether the sounds are releases.
+type SelectorKey = (usize, usize, bool);
+
+/// Remembers previous picks for the [`SelectionStrategy`]s that need them, separately
+/// for each player/button, tier and clicks/releases.
+#[derive(Default)]
+pub struct SampleSelector {
+    last: HashMap<SelectorKey, usize>,
+    bags: HashMap<SelectorKey, Vec<usize>>,
+}
+
+impl SampleSelector {
+    pub fn clear(&mut self) {
+        self.last.clear();
+        self.bags.clear();
+    }
+
+    /// Returns the index of the sound to play, one of `candidates` (the indices of the
+    /// sounds that may be played). The remembered picks are indices into all `sounds`,
+    /// so they stay valid when the candidates change.
+    pub fn pick(
+        &mut self,
+        strategy: SelectionStrategy,
+        key: SelectorKey,
+        sounds: &[SoundWrapper],
+        candidates: &[usize],
+        rng: &mut Rng,
+    ) -> usize {
+        let len = candidates.len();
+        let last = self.last.get(&key).copied().filter(|&i| i < sounds.len());
+        let idx = match strategy {
+            SelectionStrategy::Uniform => candidates[rng.usize(..len)],
+            SelectionStrategy::NoRepeat => {
+                match last.and_then(|last| candidates.iter().position(|&i| i == last)) {
+                    Some(last) if len > 1 => {
+                        // skip over the last sound
+                        let idx = rng.usize(..len - 1);
+                        candidates[if idx >= last { idx + 1 } else { idx }]
+                    }
+                    _ => candidates[rng.usize(..len)],
+                }
+            }
+            SelectionStrategy::ShuffleBag => {
+                let bag = self.bags.entry(key).or_default();
+                // sounds may have changed since the bag was filled
+                if bag.iter().any(|&i| i >= sounds.len()) {
+                    bag.clear();
+                }
+                // sounds that are excluded now are dropped from the bag
+                bag.retain(|i| candidates.contains(i));
+                if bag.is_empty() {
+                    bag.extend_from_slice(candidates);
+                    rng.shuffle(bag);
+                    // don't repeat the last sound of the previous bag. sounds are taken
+                    // from the end
+                    if len > 1 && last == bag.last().copied() {
+                        bag.swap(0, len - 1);
+                    }
+                }
+                bag.pop().unwrap_or_default()
+            }
+            SelectionStrategy::Weighted => {
+                let weight = |i: usize| sounds[i].weight.max(0.0);
+                let total: f64 = candidates.iter().map(|&i| weight(i)).sum();
+                if total > 0.0 {
+                    let mut target = rng.f64() * total;
+                    candidates
+                        .iter()
+                        .copied()
+                        .find(|&i| {
+                            target -= weight(i);
+                            target < 0.0
+                        })
+                        .unwrap_or(candidates[len - 1])
+                } else {
+                    candidates[rng.usize(..len)]
+                }
+            }
+        };
+        self.last.insert(key, idx);
+        idx
     }
 }
 
+/// One looping background sound, e.g. a PC fan or mic hiss.
+#[derive(Clone)]
+pub struct NoiseLayer {
+    /// File name without the extension, identifies the layer in the noise settings
+    pub name: String,
+    pub sound: SoundWrapper,
+}
+
+/// Sounds decoded on other threads, with the key of the sound they belong to.
+type DecodedSounds = Arc<Mutex<Vec<(SoundKey, Result<Sound>)>>>;
+
 #[derive(Default)]
 pub struct Clickpack {
     pub player1: PlayerClicks,
@@ -457,11 +1510,29 @@ pub struct Clickpack {
     pub right1: PlayerClicks,
     pub left2: PlayerClicks,
     pub right2: PlayerClicks,
-    pub noise: Option<SoundWrapper>,
+    pub noise: Vec<NoiseLayer>,
     pub num_sounds: usize,
     pub has_platformer_sounds: bool,
     pub name: String,
     pub path: PathBuf,
+    pub manifest: Option<ClickpackManifest>,
+    /// Loudness of all clicks, set by [`Clickpack::normalize`]
+    pub loudness: Option<Loudness>,
+    pub folder_loudness: Vec<FolderLoudness>,
+    /// Bytes of decoded clicks to keep in memory, from [`SampleSettings::memory_budget`]
+    pub memory_budget: Option<usize>,
+    /// Number of [`Clickpack::use_sound`] calls, used to find the least recently used
+    /// sounds
+    uses: u64,
+    /// Decode unloaded sounds on another thread and play a loaded one from the same tier
+    /// in the meantime, instead of blocking in [`Clickpack::use_sound`]. Set for live
+    /// playback, offline rendering waits for the sound
+    pub background_reloads: bool,
+    /// Sounds that are being decoded in the background
+    reloading: Vec<(SoundKey, Arc<SoundSource>)>,
+    /// Sounds decoded in the background, added to the clickpack by
+    /// [`Clickpack::poll_reloads`]
+    reloaded: DecodedSounds,
 }
 
 impl std::ops::Index<usize> for Clickpack {
@@ -496,6 +1567,9 @@ impl std::ops::IndexMut<usize> for Clickpack {
 
 const CLICKPACK_DIRNAMES: [&str; 6] = ["player1", "player2", "left1", "left2", "right1", "right2"];
 
+/// Names of the player clicks by their index in a [`Clickpack`].
+const SLOT_NAMES: [&str; 6] = ["player1", "player2", "left1", "right1", "left2", "right2"];
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub enum LoadClickpackFor {
     #[default]
@@ -522,52 +1596,142 @@ impl LoadClickpackFor {
     }
 }
 
-fn find_noise_file(dir: &Path) -> Option<PathBuf> {
-    let Ok(dir) = dir.read_dir() else {
-        return None;
-    };
-    for entry in dir {
-        let path = entry.unwrap().path();
-        let filename = path.file_name().unwrap().to_str().unwrap();
-        // if it's a noise*, etc file we should try to load it
-        if path.is_file()
-            && (filename.starts_with("noise")
+/// Finds the directory for one of [`CLICKPACK_DIRNAMES`], taking manifest aliases into
+/// account.
+fn find_player_dir(
+    fs: &dyn ClickpackFs,
+    clickpack_dir: &Path,
+    dirname: &str,
+    manifest: &ClickpackManifest,
+) -> PathBuf {
+    if manifest.aliases.is_empty() {
+        return clickpack_dir.join(dirname);
+    }
+    // player folders are numbered, so the digits have to be kept
+    fs.dirs(clickpack_dir)
+        .into_iter()
+        .find(|dir| {
+            manifest.resolve_alias(normalize_numbered_dirname(dir), normalize_numbered_name)
+                == dirname
+        })
+        .unwrap_or_else(|| clickpack_dir.join(dirname))
+}
+
+/// Noise files in `dir`: files named like noise*, whitenoise*, pcnoise* or background*,
+/// and everything in a "noise" folder.
+fn find_noise_files(fs: &dyn ClickpackFs, dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = fs
+        .files(dir)
+        .into_iter()
+        .filter(|path| {
+            let filename = path.file_name().unwrap_or_default().to_string_lossy();
+            filename.starts_with("noise")
                 || filename.starts_with("whitenoise")
                 || filename.starts_with("pcnoise")
-                || filename.starts_with("background"))
-        {
-            return Some(path);
+                || filename.starts_with("background")
+        })
+        .collect();
+    for noise_dir in fs.dirs(dir) {
+        if normalize_dirname(&noise_dir) == "noise" {
+            files.extend(fs.files(&noise_dir));
         }
     }
-    None
+    files
 }
 
 impl Clickpack {
-    fn load_noise(&mut self, dir: &Path, system: *mut FMOD_SYSTEM) {
-        let Some(path) = find_noise_file(dir) else {
-            return;
-        };
-        // try to load noise
-        self.noise = SoundWrapper::from_path(system, &path).ok();
+    fn load_noise(
+        &mut self,
+        fs: &dyn ClickpackFs,
+        dir: &Path,
+        system: *mut FMOD_SYSTEM,
+        settings: &SampleSettings,
+    ) {
+        for path in find_noise_files(fs, dir) {
+            let name = path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string();
+            if self.noise.iter().any(|layer| layer.name == name) {
+                continue;
+            }
+            match fs.load_sound(&path, system, &settings.for_noise()) {
+                Ok(sound) => {
+                    log::info!("loaded noise layer {name:?} from {path:?}");
+                    let sound = sound.crossfade_loop(system, settings.loop_crossfade);
+                    self.noise.push(NoiseLayer { name, sound });
+                }
+                Err(e) => log::error!("failed to load noise {path:?}: {e}"),
+            }
+        }
     }
 
+    fn clear_noise(&mut self) {
+        for layer in &mut self.noise {
+            layer.sound.free();
+        }
+        self.noise.clear();
+    }
+
+    /// Loads a clickpack from a directory or a zip archive.
     pub fn load_from_path(
         &mut self,
-        clickpack_dir: &Path,
+        path: &Path,
         load_for: LoadClickpackFor,
         system: *mut FMOD_SYSTEM,
+        settings: &SampleSettings,
     ) -> Result<()> {
-        log::info!("loading clickpack from path {clickpack_dir:?} for {load_for:?}");
-        self.path = clickpack_dir.to_path_buf();
-        self.name = clickpack_dir
+        log::info!("loading clickpack from path {path:?} for {load_for:?}");
+        self.memory_budget = settings.memory_budget_bytes();
+        self.path = path.to_path_buf();
+        self.name = path
             .file_name()
-            .unwrap()
+            .unwrap_or_default()
             .to_string_lossy()
             .to_string();
 
+        let zip;
+        let (fs, root): (&dyn ClickpackFs, PathBuf) = if vfs::is_zip(path) {
+            zip = ZipFs::open(path)?;
+            let root = zip.root(is_clickpack_dirname);
+            log::debug!("clickpack root inside archive: {root:?}");
+            (&zip, root)
+        } else {
+            (&DirFs, path.to_path_buf())
+        };
+        if !settings.cache {
+            return self.load_from_fs(fs, &root, load_for, system, settings);
+        }
+
+        let cache = SoundCache::default();
+        let cached = CachedFs::new(fs, &cache);
+        let result = self.load_from_fs(&cached, &root, load_for, system, settings);
+        let (hits, misses) = cached.stats();
+        log::info!("loaded {hits} sounds from the cache, decoded {misses}");
+        let _ = cache
+            .prune()
+            .map_err(|e| log::warn!("failed to prune the cache: {e}"));
+        result
+    }
+
+    fn load_from_fs(
+        &mut self,
+        fs: &dyn ClickpackFs,
+        clickpack_dir: &Path,
+        load_for: LoadClickpackFor,
+        system: *mut FMOD_SYSTEM,
+        settings: &SampleSettings,
+    ) -> Result<()> {
         if load_for != LoadClickpackFor::All {
-            self.noise = None;
+            self.clear_noise();
         }
+        let manifest = ClickpackManifest::load(fs, clickpack_dir);
+        // settings only come from the main clickpack
+        if load_for == LoadClickpackFor::All {
+            self.manifest.clone_from(&manifest);
+        }
+        let manifest = manifest.unwrap_or_default();
 
         // this is probably the most confusing code i've ever written
         let mut has_cleared = false;
@@ -585,31 +1749,29 @@ impl Clickpack {
                 has_cleared = true;
             }
 
-            let mut path = clickpack_dir.to_path_buf();
-            path.push(dir);
+            let path = find_player_dir(fs, clickpack_dir, dir, &manifest);
             log::debug!("loading from dir {path:?}");
 
-            sounds.load_from_subdirs(&path, system);
+            sounds.load_from_subdirs(fs, &path, &manifest, system, settings);
             if load_for != LoadClickpackFor::All && sounds.num_sounds() == 0 {
                 log::warn!("directory {dir:?} was not found or has no clicks, assuming there isn't a subdirectory");
-                sounds.load_from_subdirs(clickpack_dir, system);
+                sounds.load_from_subdirs(fs, clickpack_dir, &manifest, system, settings);
             }
+            // unload as we go, so the whole clickpack is never in memory at once
+            self.apply_memory_budget();
 
             // try to load noise from the sound directories
-            if self.noise.is_none() {
-                self.load_noise(&path, system);
-            }
+            self.load_noise(fs, &path, system, settings);
         }
 
         if !self.has_clicks() {
             log::warn!("folders {CLICKPACK_DIRNAMES:?} were not found in the clickpack, assuming there is only one player");
-            self[0].load_from_subdirs(clickpack_dir, system);
+            self[0].load_from_subdirs(fs, clickpack_dir, &manifest, system, settings);
+            self.apply_memory_budget();
         }
 
         // try to load noise from the root clickpack dir
-        if self.noise.is_none() {
-            self.load_noise(clickpack_dir, system);
-        }
+        self.load_noise(fs, clickpack_dir, system, settings);
 
         self.num_sounds = self.num_sounds();
         log::info!(
@@ -625,22 +1787,15 @@ impl Clickpack {
             ("right2", &self.right2),
         ] {
             log::info!("    {}: {} sounds", mode.0, mode.1.num_sounds());
-            for sounds in [
-                ("hardclicks", &mode.1.hardclicks),
-                ("hardreleases", &mode.1.hardreleases),
-                ("clicks", &mode.1.clicks),
-                ("releases", &mode.1.releases),
-                ("softclicks", &mode.1.softclicks),
-                ("softreleases", &mode.1.softreleases),
-                ("microclicks", &mode.1.microclicks),
-                ("microreleases", &mode.1.microreleases),
-            ] {
-                log::info!(
-                    "        {}: {} sounds{}",
-                    sounds.0,
-                    sounds.1.len(),
-                    if sounds.1.len() != 0 { " <<<<<<<" } else { "" }
-                );
+            for tier in &mode.1.tiers {
+                for (kind, sounds) in [("clicks", &tier.clicks), ("releases", &tier.releases)] {
+                    log::info!(
+                        "        {}{kind}: {} sounds{}",
+                        tier.name,
+                        sounds.len(),
+                        if !sounds.is_empty() { " <<<<<<<" } else { "" }
+                    );
+                }
             }
         }
         self.has_platformer_sounds = self.left1.num_sounds() != 0
@@ -673,94 +1828,381 @@ impl Clickpack {
             + self.right2.num_sounds()
     }
 
+    /// Picks a click for the player and button, see [`PlayerClicks::random_click`].
+    /// `choose` additionally gets the index of the player clicks.
     pub fn get_random_click(
-        &mut self,
-        typ: ClickType,
+        &self,
+        query: &ClickQuery,
         player2: bool,
         button: Button,
-    ) -> SoundWrapper {
+        mut choose: impl FnMut(usize, usize, bool, &[SoundWrapper], &[usize]) -> usize,
+    ) -> Option<Pick<'_>> {
         // try to get a random click/release from the player clicks
-        // if it doesn't exist for the wanted player, use the other one (guaranteed to have atleast
-        // one click)
-        let p1 = &self.player1;
-        let p2 = &self.player2;
-        let l1 = &self.left1;
-        let r1 = &self.right1;
-        let l2 = &self.left2;
-        let r2 = &self.right2;
-
-        fn get_first_valid_click<'a>(
-            sources: &'a [&'a PlayerClicks],
-            typ: ClickType,
-        ) -> SoundWrapper {
-            for source in sources {
-                if let Some(click) = source.random_click(typ) {
-                    return click.clone();
-                }
+        // if it doesn't exist for the wanted player, use the other one
+        let slots: [usize; 6] = match (button, player2) {
+            (Button::Jump, false) => [0, 1, 2, 3, 4, 5],
+            (Button::Jump, true) => [1, 0, 4, 5, 2, 3],
+            (Button::Left, false) => [2, 3, 0, 4, 5, 1],
+            (Button::Left, true) => [4, 5, 1, 2, 3, 0],
+            (Button::Right, false) => [3, 2, 0, 5, 4, 1],
+            (Button::Right, true) => [5, 4, 1, 3, 2, 0],
+        };
+        slots.into_iter().find_map(|slot| {
+            self[slot]
+                .random_click(query, |tier, release, sounds, allowed| {
+                    choose(slot, tier, release, sounds, allowed)
+                })
+                .map(|pick| Pick {
+                    key: SoundKey { slot, ..pick.key },
+                    ..pick
+                })
+        })
+    }
+
+    pub fn sound_mut(&mut self, key: SoundKey) -> Option<&mut SoundWrapper> {
+        self[key.slot]
+            .tiers
+            .get_mut(key.tier)?
+            .sounds_mut(key.release)
+            .get_mut(key.index)
+    }
+
+    fn sounds_with_keys(&self) -> impl Iterator<Item = (SoundKey, &SoundWrapper)> {
+        let tiers = (0..6).flat_map(move |slot| {
+            let tiers = self[slot].tiers.iter().enumerate();
+            tiers.map(move |(tier, sounds)| (slot, tier, sounds))
+        });
+        tiers.flat_map(|(slot, tier, sounds)| {
+            let key = move |release, index| SoundKey {
+                slot,
+                tier,
+                release,
+                index,
+            };
+            let clicks = sounds.clicks.iter().enumerate();
+            let releases = sounds.releases.iter().enumerate();
+            clicks
+                .map(move |(i, sound)| (key(false, i), sound))
+                .chain(releases.map(move |(i, sound)| (key(true, i), sound)))
+        })
+    }
+
+    /// Bytes of decoded clicks in memory, and of the encoded files kept to decode
+    /// unloaded clicks from archives.
+    pub fn memory_usage(&self) -> usize {
+        self.sounds_with_keys()
+            .map(|(_, sound)| {
+                let source = sound
+                    .source
+                    .as_ref()
+                    .map_or(0, |source| source.memory_size());
+                sound.memory_size() + source
+            })
+            .sum()
+    }
+
+    /// Number of clicks that are unloaded because of the memory budget.
+    pub fn num_unloaded(&self) -> usize {
+        self.sounds_with_keys()
+            .filter(|(_, sound)| !sound.is_loaded())
+            .count()
+    }
+
+    /// How rarely a sound is expected to be played: player 1 before player 2 before
+    /// platformer clicks, and regular clicks before other standard tiers before extra
+    /// tiers.
+    fn rarity(&self, key: SoundKey) -> usize {
+        let name = &self[key.slot].tiers[key.tier].name;
+        let tier_rarity = if name.is_empty() {
+            0
+        } else if STANDARD_TIERS.contains(&name.as_str()) {
+            1
+        } else {
+            2
+        };
+        key.slot * 3 + tier_rarity
+    }
+
+    /// Unloads the least recently used sounds until the decoded clicks fit in the memory
+    /// budget. Sounds that were never played go first, rarely used tiers before others.
+    pub fn apply_memory_budget(&mut self) {
+        let Some(budget) = self.memory_budget else {
+            return;
+        };
+        let mut used = self.memory_usage();
+        if used <= budget {
+            return;
+        }
+        let mut candidates: Vec<(u64, usize, SoundKey)> = self
+            .sounds_with_keys()
+            .filter(|(_, sound)| sound.is_loaded() && sound.source.is_some())
+            // the sound that is being played now stays
+            .filter(|(_, sound)| sound.last_used == 0 || sound.last_used != self.uses)
+            .map(|(key, sound)| (sound.last_used, self.rarity(key), key))
+            .collect();
+        candidates.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+
+        let mut num_unloaded = 0;
+        for (_, _, key) in candidates {
+            if used <= budget {
+                break;
+            }
+            let sound = self.sound_mut(key).unwrap();
+            let size = sound.memory_size();
+            if sound.unload() {
+                used -= size;
+                num_unloaded += 1;
             }
-            // this should never trigger unless we have a clickpack with 0 sounds,
-            // which is not going to lead us here
-            panic!("no valid clicks found, should be unreachable!");
         }
+        log::debug!(
+            "unloaded {num_unloaded} sounds, {:.1} MB of clicks in memory",
+            used as f64 / 1_000_000.0
+        );
+    }
 
-        match button {
-            Button::Jump => {
-                if !player2 {
-                    get_first_valid_click(&[p1, p2, l1, r1, l2, r2], typ)
-                } else {
-                    get_first_valid_click(&[p2, p1, l2, r2, l1, r1], typ)
+    /// Returns a sound to play. Other sounds may get unloaded to stay within the memory
+    /// budget.
+    ///
+    /// If the sound was unloaded it's decoded again, either right away or in the
+    /// background (see [`Clickpack::background_reloads`]). In the background case the
+    /// most recently used loaded sound from the same tier is returned instead, or an
+    /// error if there is none.
+    pub fn use_sound(&mut self, key: SoundKey) -> Result<&SoundWrapper> {
+        self.poll_reloads();
+        self.uses += 1;
+        let uses = self.uses;
+        let background = self.background_reloads;
+        let Some(sound) = self.sound_mut(key) else {
+            anyhow::bail!("no sound at {key:?}");
+        };
+        sound.last_used = uses;
+        if sound.is_loaded() {
+            return Ok(self.sound_mut(key).unwrap());
+        }
+        if !background {
+            sound.reload()?;
+            log::debug!("decoded unloaded sound {key:?}");
+            self.apply_memory_budget();
+            return Ok(self.sound_mut(key).unwrap());
+        }
+
+        self.start_reload(key);
+        let Some(fallback) = self.loaded_fallback(key) else {
+            anyhow::bail!("{key:?} is still being decoded");
+        };
+        let sound = self.sound_mut(fallback).unwrap();
+        sound.last_used = uses;
+        Ok(sound)
+    }
+
+    /// The most recently used loaded sound from the tier of `key`.
+    fn loaded_fallback(&self, key: SoundKey) -> Option<SoundKey> {
+        let sounds = self[key.slot].tiers.get(key.tier)?.sounds(key.release);
+        let (index, _) = sounds
+            .iter()
+            .enumerate()
+            .filter(|(_, sound)| sound.is_loaded())
+            .max_by_key(|(_, sound)| sound.last_used)?;
+        Some(SoundKey { index, ..key })
+    }
+
+    /// Starts decoding an unloaded sound on another thread.
+    fn start_reload(&mut self, key: SoundKey) {
+        if self.reloading.iter().any(|(k, _)| *k == key) {
+            return;
+        }
+        let Some(source) = self.sound_mut(key).and_then(|sound| sound.source.clone()) else {
+            return;
+        };
+        self.reloading.push((key, source.clone()));
+        let reloaded = self.reloaded.clone();
+        std::thread::spawn(move || {
+            let sound = source.decode();
+            reloaded.lock().push((key, sound));
+        });
+    }
+
+    /// Adds the sounds that finished decoding in the background, returns how many there
+    /// were.
+    pub fn poll_reloads(&mut self) -> usize {
+        let reloaded = std::mem::take(&mut *self.reloaded.lock());
+        let mut count = 0;
+        for (key, sound) in reloaded {
+            let Some(i) = self.reloading.iter().position(|(k, _)| *k == key) else {
+                continue;
+            };
+            let (_, source) = self.reloading.swap_remove(i);
+            let sound = match sound {
+                Ok(sound) => sound,
+                Err(e) => {
+                    log::error!("failed to decode {key:?}: {e}");
+                    continue;
                 }
+            };
+            // the clickpack may have been reloaded in the meantime
+            match self.sound_mut(key) {
+                Some(wrapper)
+                    if !wrapper.is_loaded()
+                        && wrapper
+                            .source
+                            .as_ref()
+                            .is_some_and(|s| Arc::ptr_eq(s, &source)) =>
+                {
+                    wrapper.set_frames(sound);
+                    count += 1;
+                }
+                _ => log::debug!("dropping decoded sound {key:?}, the clickpack changed"),
             }
-            Button::Left => {
-                if !player2 {
-                    get_first_valid_click(&[l1, r1, p1, l2, r2, p2], typ)
-                } else {
-                    get_first_valid_click(&[l2, r2, p2, l1, r1, p1], typ)
+        }
+        if count != 0 {
+            log::debug!("decoded {count} unloaded sounds in the background");
+            self.apply_memory_budget();
+        }
+        count
+    }
+
+    /// Whether any sounds are being decoded in the background.
+    pub fn is_reloading(&self) -> bool {
+        !self.reloading.is_empty()
+    }
+
+    /// Measures the loudness of every folder and sets the gain of each sound so the
+    /// clickpack (or every folder) has the target loudness.
+    pub fn normalize(&mut self, settings: &Normalization) {
+        self.loudness = Loudness::average((0..6).flat_map(|slot| {
+            self[slot]
+                .tiers
+                .iter()
+                .flat_map(|tier| tier.clicks.iter().chain(&tier.releases))
+        }));
+        let pack_gain = self
+            .loudness
+            .map_or(0.0, |loudness| loudness.gain_to(settings.target));
+
+        let mut folders = vec![];
+        for (slot, slot_name) in SLOT_NAMES.iter().enumerate() {
+            for tier in &mut self[slot].tiers {
+                for (kind, sounds) in [
+                    ("clicks", &mut tier.clicks),
+                    ("releases", &mut tier.releases),
+                ] {
+                    let Some(loudness) = Loudness::average(sounds.iter()) else {
+                        continue;
+                    };
+                    let gain = match settings.mode {
+                        NormalizeMode::Off => 0.0,
+                        NormalizeMode::Pack => pack_gain,
+                        NormalizeMode::Folder => loudness.gain_to(settings.target),
+                    };
+                    for sound in sounds.iter_mut() {
+                        sound.gain = 10f64.powf(gain / 20.0);
+                    }
+                    folders.push(FolderLoudness {
+                        name: format!("{slot_name}/{}{kind}", tier.name),
+                        loudness,
+                        gain,
+                    });
                 }
             }
-            Button::Right => {
-                if !player2 {
-                    get_first_valid_click(&[r1, l1, p1, r2, l2, p2], typ)
-                } else {
-                    get_first_valid_click(&[r2, l2, p2, r1, l1, p1], typ)
+        }
+        self.folder_loudness = folders;
+    }
+
+    /// Generates releases for the players that only have clicks, see
+    /// [`crate::synth::ReleaseSynth`]. Returns the number of generated releases.
+    pub fn synthesize_releases(
+        &mut self,
+        settings: &ReleaseSynth,
+        system: *mut FMOD_SYSTEM,
+    ) -> usize {
+        let count = (0..6)
+            .map(|slot| self[slot].synthesize_releases(settings, system))
+            .sum();
+        self.num_sounds = self.num_sounds();
+        self.apply_memory_budget();
+        count
+    }
+
+    /// Number of releases that were generated from clicks.
+    pub fn num_synthesized(&self) -> usize {
+        (0..6)
+            .flat_map(|slot| &self[slot].tiers)
+            .filter(|tier| tier.synthesized)
+            .map(|tier| tier.releases.len())
+            .sum()
+    }
+
+    /// Writes the generated releases as WAV files into a releases folder next to each
+    /// clicks folder, e.g. "softreleases" next to "softclicks", so they're loaded like
+    /// any other releases from then on. Returns the number of files written.
+    pub fn save_synthesized_releases(&mut self) -> Result<usize> {
+        if vfs::is_zip(&self.path) {
+            anyhow::bail!("can't save into a zip archive, extract the clickpack first");
+        }
+        // check every file first, so nothing is written if any of them exists
+        let mut targets = vec![];
+        for slot in 0..6 {
+            for (i, tier) in self[slot].tiers.iter().enumerate() {
+                let Some(clicks_dir) = tier.dir.as_ref().filter(|_| tier.synthesized) else {
+                    continue;
+                };
+                let dir = clicks_dir.with_file_name(format!("{}releases", tier.name));
+                let paths: Vec<PathBuf> = (1..=tier.releases.len())
+                    .map(|n| dir.join(format!("release{n}.wav")))
+                    .collect();
+                if let Some(path) = paths.iter().find(|path| path.exists()) {
+                    anyhow::bail!("{path:?} already exists");
                 }
+                targets.push((slot, i, dir, paths));
             }
         }
+
+        let mut count = 0;
+        for (slot, i, dir, paths) in targets {
+            let tier = &mut self[slot].tiers[i];
+            std::fs::create_dir_all(&dir)?;
+            for (sound, path) in tier.releases.iter().zip(&paths) {
+                render::write_wav(path, &sound.frames, sound.sample_rate())?;
+                count += 1;
+            }
+            log::info!("saved {} releases to {dir:?}", tier.releases.len());
+            tier.synthesized = false;
+        }
+        Ok(count)
+    }
+
+    /// Tags of all samples, sorted.
+    pub fn sample_tags(&self) -> Vec<String> {
+        let mut tags: Vec<String> = self
+            .sounds_with_keys()
+            .flat_map(|(_, sound)| sound.meta.tags.iter().cloned())
+            .collect();
+        tags.sort();
+        tags.dedup();
+        tags
+    }
+
+    /// Names of the extra tiers of all players, sorted.
+    pub fn extra_tiers(&self) -> Vec<String> {
+        let mut names: Vec<String> = (0..6)
+            .flat_map(|i| self[i].extra_tiers().map(|tier| tier.name.clone()))
+            .collect();
+        names.sort();
+        names.dedup();
+        names
     }
 
     #[inline]
-    pub const fn has_noise(&self) -> bool {
-        self.noise.is_some()
+    pub fn has_noise(&self) -> bool {
+        !self.noise.is_empty()
     }
 }
 
 impl Drop for Clickpack {
     fn drop(&mut self) {
-        if let Some(mut noise) = self.noise.take() {
-            noise.free();
-        }
-        for sounds in [
-            &mut self.player1,
-            &mut self.player2,
-            &mut self.left1,
-            &mut self.right1,
-            &mut self.left2,
-            &mut self.right2,
-        ] {
-            for clicks in [
-                &mut sounds.hardclicks,
-                &mut sounds.hardreleases,
-                &mut sounds.clicks,
-                &mut sounds.releases,
-                &mut sounds.softclicks,
-                &mut sounds.softreleases,
-                &mut sounds.microclicks,
-                &mut sounds.microreleases,
-            ] {
-                for sound in clicks {
-                    sound.free();
-                }
-            }
+        self.clear_noise();
+        for slot in 0..6 {
+            self[slot].free();
         }
     }
 }
@@ -125,7 +125,7 @@ pub mod base_game_layer {
 
     pub fn init(this: usize) {
         unsafe {
-            BOT.on_init(GameManager::shared().play_layer().addr);
+            BOT.on_level_start(GameManager::shared().play_layer().addr);
             INIT_ORIGINAL.call(this);
         }
     }
@@ -1,5 +1,7 @@
-mod bot;
-mod clickpack;
+pub mod audio;
+pub mod bot;
+pub mod cache;
+pub mod clickpack;
 
 #[cfg(not(feature = "geode"))]
 mod game;
@@ -7,7 +9,11 @@ mod game;
 #[cfg(not(feature = "geode"))]
 mod hooks;
 
+pub mod render;
+pub mod replay;
+pub mod synth;
 mod utils;
+mod vfs;
 
 use bot::{Bot, BOT};
 use clickpack::Button;
@@ -242,7 +248,7 @@ unsafe extern "C" fn zcblive_set_playlayer_time(playlayer_time: f64) {
 /// can pass NULL to `playlayer`
 #[no_mangle]
 unsafe extern "C" fn zcblive_on_init(playlayer: usize) {
-    BOT.on_init(playlayer);
+    BOT.on_level_start(playlayer);
 }
 
 /// equivalent to passing NULL to `zcblive_on_init`. optional implementation
@@ -0,0 +1,287 @@
+//! Offline rendering of actions into audio, using the same click algorithm as the
+//! live clickbot.
+
+use crate::{
+    audio::{pan_gains, LowPass},
+    bot::{ClickState, Config},
+    clickpack::{Button, Clickpack, SoundWrapper},
+};
+use anyhow::Result;
+use fastrand::Rng;
+use kittyaudio::Frame;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// A single button press or release at a point in time.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Action {
+    /// Time of the action in seconds, relative to the level start
+    pub time: f64,
+    pub button: Button,
+    pub player2: bool,
+    pub push: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenderSettings {
+    pub sample_rate: u32,
+    /// Extra time in seconds rendered after the last action, so the last click doesn't
+    /// get cut off
+    pub tail: f64,
+    /// Whether to mix the clickpack noise (if any) under the clicks
+    pub noise: bool,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            sample_rate: 44100,
+            tail: 1.0,
+            noise: false,
+        }
+    }
+}
+
+/// A sound that is currently being mixed.
+struct Voice {
+    sound: SoundWrapper,
+    pos: f64,
+    step: f64,
+    /// Left and right channel volume
+    gains: (f32, f32),
+    filter: Option<LowPass>,
+    looping: bool,
+}
+
+impl Voice {
+    fn new(
+        sound: SoundWrapper,
+        speed: f64,
+        volume: f64,
+        pan: f64,
+        lowpass: f64,
+        looping: bool,
+        sample_rate: u32,
+    ) -> Self {
+        let step = speed * sound.sample_rate() as f64 / sample_rate as f64;
+        let (left, right) = pan_gains(pan);
+        Self {
+            sound,
+            pos: 0.0,
+            step,
+            gains: (left * volume as f32, right * volume as f32),
+            filter: (lowpass > 0.0).then(|| LowPass::new(lowpass, sample_rate)),
+            looping,
+        }
+    }
+
+    /// Returns the next resampled frame, or `None` if the sound has finished playing.
+    fn next_frame(&mut self) -> Option<Frame> {
+        let frames = &self.sound.frames;
+        let len = frames.len();
+        if len == 0 {
+            return None;
+        }
+        if self.looping && self.pos >= len as f64 {
+            self.pos %= len as f64;
+        }
+        let idx = self.pos as usize;
+        if idx >= len {
+            return None;
+        }
+
+        // linear interpolation between this frame and the next one
+        let next = if idx + 1 < len {
+            idx + 1
+        } else if self.looping {
+            0
+        } else {
+            idx
+        };
+        let t = self.pos.fract() as f32;
+        let (a, b) = (&frames[idx], &frames[next]);
+        self.pos += self.step;
+        let frame = Frame {
+            left: (a.left + (b.left - a.left) * t) * self.gains.0,
+            right: (a.right + (b.right - a.right) * t) * self.gains.1,
+        };
+        Some(match &mut self.filter {
+            Some(filter) => filter.process(frame),
+            None => frame,
+        })
+    }
+}
+
+/// Mixes all voices into `out`, dropping the ones that have finished playing.
+fn mix(out: &mut [Frame], voices: &mut Vec<Voice>) {
+    for frame in out.iter_mut() {
+        voices.retain_mut(|voice| {
+            let Some(f) = voice.next_frame() else {
+                return false;
+            };
+            frame.left += f.left;
+            frame.right += f.right;
+            true
+        });
+        if voices.is_empty() {
+            break;
+        }
+    }
+}
+
+/// Adds `noise` to `out`, ducking it by `amount` under each click in `clicks` (frame
+/// positions, sorted) and bringing it back over `release` frames.
+fn mix_ducked_noise(
+    out: &mut [Frame],
+    noise: &[Frame],
+    clicks: &[usize],
+    amount: f64,
+    release: f64,
+) {
+    let step = if release > 0.0 {
+        amount / release
+    } else {
+        amount
+    };
+    let mut clicks = clicks.iter().peekable();
+    let mut duck = 0.0;
+    for (i, (frame, noise)) in out.iter_mut().zip(noise).enumerate() {
+        while clicks.next_if(|&&pos| pos <= i).is_some() {
+            duck = amount;
+        }
+        let gain = (1.0 - duck) as f32;
+        frame.left += noise.left * gain;
+        frame.right += noise.right * gain;
+        duck = (duck - step).max(0.0);
+    }
+}
+
+/// Renders a list of actions into stereo frames at `settings.sample_rate`.
+///
+/// Actions don't need to be sorted.
+pub fn render(
+    conf: &Config,
+    clickpack: &mut Clickpack,
+    actions: &[Action],
+    settings: &RenderSettings,
+) -> Result<Vec<Frame>> {
+    if clickpack.num_sounds == 0 {
+        anyhow::bail!("clickpack has no sounds");
+    }
+    let sample_rate = settings.sample_rate.max(1);
+
+    let mut actions = actions.to_vec();
+    actions.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+    let end = actions.last().map_or(0.0, |a| a.time.max(0.0)) + settings.tail.max(0.0);
+    let num_frames = (end * sample_rate as f64).ceil() as usize;
+    let mut out = vec![
+        Frame {
+            left: 0.0,
+            right: 0.0
+        };
+        num_frames
+    ];
+    log::info!(
+        "rendering {} actions, {end:.3}s ({num_frames} frames at {sample_rate} Hz)",
+        actions.len()
+    );
+
+    // noise is mixed separately so cutting sounds doesn't stop it, and added after the
+    // clicks so it can be ducked under them
+    let mut noise_out = vec![];
+    if settings.noise {
+        if !clickpack.has_noise() {
+            log::warn!("clickpack doesn't have a noise file, not rendering noise");
+        }
+        // separate from the click rng, so rendering noise doesn't change the clicks
+        let mut rng = conf.rng_seed.map_or_else(Rng::new, Rng::with_seed);
+        let mut noise: Vec<Voice> = clickpack
+            .noise
+            .iter()
+            .filter(|layer| conf.noise.layer(&layer.name).enabled)
+            .map(|layer| {
+                let volume = conf.effective_noise_volume() * conf.noise.layer(&layer.name).volume;
+                let len = layer.sound.frames.len();
+                let mut voice = Voice::new(
+                    layer.sound.clone(),
+                    conf.noise_speedhack,
+                    volume,
+                    0.0,
+                    0.0,
+                    true,
+                    sample_rate,
+                );
+                if conf.noise.random_start && len > 0 {
+                    voice.pos = rng.usize(..len) as f64;
+                }
+                voice
+            })
+            .collect();
+        noise_out = out.clone();
+        mix(&mut noise_out, &mut noise);
+    }
+
+    let mut state = ClickState::default();
+    state.reset(conf);
+    let mut voices: Vec<Voice> = vec![];
+    let mut click_positions = vec![];
+    let mut cursor = 0;
+    for action in &actions {
+        let pos = ((action.time.max(0.0) * sample_rate as f64).round() as usize).min(num_frames);
+        mix(&mut out[cursor..pos], &mut voices);
+        cursor = pos;
+
+        let Some(click) = state.next_click(
+            conf,
+            clickpack,
+            action.time,
+            action.button,
+            action.player2,
+            action.push,
+        ) else {
+            continue;
+        };
+        if click.cut {
+            voices.clear();
+        }
+        click_positions.push(pos);
+        voices.push(Voice::new(
+            click.sound,
+            click.pitch,
+            click.volume,
+            click.pan,
+            click.lowpass,
+            false,
+            sample_rate,
+        ));
+    }
+    mix(&mut out[cursor..], &mut voices);
+
+    let duck = conf.noise.click_duck.clamp(0.0, 1.0);
+    mix_ducked_noise(
+        &mut out,
+        &noise_out,
+        if duck > 0.0 { &click_positions } else { &[] },
+        duck,
+        conf.noise.click_duck_release * sample_rate as f64,
+    );
+    Ok(out)
+}
+
+/// Writes stereo frames to a 32-bit float WAV file.
+pub fn write_wav(path: &Path, frames: &[Frame], sample_rate: u32) -> Result<()> {
+    let spec = hound::WavSpec {
+        channels: 2,
+        sample_rate,
+        bits_per_sample: 32,
+        sample_format: hound::SampleFormat::Float,
+    };
+    let mut writer = hound::WavWriter::create(path, spec)?;
+    for frame in frames {
+        writer.write_sample(frame.left)?;
+        writer.write_sample(frame.right)?;
+    }
+    writer.finalize()?;
+    Ok(())
+}
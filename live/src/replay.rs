@@ -0,0 +1,344 @@
+//! Parsers for Geometry Dash bot macro formats, producing actions for offline rendering.
+
+use crate::{clickpack::Button, render::Action};
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::path::Path;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ReplayFormat {
+    /// GDR JSON (`.gdr.json`)
+    Gdr,
+    /// Mega Hack replay JSON (`.mhr.json`)
+    MegaHackJson,
+    /// TASBot JSON
+    TasBot,
+    /// Echo JSON (`.echo`)
+    Echo,
+    /// zBot frame macros (`.zbf`)
+    Zbot,
+    /// yBot frame macros (`.ybf`)
+    Ybot,
+    /// Plain text: optional FPS on the first line, then `frame button push [player2]`
+    Plain,
+}
+
+impl ReplayFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name.to_lowercase().as_str() {
+            "gdr" => Self::Gdr,
+            "mhr" | "megahack" | "mhrjson" => Self::MegaHackJson,
+            "tasbot" => Self::TasBot,
+            "echo" => Self::Echo,
+            "zbot" | "zbf" => Self::Zbot,
+            "ybot" | "ybf" => Self::Ybot,
+            "plain" | "txt" => Self::Plain,
+            _ => return None,
+        })
+    }
+
+    /// Guesses the format from the file extension and contents.
+    pub fn guess(path: &Path, data: &[u8]) -> Option<Self> {
+        let ext = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match ext.as_str() {
+            "zbf" => return Some(Self::Zbot),
+            "ybf" => return Some(Self::Ybot),
+            "txt" => return Some(Self::Plain),
+            _ => {}
+        }
+        if data.starts_with(b"ybot") {
+            return Some(Self::Ybot);
+        }
+
+        // json formats are detected by their keys
+        if let Ok(Value::Object(obj)) = serde_json::from_slice::<Value>(data) {
+            return if obj.contains_key("inputs") {
+                Some(Self::Gdr)
+            } else if obj.contains_key("events") {
+                Some(Self::MegaHackJson)
+            } else if obj.contains_key("macro") {
+                Some(Self::TasBot)
+            } else if obj.contains_key("Echo Replay") {
+                Some(Self::Echo)
+            } else {
+                None
+            };
+        }
+
+        std::str::from_utf8(data).is_ok().then_some(Self::Plain)
+    }
+}
+
+/// `(frame, button, player2, push)` inputs, before frames are converted to seconds.
+type Inputs = Vec<(f64, Button, bool, bool)>;
+
+/// Actions read from a macro, with times converted to seconds.
+#[derive(Clone, Debug, Default)]
+pub struct Replay {
+    pub fps: f64,
+    pub actions: Vec<Action>,
+}
+
+/// Reads little-endian values from a byte slice.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn bytes<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + N)
+            .context("unexpected end of file")?;
+        self.pos += N;
+        Ok(bytes.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes::<1>()?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes()?))
+    }
+
+    fn i32(&mut self) -> Result<i32> {
+        Ok(i32::from_le_bytes(self.bytes()?))
+    }
+
+    fn f32(&mut self) -> Result<f32> {
+        Ok(f32::from_le_bytes(self.bytes()?))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+}
+
+fn json_f64(value: &Value, keys: &[&str]) -> Option<f64> {
+    keys.iter().find_map(|key| value.get(key)?.as_f64())
+}
+
+fn json_bool(value: &Value, keys: &[&str]) -> Option<bool> {
+    keys.iter().find_map(|key| {
+        let v = value.get(key)?;
+        v.as_bool().or_else(|| v.as_u64().map(|v| v != 0))
+    })
+}
+
+fn json_array<'a>(value: &'a Value, key: &str) -> Result<&'a Vec<Value>> {
+    value
+        .get(key)
+        .and_then(Value::as_array)
+        .with_context(|| format!("missing \"{key}\" array"))
+}
+
+/// The button of an input, jump if it doesn't have one.
+fn json_button(value: &Value) -> Result<Button> {
+    let Some(button) = value.get("btn").or_else(|| value.get("button")) else {
+        return Ok(Button::Jump);
+    };
+    button
+        .as_u64()
+        .and_then(|b| Button::try_from_u8(b.try_into().ok()?))
+        .with_context(|| format!("invalid button {button}"))
+}
+
+impl Replay {
+    /// Loads a macro from a file. `format` and `fps` are guessed if not given.
+    pub fn load(path: &Path, format: Option<ReplayFormat>, fps: Option<f64>) -> Result<Self> {
+        let data = std::fs::read(path).with_context(|| format!("failed to read {path:?}"))?;
+        let format = format
+            .or_else(|| ReplayFormat::guess(path, &data))
+            .with_context(|| format!("unknown macro format: {path:?}"))?;
+        log::info!("loading {format:?} macro from {path:?}");
+        Self::parse(format, &data, fps)
+    }
+
+    /// Parses a macro. `fps` overrides the framerate stored in the macro, and is required
+    /// for plain text macros that don't specify one.
+    pub fn parse(format: ReplayFormat, data: &[u8], fps: Option<f64>) -> Result<Self> {
+        let mut replay = Self::default();
+        let frames = match format {
+            ReplayFormat::Gdr => replay.parse_gdr(data)?,
+            ReplayFormat::MegaHackJson => replay.parse_mhr_json(data)?,
+            ReplayFormat::TasBot => replay.parse_tasbot(data)?,
+            ReplayFormat::Echo => replay.parse_echo(data)?,
+            ReplayFormat::Zbot => replay.parse_zbf(data)?,
+            ReplayFormat::Ybot => replay.parse_ybf(data)?,
+            ReplayFormat::Plain => replay.parse_plain(data)?,
+        };
+
+        if let Some(fps) = fps {
+            replay.fps = fps;
+        }
+        if !(replay.fps > 0.0 && replay.fps.is_finite()) {
+            anyhow::bail!("invalid macro fps {}, specify it manually", replay.fps);
+        }
+
+        // convert frames to seconds
+        replay.actions = frames
+            .into_iter()
+            .map(|(frame, button, player2, push)| Action {
+                time: frame / replay.fps,
+                button,
+                player2,
+                push,
+            })
+            .collect();
+        replay.actions.sort_by(|a, b| a.time.total_cmp(&b.time));
+        log::info!(
+            "loaded {} actions at {} fps",
+            replay.actions.len(),
+            replay.fps
+        );
+        Ok(replay)
+    }
+
+    fn parse_gdr(&mut self, data: &[u8]) -> Result<Inputs> {
+        let v: Value = serde_json::from_slice(data)?;
+        self.fps = json_f64(&v, &["framerate", "fps"]).unwrap_or(240.0);
+        json_array(&v, "inputs")?
+            .iter()
+            .map(|input| {
+                Ok((
+                    json_f64(input, &["frame"]).context("input has no frame")?,
+                    json_button(input)?,
+                    json_bool(input, &["2p"]).unwrap_or(false),
+                    json_bool(input, &["down"]).context("input has no \"down\"")?,
+                ))
+            })
+            .collect()
+    }
+
+    fn parse_mhr_json(&mut self, data: &[u8]) -> Result<Inputs> {
+        let v: Value = serde_json::from_slice(data)?;
+        self.fps = v
+            .get("meta")
+            .and_then(|meta| json_f64(meta, &["fps"]))
+            .unwrap_or(240.0);
+        json_array(&v, "events")?
+            .iter()
+            .filter_map(|event| {
+                // events without "down" are physics events, skip them
+                let frame = json_f64(event, &["frame"])?;
+                let push = json_bool(event, &["down"])?;
+                let player2 = json_bool(event, &["p2"]).unwrap_or(false);
+                Some(json_button(event).map(|button| (frame, button, player2, push)))
+            })
+            .collect()
+    }
+
+    fn parse_tasbot(&mut self, data: &[u8]) -> Result<Inputs> {
+        let v: Value = serde_json::from_slice(data)?;
+        self.fps = json_f64(&v, &["fps"]).unwrap_or(60.0);
+        let mut actions = vec![];
+        for frame_data in json_array(&v, "macro")? {
+            let frame = json_f64(frame_data, &["frame"]).context("macro entry has no frame")?;
+            for (key, player2) in [("player_1", false), ("player_2", true)] {
+                // 0 = nothing, 1 = push, 2 = release
+                match frame_data
+                    .get(key)
+                    .and_then(|p| json_f64(p, &["click"]))
+                    .unwrap_or(0.0) as u8
+                {
+                    1 => actions.push((frame, Button::Jump, player2, true)),
+                    2 => actions.push((frame, Button::Jump, player2, false)),
+                    _ => {}
+                }
+            }
+        }
+        Ok(actions)
+    }
+
+    fn parse_echo(&mut self, data: &[u8]) -> Result<Inputs> {
+        let v: Value = serde_json::from_slice(data)?;
+        self.fps = json_f64(&v, &["FPS", "fps"]).unwrap_or(240.0);
+        let starting_frame = json_f64(&v, &["Starting Frame"]).unwrap_or(0.0);
+        json_array(&v, "Echo Replay")?
+            .iter()
+            .map(|input| {
+                Ok((
+                    json_f64(input, &["Frame"]).context("input has no frame")? - starting_frame,
+                    Button::Jump,
+                    json_bool(input, &["Player 2"]).unwrap_or(false),
+                    json_bool(input, &["Hold"]).context("input has no \"Hold\"")?,
+                ))
+            })
+            .collect()
+    }
+
+    fn parse_zbf(&mut self, data: &[u8]) -> Result<Inputs> {
+        let mut r = Reader::new(data);
+        let delta = r.f32()? as f64;
+        let speedhack = r.f32()? as f64;
+        self.fps = 1.0 / delta / speedhack;
+
+        let mut actions = vec![];
+        while !r.is_empty() {
+            let frame = r.i32()? as f64;
+            let push = r.u8()? == 0x31;
+            let player1 = r.u8()? == 0x31;
+            actions.push((frame, Button::Jump, !player1, push));
+        }
+        Ok(actions)
+    }
+
+    fn parse_ybf(&mut self, data: &[u8]) -> Result<Inputs> {
+        let mut r = Reader::new(data);
+        if &r.bytes::<4>()? != b"ybot" {
+            anyhow::bail!("not a yBot macro (invalid magic)");
+        }
+        self.fps = r.f32()? as f64;
+        let num_actions = r.i32()?.max(0) as usize;
+        r.pos = 24; // skip the rest of the header
+
+        // the count comes from the file, don't trust it for the allocation
+        let mut actions = Vec::with_capacity(num_actions.min(data.len() / 5));
+        for _ in 0..num_actions {
+            let frame = r.u32()? as f64;
+            let state = r.u8()?;
+            actions.push((frame, Button::Jump, state & 0b01 != 0, state & 0b10 != 0));
+        }
+        Ok(actions)
+    }
+
+    fn parse_plain(&mut self, data: &[u8]) -> Result<Inputs> {
+        let text = std::str::from_utf8(data).context("plain text macro is not valid UTF-8")?;
+        let mut actions = vec![];
+        for (i, line) in text.lines().enumerate() {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            let parse_err = || format!("invalid line {}: \"{line}\"", i + 1);
+            match parts.as_slice() {
+                [] => continue,
+                [fps] if i == 0 => self.fps = fps.parse().with_context(parse_err)?,
+                [frame, button, push, rest @ ..] if rest.len() <= 1 => {
+                    let flag = |s: &str| match s {
+                        "1" | "true" => Ok(true),
+                        "0" | "false" => Ok(false),
+                        _ => Err(anyhow::anyhow!(parse_err())),
+                    };
+                    let button = Button::try_from_u8(button.parse().with_context(parse_err)?)
+                        .with_context(parse_err)?;
+                    let player2 = rest.first().map_or(Ok(false), |p2| flag(p2))?;
+                    actions.push((
+                        frame.parse().with_context(parse_err)?,
+                        button,
+                        player2,
+                        flag(push)?,
+                    ));
+                }
+                _ => anyhow::bail!(parse_err()),
+            }
+        }
+        Ok(actions)
+    }
+}
@@ -0,0 +1,117 @@
+//! Synthesis of release sounds from clicks, for clickpacks that only have clicks.
+
+use crate::{
+    audio::{self, LowPass},
+    clickpack::{Loudness, SoundWrapper},
+};
+use gfmod::FMOD_SYSTEM;
+use kittyaudio::{Frame, Sound};
+use serde::{Deserialize, Serialize};
+
+/// Seconds faded in and out at the ends of a generated release, so it doesn't pop.
+const EDGE_FADE: f64 = 0.002;
+
+/// How releases are made from clicks.
+///
+/// The tail of the click (everything after its loudest point) is reversed, so it swells
+/// into the release instead of decaying, then band-pass filtered, pitched and made
+/// quieter, since releases are usually lighter than clicks.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct ReleaseSynth {
+    /// Generate releases for players that have clicks but no releases
+    pub enabled: bool,
+    /// Seconds of the click tail used for the release
+    pub tail: f64,
+    /// Frequencies below this are cut, in Hz
+    pub low_cut: f64,
+    /// Frequencies above this are cut, in Hz
+    pub high_cut: f64,
+    /// Playback speed of the release, above 1 makes it higher and shorter
+    pub pitch: f64,
+    /// Volume of the release relative to the click
+    pub volume: f64,
+}
+
+impl Default for ReleaseSynth {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            tail: 0.06,
+            low_cut: 400.0,
+            high_cut: 8000.0,
+            pitch: 1.15,
+            volume: 0.6,
+        }
+    }
+}
+
+/// Makes release frames from the frames of a click.
+pub fn synthesize_release(
+    click: &[Frame],
+    sample_rate: u32,
+    settings: &ReleaseSynth,
+) -> Vec<Frame> {
+    let level = |f: &Frame| f.left.abs().max(f.right.abs());
+    let Some(peak) = click
+        .iter()
+        .enumerate()
+        .max_by(|a, b| level(a.1).total_cmp(&level(b.1)))
+        .map(|(i, _)| i)
+    else {
+        return vec![];
+    };
+    let len = ((settings.tail.max(0.0) * sample_rate as f64) as usize).max(1);
+    let tail = &click[peak..(peak + len).min(click.len())];
+
+    // band-pass: a high-pass made from a low-pass, followed by a low-pass
+    let mut low = LowPass::with_cutoff(settings.low_cut.max(1.0), sample_rate);
+    let mut high = LowPass::with_cutoff(settings.high_cut.max(1.0), sample_rate);
+    let filtered: Vec<Frame> = tail
+        .iter()
+        .rev()
+        .map(|&frame| {
+            let lows = low.process(frame);
+            high.process(Frame {
+                left: frame.left - lows.left,
+                right: frame.right - lows.right,
+            })
+        })
+        .collect();
+
+    // playing the frames at a higher rate raises the pitch
+    let pitched_rate = (sample_rate as f64 * settings.pitch.max(0.01)).round() as u32;
+    let mut frames = audio::resample(&filtered, pitched_rate, sample_rate);
+
+    let fade = ((EDGE_FADE * sample_rate as f64) as usize).clamp(1, frames.len().max(1));
+    let len = frames.len();
+    for (i, frame) in frames.iter_mut().enumerate() {
+        let edge = i.min(len - 1 - i);
+        let gain = settings.volume as f32 * (edge as f32 / fade as f32).min(1.0);
+        frame.left *= gain;
+        frame.right *= gain;
+    }
+    frames
+}
+
+/// Makes a release sound from a click, see [`synthesize_release`]. It keeps the weight
+/// and the metadata of the click.
+pub fn release_from_click(
+    click: &SoundWrapper,
+    system: *mut FMOD_SYSTEM,
+    settings: &ReleaseSynth,
+) -> SoundWrapper {
+    let sample_rate = click.sample_rate();
+    let frames = synthesize_release(&click.frames, sample_rate, settings);
+    let loudness = Loudness::measure(&frames, sample_rate);
+    SoundWrapper {
+        weight: click.weight,
+        meta: click.meta.clone(),
+        ..SoundWrapper::from_processed(
+            system,
+            Sound::from_frames(sample_rate, &frames),
+            loudness,
+            (0.0, 0.0),
+        )
+    }
+}
@@ -1,3 +1,4 @@
+use fastrand::Rng;
 use std::ops::RangeInclusive;
 
 pub fn capitalize_first_letter(s: &str) -> String {
@@ -9,6 +10,6 @@ pub fn capitalize_first_letter(s: &str) -> String {
 }
 
 #[inline]
-pub fn f64_range(range: RangeInclusive<f64>) -> f64 {
-    fastrand::f64() * (range.end() - range.start()) + range.start()
+pub fn f64_range(rng: &mut Rng, range: RangeInclusive<f64>) -> f64 {
+    rng.f64() * (range.end() - range.start()) + range.start()
 }
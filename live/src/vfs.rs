@@ -0,0 +1,262 @@
+//! File trees that clickpacks can be loaded from: directories and zip archives.
+
+use crate::{
+    cache::FileId,
+    clickpack::{SampleSettings, SoundWrapper},
+};
+use anyhow::Result;
+use gfmod::FMOD_SYSTEM;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::Read,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// A file that can still be read after the [`ClickpackFs`] it came from is gone.
+pub enum SoundFile {
+    Path(PathBuf),
+    /// Contents of a file from an archive
+    Bytes(Arc<[u8]>),
+}
+
+pub trait ClickpackFs {
+    /// Paths of the directories inside `dir`.
+    fn dirs(&self, dir: &Path) -> Vec<PathBuf>;
+
+    /// Paths of the files inside `dir`.
+    fn files(&self, dir: &Path) -> Vec<PathBuf>;
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>>;
+
+    /// Identifies the current contents of a file for the sound cache, `None` if the
+    /// file can't be cached.
+    fn file_id(&self, path: &Path) -> Option<FileId>;
+
+    /// Keeps a file around so its sound can be decoded again later.
+    fn keep(&self, path: &Path) -> Result<SoundFile>;
+
+    /// Whether [`ClickpackFs::keep`] copies the whole file into memory.
+    fn keep_copies(&self) -> bool {
+        false
+    }
+
+    fn load_sound(
+        &self,
+        path: &Path,
+        system: *mut FMOD_SYSTEM,
+        settings: &SampleSettings,
+    ) -> Result<SoundWrapper>;
+}
+
+/// The regular filesystem.
+pub struct DirFs;
+
+impl DirFs {
+    fn entries(dir: &Path, want_dirs: bool) -> Vec<PathBuf> {
+        let Ok(entries) = dir.read_dir() else {
+            // log::warn!("can't find directory {dir:?}, skipping");
+            return vec![];
+        };
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|entry| {
+                entry
+                    .map_err(|e| log::warn!("error in directory entry: {e}"))
+                    .ok()
+            })
+            .map(|entry| entry.path())
+            .filter(|path| path.is_dir() == want_dirs)
+            .collect();
+        paths.sort();
+        paths
+    }
+}
+
+impl ClickpackFs for DirFs {
+    fn dirs(&self, dir: &Path) -> Vec<PathBuf> {
+        Self::entries(dir, true)
+    }
+
+    fn files(&self, dir: &Path) -> Vec<PathBuf> {
+        Self::entries(dir, false)
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        Ok(std::fs::read(path)?)
+    }
+
+    fn file_id(&self, path: &Path) -> Option<FileId> {
+        FileId::of(path)
+    }
+
+    fn keep(&self, path: &Path) -> Result<SoundFile> {
+        Ok(SoundFile::Path(path.to_path_buf()))
+    }
+
+    fn load_sound(
+        &self,
+        path: &Path,
+        system: *mut FMOD_SYSTEM,
+        settings: &SampleSettings,
+    ) -> Result<SoundWrapper> {
+        SoundWrapper::from_path(system, path, settings)
+    }
+}
+
+#[inline]
+pub fn is_zip(path: &Path) -> bool {
+    path.is_file()
+        && path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"))
+}
+
+/// Whether two archive paths are the same, ignoring case. Archives made on Windows often
+/// have folders like "Player1" that clickpacks refer to as "player1". Paths are compared
+/// part by part, so the separators don't matter.
+fn same_path(a: &Path, b: &Path) -> bool {
+    let (mut a, mut b) = (a.components(), b.components());
+    loop {
+        match (a.next(), b.next()) {
+            (None, None) => return true,
+            (Some(a), Some(b)) if a.as_os_str().eq_ignore_ascii_case(b.as_os_str()) => {}
+            _ => return false,
+        }
+    }
+}
+
+/// Largest file that is read from an archive. Clicks and noise are far smaller, an entry
+/// this big is a broken archive.
+const MAX_FILE_SIZE: u64 = 256 * 1024 * 1024;
+
+/// A zip archive, fully read into memory. Paths are relative to the archive root and
+/// looked up ignoring case.
+pub struct ZipFs {
+    files: BTreeMap<PathBuf, Vec<u8>>,
+    dirs: BTreeSet<PathBuf>,
+    /// The archive itself, every file inside changes with it
+    archive: Option<FileId>,
+}
+
+impl ZipFs {
+    pub fn open(path: &Path) -> Result<Self> {
+        log::info!("reading zip archive {path:?}");
+        let mut archive = zip::ZipArchive::new(std::fs::File::open(path)?)?;
+        let mut files = BTreeMap::new();
+        let mut dirs = BTreeSet::new();
+        dirs.insert(PathBuf::new());
+
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i)?;
+            let Some(name) = file.enclosed_name() else {
+                log::warn!("skipping zip entry with unsafe path {:?}", file.name());
+                continue;
+            };
+            // names always use "/", rebuild them with the separator of the platform
+            let name: PathBuf = name.components().collect();
+            // macos metadata, not actual sounds
+            if name.starts_with("__MACOSX") {
+                continue;
+            }
+
+            // not all archives have entries for directories, add them from the file paths
+            for parent in name.ancestors().skip(1) {
+                dirs.insert(parent.to_path_buf());
+            }
+            if file.is_dir() {
+                dirs.insert(name);
+                continue;
+            }
+            // the size comes from the archive, don't trust it for the allocation
+            if file.size() > MAX_FILE_SIZE {
+                anyhow::bail!("{name:?} is too large ({} bytes)", file.size());
+            }
+            let mut data = vec![];
+            file.by_ref()
+                .take(MAX_FILE_SIZE + 1)
+                .read_to_end(&mut data)?;
+            if data.len() as u64 > MAX_FILE_SIZE {
+                anyhow::bail!("{name:?} is too large");
+            }
+            files.insert(name, data);
+        }
+
+        log::debug!("zip archive has {} files, {} dirs", files.len(), dirs.len());
+        Ok(Self {
+            files,
+            dirs,
+            archive: FileId::of(path),
+        })
+    }
+
+    /// The directory that contains the clickpack. Many archives wrap everything in a
+    /// single folder, in that case that folder is returned. `is_clickpack_dir` should
+    /// return true for folders that are a part of the clickpack itself (e.g. "player1").
+    pub fn root(&self, is_clickpack_dir: impl Fn(&Path) -> bool) -> PathBuf {
+        let mut root = PathBuf::new();
+        loop {
+            let mut dirs = self.dirs(&root);
+            if !self.files(&root).is_empty() || dirs.len() != 1 || is_clickpack_dir(&dirs[0]) {
+                return root;
+            }
+            root = dirs.pop().unwrap();
+        }
+    }
+
+    fn get(&self, path: &Path) -> Result<&Vec<u8>> {
+        let data = self.files.get(path).or_else(|| {
+            self.files
+                .iter()
+                .find(|(name, _)| same_path(name, path))
+                .map(|(_, data)| data)
+        });
+        data.ok_or_else(|| anyhow::anyhow!("file {path:?} not found in archive"))
+    }
+}
+
+impl ClickpackFs for ZipFs {
+    fn dirs(&self, dir: &Path) -> Vec<PathBuf> {
+        self.dirs
+            .iter()
+            .filter(|path| path.parent().is_some_and(|parent| same_path(parent, dir)))
+            .cloned()
+            .collect()
+    }
+
+    fn files(&self, dir: &Path) -> Vec<PathBuf> {
+        self.files
+            .keys()
+            .filter(|path| path.parent().is_some_and(|parent| same_path(parent, dir)))
+            .cloned()
+            .collect()
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        Ok(self.get(path)?.clone())
+    }
+
+    fn file_id(&self, path: &Path) -> Option<FileId> {
+        let archive = self.archive.as_ref()?;
+        Some(FileId {
+            path: archive.path.join(path),
+            ..archive.clone()
+        })
+    }
+
+    fn keep(&self, path: &Path) -> Result<SoundFile> {
+        Ok(SoundFile::Bytes(self.get(path)?.as_slice().into()))
+    }
+
+    fn keep_copies(&self) -> bool {
+        true
+    }
+
+    fn load_sound(
+        &self,
+        path: &Path,
+        system: *mut FMOD_SYSTEM,
+        settings: &SampleSettings,
+    ) -> Result<SoundWrapper> {
+        SoundWrapper::from_bytes(system, self.read(path)?, settings)
+    }
+}
@@ -0,0 +1,187 @@
+mod sim;
+
+use sim::{id_of, wav_from_frames, TempDir};
+use zcblive::{
+    cache::{FileId, SoundCache},
+    clickpack::{SampleSettings, SoundWrapper},
+};
+
+fn settings() -> SampleSettings {
+    SampleSettings {
+        trim_silence: true,
+        ..Default::default()
+    }
+}
+
+fn load(path: &std::path::Path, settings: &SampleSettings) -> SoundWrapper {
+    SoundWrapper::from_path(std::ptr::null_mut(), path, settings).unwrap()
+}
+
+#[test]
+fn cached_sound_matches_decoded() {
+    let dir = TempDir::new("cache-roundtrip");
+    let mut frames = vec![(0.0, 0.0); 100];
+    frames.extend((0..200).map(|i| (i as f32 / 400.0, -0.25)));
+    let path = dir.0.join("click.wav");
+    std::fs::write(&path, wav_from_frames(44100, &frames)).unwrap();
+
+    let cache = SoundCache::new(dir.0.join("cache"));
+    let id = FileId::of(&path).unwrap();
+    let decoded = load(&path, &settings());
+    cache.store(&id, &settings(), &decoded);
+
+    let cached = cache.load(&id, &settings(), std::ptr::null_mut()).unwrap();
+    assert_eq!(cached.sample_rate(), 44100);
+    assert_eq!(cached.frames.len(), decoded.frames.len());
+    assert!(cached
+        .frames
+        .iter()
+        .zip(decoded.frames.iter())
+        .all(|(a, b)| a.left == b.left && a.right == b.right));
+    assert_eq!(cached.trimmed, decoded.trimmed);
+    assert_eq!(cached.loudness.peak, decoded.loudness.peak);
+    assert_eq!(cached.loudness.rms, decoded.loudness.rms);
+}
+
+#[test]
+fn changed_file_is_not_loaded_from_cache() {
+    let dir = TempDir::new("cache-changed");
+    let path = dir.add_sound("", 1);
+    let cache = SoundCache::new(dir.0.join("cache"));
+    let id = FileId::of(&path).unwrap();
+    cache.store(&id, &settings(), &load(&path, &settings()));
+
+    std::fs::write(&path, wav_from_frames(44100, &[(0.5, 0.5); 10])).unwrap();
+    let new_id = FileId::of(&path).unwrap();
+    assert_ne!(id, new_id);
+    assert!(cache
+        .load(&new_id, &settings(), std::ptr::null_mut())
+        .is_none());
+    // the old entry is still valid for the old contents
+    let cached = cache.load(&id, &settings(), std::ptr::null_mut()).unwrap();
+    assert_eq!(id_of(&cached), 1);
+}
+
+#[test]
+fn different_settings_are_cached_separately() {
+    let dir = TempDir::new("cache-settings");
+    let path = dir.add_sound("", 2);
+    let cache = SoundCache::new(dir.0.join("cache"));
+    let id = FileId::of(&path).unwrap();
+    cache.store(&id, &settings(), &load(&path, &settings()));
+
+    let resampled = SampleSettings {
+        resample: true,
+        ..settings()
+    };
+    assert!(cache.load(&id, &resampled, std::ptr::null_mut()).is_none());
+    // trimming options don't matter if trimming is off
+    let untrimmed = SampleSettings::default();
+    cache.store(&id, &untrimmed, &load(&path, &untrimmed));
+    let other_threshold = SampleSettings {
+        trim_threshold: -10.0,
+        ..Default::default()
+    };
+    assert!(cache
+        .load(&id, &other_threshold, std::ptr::null_mut())
+        .is_some());
+}
+
+#[test]
+fn clear_removes_entries() {
+    let dir = TempDir::new("cache-clear");
+    let path = dir.add_sound("", 3);
+    let cache = SoundCache::new(dir.0.join("cache"));
+    let id = FileId::of(&path).unwrap();
+    cache.store(&id, &settings(), &load(&path, &settings()));
+
+    // files that aren't cache entries are left alone
+    std::fs::write(dir.0.join("cache/notes.txt"), "hi").unwrap();
+    std::fs::create_dir(dir.0.join("cache/sub")).unwrap();
+
+    assert!(cache.clear().unwrap() > 0);
+    assert!(cache.load(&id, &settings(), std::ptr::null_mut()).is_none());
+    assert_eq!(cache.clear().unwrap(), 0);
+    assert!(dir.0.join("cache/notes.txt").exists());
+    assert!(dir.0.join("cache/sub").is_dir());
+}
+
+#[test]
+fn prune_removes_stale_entries() {
+    let dir = TempDir::new("cache-prune-stale");
+    let kept = dir.add_sound("", 1);
+    let changed = dir.add_sound("", 2);
+    let removed = dir.add_sound("", 3);
+    let cache = SoundCache::new(dir.0.join("cache"));
+    let ids: Vec<_> = [&kept, &changed, &removed]
+        .map(|path| FileId::of(path).unwrap())
+        .to_vec();
+    for (id, path) in ids.iter().zip([&kept, &changed, &removed]) {
+        cache.store(id, &settings(), &load(path, &settings()));
+    }
+
+    std::fs::write(&changed, wav_from_frames(44100, &[(0.5, 0.5); 10])).unwrap();
+    std::fs::remove_file(&removed).unwrap();
+    assert!(cache.prune().unwrap() > 0);
+    assert!(cache
+        .load(&ids[0], &settings(), std::ptr::null_mut())
+        .is_some());
+    assert!(cache
+        .load(&ids[1], &settings(), std::ptr::null_mut())
+        .is_none());
+    assert!(cache
+        .load(&ids[2], &settings(), std::ptr::null_mut())
+        .is_none());
+    assert_eq!(cache.prune().unwrap(), 0);
+}
+
+#[test]
+fn prune_limits_the_cache_size() {
+    let dir = TempDir::new("cache-prune-size");
+    let old = dir.add_sound("", 1);
+    let new = dir.add_sound("", 2);
+    let (old_id, new_id) = (FileId::of(&old).unwrap(), FileId::of(&new).unwrap());
+    let unlimited = SoundCache::new(dir.0.join("cache"));
+    unlimited.store(&old_id, &settings(), &load(&old, &settings()));
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    unlimited.store(&new_id, &settings(), &load(&new, &settings()));
+    assert_eq!(unlimited.prune().unwrap(), 0);
+
+    // only room for one entry, the oldest one goes
+    let size = std::fs::read_dir(dir.0.join("cache"))
+        .unwrap()
+        .map(|e| e.unwrap().metadata().unwrap().len())
+        .max()
+        .unwrap();
+    let cache = SoundCache::new(dir.0.join("cache")).with_max_size(size);
+    assert!(cache.prune().unwrap() > 0);
+    assert!(cache
+        .load(&old_id, &settings(), std::ptr::null_mut())
+        .is_none());
+    assert!(cache
+        .load(&new_id, &settings(), std::ptr::null_mut())
+        .is_some());
+}
+
+#[test]
+fn corrupt_frame_count_is_a_miss() {
+    let dir = TempDir::new("cache-corrupt");
+    let path = dir.add_sound("", 4);
+    let cache = SoundCache::new(dir.0.join("cache"));
+    let id = FileId::of(&path).unwrap();
+    let sound = load(&path, &settings());
+    cache.store(&id, &settings(), &sound);
+
+    // a frame count that overflows when converted to bytes
+    let entry = std::fs::read_dir(dir.0.join("cache"))
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap()
+        .path();
+    let mut data = std::fs::read(&entry).unwrap();
+    let offset = data.len() - sound.frames.len() * 8 - 8;
+    data[offset..offset + 8].copy_from_slice(&(u64::MAX / 4).to_le_bytes());
+    std::fs::write(&entry, data).unwrap();
+    assert!(cache.load(&id, &settings(), std::ptr::null_mut()).is_none());
+}
@@ -0,0 +1,694 @@
+mod sim;
+
+use sim::{clickpack, simple_clickpack, sound_id, static_config, Sim, ALL_TYPES};
+use zcblive::{
+    bot::{Config, ProfileKind},
+    clickpack::{
+        Button, ClickType, ClickpackManifest, Curve, Fallback, SampleMeta, SelectionStrategy,
+        Timings, VolumeSettings,
+    },
+};
+
+fn assert_close(a: f64, b: f64) {
+    assert!((a - b).abs() < 1e-9, "{a} != {b}");
+}
+
+#[test]
+fn classifies_by_time_since_previous_action() {
+    let mut sim = Sim::new(static_config(), simple_clickpack(), 0);
+    let expected = [
+        (10.0, true, ClickType::HardClick),
+        (10.5, false, ClickType::Release),
+        (10.6, true, ClickType::SoftClick),
+        (10.61, false, ClickType::MicroRelease),
+        (13.0, true, ClickType::HardClick),
+        (15.5, false, ClickType::HardRelease),
+        (15.7, true, ClickType::Click),
+        (15.71, false, ClickType::MicroRelease),
+        (15.72, true, ClickType::MicroClick),
+        (15.8, false, ClickType::SoftRelease),
+    ];
+    for (time, push, typ) in expected {
+        let played = sim.jump(time, push).unwrap();
+        assert_eq!(played.typ, typ, "at {time}");
+        assert_eq!(played.sound, sound_id(0, typ, 0), "at {time}");
+        assert_close(played.pitch, 1.0);
+    }
+}
+
+#[test]
+fn ignores_repeated_pushes_and_releases() {
+    let mut sim = Sim::new(static_config(), simple_clickpack(), 0);
+    assert_eq!(sim.jump(10.0, false), None, "release without a push");
+    assert!(sim.jump(10.1, true).is_some());
+    assert_eq!(sim.jump(10.2, true), None, "push while held");
+    assert!(sim.jump(10.3, false).is_some());
+    assert_eq!(sim.jump(10.4, false), None, "release while released");
+    assert_eq!(sim.num_played(), 2);
+}
+
+#[test]
+fn falls_back_to_available_click_types() {
+    let pack = clickpack(&[0], &[ClickType::Click, ClickType::Release], 1);
+    let mut sim = Sim::new(static_config(), pack, 0);
+
+    let played = sim.jump(10.0, true).unwrap();
+    assert_eq!(played.typ, ClickType::HardClick);
+    assert_eq!(played.sound, sound_id(0, ClickType::Click, 0));
+
+    let played = sim.jump(10.01, false).unwrap();
+    assert_eq!(played.typ, ClickType::MicroRelease);
+    assert_eq!(played.sound, sound_id(0, ClickType::Release, 0));
+}
+
+#[test]
+fn players_have_separate_state_and_sounds() {
+    let pack = clickpack(&[0, 1], &ALL_TYPES, 1);
+    let mut sim = Sim::new(static_config(), pack, 0);
+    let played = sim.run(&[
+        (10.0, Button::Jump, false, true),
+        (10.05, Button::Jump, true, true),
+        (10.1, Button::Jump, false, false),
+        (10.3, Button::Jump, true, false),
+    ]);
+    let got: Vec<_> = played.iter().map(|p| (p.typ, p.sound)).collect();
+    assert_eq!(
+        got,
+        [
+            (ClickType::HardClick, sound_id(0, ClickType::HardClick, 0)),
+            (ClickType::HardClick, sound_id(1, ClickType::HardClick, 0)),
+            (
+                ClickType::SoftRelease,
+                sound_id(0, ClickType::SoftRelease, 0)
+            ),
+            (ClickType::Release, sound_id(1, ClickType::Release, 0)),
+        ]
+    );
+}
+
+#[test]
+fn player2_uses_player1_sounds_if_missing() {
+    let mut sim = Sim::new(static_config(), simple_clickpack(), 0);
+    let played = sim.action(10.0, Button::Jump, true, true).unwrap();
+    assert_eq!(played.sound, sound_id(0, ClickType::HardClick, 0));
+}
+
+#[test]
+fn platformer_needs_platformer_sounds_or_force() {
+    let mut sim = Sim::new(static_config(), simple_clickpack(), 0);
+    assert_eq!(sim.action(10.0, Button::Left, false, true), None);
+
+    let mut conf = static_config();
+    conf.force_playing_platformer = true;
+    let mut sim = Sim::new(conf, simple_clickpack(), 0);
+    let played = sim.action(10.0, Button::Left, false, true).unwrap();
+    assert_eq!(played.sound, sound_id(0, ClickType::HardClick, 0));
+}
+
+#[test]
+fn platformer_buttons_use_their_own_sounds() {
+    let mut conf = static_config();
+    conf.decouple_platformer = true;
+    let pack = clickpack(&[0, 2, 3], &ALL_TYPES, 1);
+    let mut sim = Sim::new(conf, pack, 0);
+
+    let left = sim.action(10.0, Button::Left, false, true).unwrap();
+    assert_eq!(left.sound, sound_id(2, ClickType::HardClick, 0));
+    let right = sim.action(10.1, Button::Right, false, true).unwrap();
+    assert_eq!(right.sound, sound_id(3, ClickType::HardClick, 0));
+    let jump = sim.jump(10.2, true).unwrap();
+    assert_eq!(jump.sound, sound_id(0, ClickType::HardClick, 0));
+}
+
+#[test]
+fn coupled_platformer_buttons_share_state() {
+    let pack = clickpack(&[0, 2, 3], &ALL_TYPES, 1);
+    let mut sim = Sim::new(static_config(), pack, 0);
+
+    assert!(sim.action(10.0, Button::Left, false, true).is_some());
+    // right counts as the same button as left, which is already held
+    assert_eq!(sim.action(10.1, Button::Right, false, true), None);
+    let played = sim.action(10.2, Button::Right, false, false).unwrap();
+    assert_eq!(played.typ, ClickType::Release);
+    assert_eq!(played.sound, sound_id(3, ClickType::Release, 0));
+}
+
+#[test]
+fn decoupled_platformer_buttons_have_separate_state() {
+    let mut conf = static_config();
+    conf.decouple_platformer = true;
+    let pack = clickpack(&[0, 2, 3], &ALL_TYPES, 1);
+    let mut sim = Sim::new(conf, pack, 0);
+
+    assert!(sim.action(10.0, Button::Left, false, true).is_some());
+    let played = sim.action(10.1, Button::Right, false, true).unwrap();
+    assert_eq!(played.typ, ClickType::HardClick);
+}
+
+#[test]
+fn ignored_click_types_are_not_played() {
+    let mut conf = static_config();
+    conf.ignored_click_types.microreleases = true;
+    let mut sim = Sim::new(conf, simple_clickpack(), 0);
+    assert!(sim.jump(10.0, true).is_some());
+    assert_eq!(sim.jump(10.01, false), None);
+
+    let mut conf = static_config();
+    conf.ignored_click_types.hardclicks = true;
+    let mut sim = Sim::new(conf, simple_clickpack(), 0);
+    assert_eq!(sim.jump(10.0, true), None);
+    // the push was ignored, so this is the first push
+    let played = sim.jump(12.5, true);
+    assert_eq!(played, None, "still a hardclick");
+    assert_eq!(sim.num_played(), 0);
+}
+
+#[test]
+fn spam_lowers_volume() {
+    let mut conf = static_config();
+    conf.sound.volume_settings.global_volume = 2.0;
+    let mut sim = Sim::new(conf, simple_clickpack(), 0);
+
+    assert_close(sim.jump(10.0, true).unwrap().volume, 2.0);
+    // releases keep their volume unless `change_releases_volume` is set
+    assert_close(sim.jump(10.05, false).unwrap().volume, 2.0);
+    // (spam_time - dt) * spam_vol_offset_factor = (0.3 - 0.05) * 1.3
+    assert_close(sim.jump(10.1, true).unwrap().volume, (1.0 - 0.325) * 2.0);
+    assert_close(sim.bot.click_state.prev_spam_offset, 0.325);
+}
+
+#[test]
+fn spam_volume_offset_is_capped() {
+    let mut conf = static_config();
+    conf.sound.volume_settings.spam_vol_offset_factor = 10.0;
+    conf.sound.volume_settings.change_releases_volume = true;
+    let mut sim = Sim::new(conf, simple_clickpack(), 0);
+
+    sim.jump(10.0, true).unwrap();
+    let played = sim.jump(10.05, false).unwrap();
+    assert_close(played.volume, 1.0 - 0.6);
+}
+
+#[test]
+fn default_spam_curve_matches_linear_offset() {
+    let mut conf = static_config();
+    conf.sound.volume_settings.spam_curves = true;
+    let mut sim = Sim::new(conf, simple_clickpack(), 0);
+    sim.jump(10.0, true).unwrap();
+    sim.jump(10.05, false).unwrap();
+    assert_close(sim.jump(10.1, true).unwrap().volume, 1.0 - 0.325);
+}
+
+#[test]
+fn spam_curves_set_volume_and_pitch() {
+    let mut conf = static_config();
+    let vol = &mut conf.sound.volume_settings;
+    vol.spam_curves = true;
+    vol.spam_volume_curve = Curve::new(&[(0.05, 0.5), (0.15, 0.8), (0.25, 1.0)]);
+    vol.spam_pitch_curve = Curve::new(&[(0.0, 1.1), (0.2, 1.0)]);
+    let mut sim = Sim::new(conf, simple_clickpack(), 0);
+
+    let played = sim.jump(10.0, true).unwrap();
+    assert_close(played.volume, 1.0);
+    assert_close(played.pitch, 1.0);
+    sim.jump(10.05, false).unwrap();
+    let played = sim.jump(10.15, true).unwrap();
+    assert_close(played.volume, 0.65);
+    assert_close(played.pitch, 1.05);
+    sim.jump(10.16, false).unwrap();
+    // flat before the first point
+    assert_close(sim.jump(10.17, true).unwrap().volume, 0.5);
+}
+
+#[test]
+fn platformer_volume_factor() {
+    let mut conf = static_config();
+    conf.force_playing_platformer = true;
+    conf.sound.volume_settings.platformer_volume_factor = 0.5;
+    let mut sim = Sim::new(conf, simple_clickpack(), 0);
+    assert_close(
+        sim.action(10.0, Button::Right, false, true).unwrap().volume,
+        0.5,
+    );
+    assert_close(sim.jump(10.0, true).unwrap().volume, 1.0);
+}
+
+#[test]
+fn player2_profile_has_own_settings() {
+    let mut conf = static_config();
+    conf.set_profile_enabled(ProfileKind::Player2Jump, true);
+    let p2 = conf.profile_mut(ProfileKind::Player2Jump);
+    p2.timings.hard = 0.2;
+    p2.volume_settings.global_volume = 0.5;
+    let mut sim = Sim::new(conf, clickpack(&[0, 1], &ALL_TYPES, 1), 0);
+    let played = sim.run(&[
+        (10.0, Button::Jump, false, true),
+        (10.0, Button::Jump, true, true),
+        (10.3, Button::Jump, false, false),
+        (10.3, Button::Jump, true, false),
+    ]);
+    let got: Vec<_> = played.iter().map(|p| (p.typ, p.volume)).collect();
+    assert_eq!(
+        got,
+        [
+            (ClickType::HardClick, 1.0),
+            (ClickType::HardClick, 0.5),
+            (ClickType::Release, 1.0),
+            (ClickType::HardRelease, 0.5),
+        ]
+    );
+}
+
+#[test]
+fn profiles_fall_back_to_related_profile() {
+    let mut conf = static_config();
+    conf.force_playing_platformer = true;
+    conf.set_profile_enabled(ProfileKind::Player1Platformer, true);
+    conf.profile_mut(ProfileKind::Player2Platformer)
+        .volume_settings
+        .global_volume = 0.5;
+    assert!(conf.profiles.platformer_player2.is_none());
+
+    let mut sim = Sim::new(conf, simple_clickpack(), 0);
+    let volume = |sim: &mut Sim, time, button, player2| {
+        sim.action(time, button, player2, true).unwrap().volume
+    };
+    assert_close(volume(&mut sim, 10.0, Button::Left, true), 0.5);
+    assert_close(volume(&mut sim, 10.0, Button::Right, false), 0.5);
+    assert_close(volume(&mut sim, 10.0, Button::Jump, true), 1.0);
+}
+
+#[test]
+fn manifest_settings_are_used_on_top_of_config() {
+    let mut conf = static_config();
+    conf.manifest = Some(ClickpackManifest {
+        volume_settings: Some(VolumeSettings {
+            global_volume: 0.5,
+            ..static_config().sound.volume_settings
+        }),
+        ..Default::default()
+    });
+    conf.set_profile_enabled(ProfileKind::Player2Jump, true);
+    conf.profile_mut(ProfileKind::Player2Jump)
+        .volume_settings
+        .global_volume = 0.25;
+    let settings = conf.sound_settings(ProfileKind::Player1Jump);
+    assert_eq!(settings.volume_settings.global_volume, 0.5);
+    // the user's settings are left alone
+    assert_eq!(conf.sound.volume_settings.global_volume, 1.0);
+
+    let mut sim = Sim::new(conf.clone(), clickpack(&[0, 1], &ALL_TYPES, 1), 0);
+    assert_close(sim.jump(10.0, true).unwrap().volume, 0.5);
+    // profiles with their own settings keep them
+    let played = sim.action(10.0, Button::Jump, true, true).unwrap();
+    assert_close(played.volume, 0.25);
+
+    conf.use_clickpack_defaults.volume_settings = false;
+    let mut sim = Sim::new(conf, clickpack(&[0, 1], &ALL_TYPES, 1), 0);
+    assert_close(sim.jump(10.0, true).unwrap().volume, 1.0);
+}
+
+#[test]
+fn clicks_are_centered_by_default() {
+    let mut sim = Sim::new(static_config(), clickpack(&[0, 1], &ALL_TYPES, 1), 0);
+    assert_eq!(sim.jump(10.0, true).unwrap().pan, 0.0);
+    assert_eq!(sim.action(10.0, Button::Jump, true, true).unwrap().pan, 0.0);
+}
+
+#[test]
+fn panning_by_player_and_button() {
+    let mut conf = static_config();
+    conf.force_playing_platformer = true;
+    conf.panning.enabled = true;
+    conf.panning.jitter = 0.0;
+    conf.panning.player2 = 0.8;
+    let mut sim = Sim::new(conf, clickpack(&[0, 1], &ALL_TYPES, 1), 0);
+    let mut time = 10.0;
+    let mut pan = |button, player2| {
+        time += 1.0;
+        let pan = sim.action(time, button, player2, true).unwrap().pan;
+        sim.action(time + 0.5, button, player2, false);
+        pan
+    };
+    assert_close(pan(Button::Jump, false), -0.3);
+    assert_close(pan(Button::Jump, true), 0.8);
+    assert_close(pan(Button::Left, false), -0.6);
+    assert_close(pan(Button::Right, false), 0.0);
+    // clamped to the right edge
+    assert_close(pan(Button::Right, true), 1.0);
+}
+
+#[test]
+fn pan_jitter() {
+    let mut conf = static_config();
+    conf.panning.enabled = true;
+    conf.panning.player1 = 0.0;
+    conf.panning.jitter = 0.1;
+    let mut sim = Sim::new(conf, simple_clickpack(), 0);
+    let pans: Vec<_> = (0..20)
+        .map(|i| sim.jump(10.0 + i as f64, i % 2 == 0).unwrap().pan)
+        .collect();
+    assert!(pans.iter().all(|pan| pan.abs() <= 0.1), "{pans:?}");
+    assert!(pans.iter().any(|&pan| pan != pans[0]), "{pans:?}");
+}
+
+fn click_rate_config() -> Config {
+    let mut conf = static_config();
+    conf.sound.volume_settings.enabled = false;
+    let rate = &mut conf.click_rate;
+    rate.enabled = true;
+    rate.window = 0.1;
+    rate.min_rate = 5.0;
+    rate.max_rate = 10.0;
+    rate.pitch = 1.1;
+    rate.volume = 0.5;
+    rate.lowpass = 0.4;
+    conf
+}
+
+#[test]
+fn click_rate_changes_sustained_spam() {
+    let mut sim = Sim::new(click_rate_config(), simple_clickpack(), 0);
+    let played = sim.jump(10.0, true).unwrap();
+    assert_eq!(
+        (played.pitch, played.volume, played.lowpass),
+        (1.0, 1.0, 0.0)
+    );
+
+    // 20 clicks per second
+    let mut played = vec![];
+    for i in 0..20 {
+        let time = 10.5 + i as f64 * 0.05;
+        sim.jump(time - 0.025, false).unwrap();
+        played.push(sim.jump(time, true).unwrap());
+    }
+    let first = &played[0];
+    assert!(first.lowpass < 0.4, "{first:?}");
+    let last = played.last().unwrap();
+    assert_close(last.pitch, 1.1);
+    assert_close(last.volume, 0.5);
+    assert_close(last.lowpass, 0.4);
+    // releases keep the rate of their click
+    assert_close(sim.jump(12.0, false).unwrap().lowpass, 0.4);
+
+    // an isolated click resets the rate
+    let played = sim.jump(13.0, true).unwrap();
+    assert_eq!(
+        (played.pitch, played.volume, played.lowpass),
+        (1.0, 1.0, 0.0)
+    );
+}
+
+#[test]
+fn click_rate_is_tracked_per_player() {
+    let mut sim = Sim::new(click_rate_config(), clickpack(&[0, 1], &ALL_TYPES, 1), 0);
+    for i in 0..20 {
+        let time = 10.0 + i as f64 * 0.05;
+        sim.jump(time, true).unwrap();
+        sim.jump(time + 0.025, false).unwrap();
+    }
+    let played = sim.action(11.0, Button::Jump, true, true).unwrap();
+    assert_eq!(played.lowpass, 0.0);
+}
+
+#[test]
+fn cut_sounds() {
+    let mut conf = static_config();
+    conf.cut_sounds = true;
+    let mut sim = Sim::new(conf.clone(), simple_clickpack(), 0);
+    sim.jump(10.0, true).unwrap();
+    sim.jump(10.1, false).unwrap();
+    assert_eq!(sim.cuts(), 1, "releases don't cut by default");
+
+    conf.cut_by_releases = true;
+    let mut sim = Sim::new(conf, simple_clickpack(), 0);
+    sim.jump(10.0, true).unwrap();
+    sim.jump(10.1, false).unwrap();
+    assert_eq!(sim.cuts(), 2);
+}
+
+#[test]
+fn same_seed_gives_same_clicks() {
+    let script: Vec<_> = (0..200)
+        .map(|i| (10.0 + i as f64 * 0.07, Button::Jump, i % 4 >= 2, i % 2 == 0))
+        .collect();
+    let run = |seed| {
+        let pack = clickpack(&[0, 1], &ALL_TYPES, 5);
+        Sim::new(Default::default(), pack, seed).run(&script)
+    };
+
+    let a = run(1);
+    assert_eq!(a.len(), script.len());
+    assert_eq!(a, run(1));
+    assert_ne!(a, run(2));
+    for played in &a {
+        assert!((0.98..=1.02).contains(&played.pitch), "{}", played.pitch);
+    }
+}
+
+#[test]
+fn releases_held_buttons_on_death() {
+    let mut conf = static_config();
+    conf.death_release_delay = 0.0;
+    conf.death_release_delay_offset = 0.0;
+    let mut sim = Sim::new(conf, simple_clickpack(), 0);
+    sim.jump(10.0, true).unwrap();
+    sim.action(10.0, Button::Jump, true, true).unwrap();
+    sim.bot.time_override = Some(10.5);
+    unsafe { sim.bot.on_death() };
+    assert_eq!(sim.num_played(), 4);
+
+    // nothing is held anymore
+    unsafe { sim.bot.on_death() };
+    assert_eq!(sim.num_played(), 4);
+}
+
+#[test]
+fn delayed_release_on_death() {
+    let mut sim = Sim::new(static_config(), simple_clickpack(), 0);
+    sim.jump(10.0, true).unwrap();
+    sim.bot.time_override = Some(10.5);
+    unsafe {
+        sim.bot.on_death();
+        sim.bot.on_update(0.0005);
+        assert_eq!(sim.num_played(), 1, "released before the delay");
+        sim.bot.on_update(0.2);
+    }
+    assert_eq!(sim.num_played(), 2);
+}
+
+#[test]
+fn reset_cancels_release_on_death() {
+    let mut sim = Sim::new(static_config(), simple_clickpack(), 0);
+    sim.jump(10.0, true).unwrap();
+    sim.bot.time_override = Some(10.5);
+    unsafe {
+        sim.bot.on_death();
+        sim.bot.on_reset();
+        sim.bot.on_update(1.0);
+    }
+    assert_eq!(sim.num_played(), 1);
+}
+
+#[test]
+fn reseed_every_attempt_repeats_clicks() {
+    let script: Vec<_> = (0..20)
+        .map(|i| (10.0 + i as f64 * 0.1, Button::Jump, false, i % 2 == 0))
+        .collect();
+    let pack = clickpack(&[0], &ALL_TYPES, 5);
+    let mut conf = Config {
+        reseed_every_attempt: true,
+        ..Default::default()
+    };
+    let mut sim = Sim::new(conf.clone(), pack, 3);
+
+    let first = sim.run(&script);
+    sim.bot.on_reset();
+    assert_eq!(first, sim.run(&script));
+
+    // without reseeding, the next attempt continues the random sequence
+    conf.reseed_every_attempt = false;
+    sim.bot.conf = conf;
+    sim.bot.on_reset();
+    assert_ne!(first, sim.run(&script));
+}
+
+/// Sounds played for `count` hardclicks starting at `start` seconds, by player 1 or
+/// player 2 if `player2` is set.
+fn hardclick_sounds(sim: &mut Sim, start: f64, count: usize, player2: bool) -> Vec<u32> {
+    (0..count)
+        .map(|i| {
+            let time = start + i as f64 * 5.0;
+            let played = sim.action(time, Button::Jump, player2, true).unwrap();
+            sim.action(time + 1.0, Button::Jump, player2, false);
+            assert_eq!(played.typ, ClickType::HardClick);
+            played.sound
+        })
+        .collect()
+}
+
+fn selection_config(strategy: SelectionStrategy) -> Config {
+    Config {
+        selection_strategy: strategy,
+        ..static_config()
+    }
+}
+
+#[test]
+fn no_repeat_never_plays_same_sound_twice() {
+    let pack = clickpack(&[0], &ALL_TYPES, 2);
+    let mut sim = Sim::new(selection_config(SelectionStrategy::NoRepeat), pack, 0);
+    let sounds = hardclick_sounds(&mut sim, 10.0, 50, false);
+    assert!(sounds.windows(2).all(|w| w[0] != w[1]), "{sounds:?}");
+}
+
+#[test]
+fn shuffle_bag_plays_every_sound_once_per_round() {
+    let pack = clickpack(&[0], &ALL_TYPES, 5);
+    let mut sim = Sim::new(selection_config(SelectionStrategy::ShuffleBag), pack, 0);
+    let sounds = hardclick_sounds(&mut sim, 10.0, 50, false);
+    for round in sounds.chunks(5) {
+        let mut round = round.to_vec();
+        round.sort();
+        let expected: Vec<_> = (0..5)
+            .map(|n| sound_id(0, ClickType::HardClick, n))
+            .collect();
+        assert_eq!(round, expected);
+    }
+    assert!(sounds.windows(2).all(|w| w[0] != w[1]), "{sounds:?}");
+}
+
+#[test]
+fn weighted_selection_follows_weights() {
+    let mut pack = clickpack(&[0], &ALL_TYPES, 3);
+    pack.player1.sounds_mut(ClickType::HardClick)[0].weight = 0.0;
+    pack.player1.sounds_mut(ClickType::HardClick)[2].weight = 4.0;
+    let mut sim = Sim::new(selection_config(SelectionStrategy::Weighted), pack, 0);
+    let sounds = hardclick_sounds(&mut sim, 10.0, 500, false);
+    let count = |n| {
+        let id = sound_id(0, ClickType::HardClick, n);
+        sounds.iter().filter(|&&s| s == id).count()
+    };
+    assert_eq!(count(0), 0);
+    assert!(count(2) > count(1) * 2, "{} vs {}", count(2), count(1));
+}
+
+#[test]
+fn selection_is_tracked_per_player() {
+    let pack = clickpack(&[0, 1], &ALL_TYPES, 3);
+    let mut sim = Sim::new(selection_config(SelectionStrategy::ShuffleBag), pack, 0);
+    let mut p1 = vec![];
+    for i in 0..3 {
+        let start = 10.0 + i as f64 * 20.0;
+        p1.extend(hardclick_sounds(&mut sim, start, 1, false));
+        // player 2 clicks in between don't use up player 1's bag
+        hardclick_sounds(&mut sim, start + 5.0, 2, true);
+    }
+    p1.sort();
+    let expected: Vec<_> = (0..3)
+        .map(|n| sound_id(0, ClickType::HardClick, n))
+        .collect();
+    assert_eq!(p1, expected);
+}
+
+#[test]
+fn selection_survives_changing_exclusions() {
+    for strategy in [SelectionStrategy::NoRepeat, SelectionStrategy::ShuffleBag] {
+        let mut pack = clickpack(&[0], &ALL_TYPES, 3);
+        pack.player1.sounds_mut(ClickType::HardClick)[0].meta = SampleMeta {
+            tags: vec!["loud".to_string()],
+            ..Default::default()
+        };
+        let mut sim = Sim::new(selection_config(strategy), pack, 0);
+        let mut sounds = vec![];
+        for i in 0..60 {
+            let exclude = i % 3 == 0;
+            sim.bot.conf.excluded_sample_tags = if exclude {
+                vec!["loud".to_string()]
+            } else {
+                vec![]
+            };
+            let sound = hardclick_sounds(&mut sim, 10.0 + i as f64 * 5.0, 1, false)[0];
+            assert!(!exclude || sound != sound_id(0, ClickType::HardClick, 0));
+            sounds.push(sound);
+        }
+        assert!(
+            sounds.windows(2).all(|w| w[0] != w[1]),
+            "{strategy:?}: {sounds:?}"
+        );
+    }
+}
+
+#[test]
+fn hold_aware_timings_classify_releases_by_hold_duration() {
+    let mut conf = static_config();
+    conf.hold_aware_timings = true;
+    conf.sound.release_timings = Timings {
+        hard: 0.5,
+        regular: 0.2,
+        soft: 0.05,
+        ..Default::default()
+    };
+    let mut sim = Sim::new(conf, simple_clickpack(), 0);
+    assert_eq!(sim.jump(10.0, true).unwrap().typ, ClickType::HardClick);
+    // held for 0.6s, which is only a regular release with the click timings
+    assert_eq!(sim.jump(10.6, false).unwrap().typ, ClickType::HardRelease);
+    // clicks still use the gap since the release
+    assert_eq!(sim.jump(10.7, true).unwrap().typ, ClickType::SoftClick);
+    assert_eq!(sim.jump(10.8, false).unwrap().typ, ClickType::SoftRelease);
+}
+
+#[test]
+fn click_gap_from_previous_click() {
+    let mut conf = static_config();
+    conf.hold_aware_timings = true;
+    conf.click_gap_from_click = true;
+    let mut sim = Sim::new(conf, simple_clickpack(), 0);
+    sim.jump(10.0, true).unwrap();
+    sim.jump(10.5, false).unwrap();
+    // 0.1s after the release, but 0.6s after the previous click
+    assert_eq!(sim.jump(10.6, true).unwrap().typ, ClickType::Click);
+}
+
+#[test]
+fn fallback_nothing_plays_nothing() {
+    let mut conf = static_config();
+    conf.fallbacks.microreleases = Fallback::Nothing;
+    let pack = clickpack(&[0], &[ClickType::Click, ClickType::Release], 1);
+    let mut sim = Sim::new(conf, pack, 0);
+    assert!(sim.jump(10.0, true).is_some());
+    assert_eq!(sim.jump(10.01, false), None);
+    // the release still happened
+    assert!(sim.jump(10.5, true).is_some());
+    assert!(sim.jump(10.6, false).is_some());
+}
+
+#[test]
+fn fallback_custom_order() {
+    let mut conf = static_config();
+    conf.fallbacks.microreleases =
+        Fallback::Order(vec![ClickType::MicroClick, ClickType::HardRelease]);
+    let pack = clickpack(
+        &[0],
+        &[ClickType::Click, ClickType::Release, ClickType::HardRelease],
+        1,
+    );
+    let mut sim = Sim::new(conf, pack, 0);
+    sim.jump(10.0, true).unwrap();
+    let played = sim.jump(10.01, false).unwrap();
+    assert_eq!(played.sound, sound_id(0, ClickType::HardRelease, 0));
+}
+
+#[test]
+fn fallback_synthesize_shifts_nearest_tier() {
+    let mut conf = static_config();
+    conf.fallbacks.microreleases = Fallback::Synthesize;
+    let pack = clickpack(&[0], &[ClickType::Click, ClickType::Release], 1);
+    let mut sim = Sim::new(conf, pack, 0);
+    sim.jump(10.0, true).unwrap();
+    // microreleases are two tiers softer than releases
+    let played = sim.jump(10.01, false).unwrap();
+    assert_eq!(played.sound, sound_id(0, ClickType::Release, 0));
+    assert_close(played.volume, 0.8 * 0.8);
+    assert_close(played.pitch, 1.02 * 1.02);
+}
@@ -0,0 +1,46 @@
+use egui::{vec2, Context, Event, Modifiers, PointerButton, Pos2, RawInput, Rect};
+use zcblive::{bot::curve_edit, clickpack::Curve};
+
+/// Runs a frame with the curve editor, returns the top left corner of the plot.
+fn frame(ctx: &Context, curve: &mut Curve, events: Vec<Event>) -> Pos2 {
+    let mut corner = Pos2::ZERO;
+    let input = RawInput {
+        screen_rect: Some(Rect::from_min_size(Pos2::ZERO, vec2(400.0, 300.0))),
+        events,
+        ..Default::default()
+    };
+    let _ = ctx.run(input, |ctx| {
+        egui::CentralPanel::default().show(ctx, |ui| {
+            corner = ui.cursor().min;
+            curve_edit(ui, curve, 0.0..=1.0, 0.0..=2.0, |x, y| format!("{x} {y}"));
+        });
+    });
+    corner
+}
+
+fn right_click(ctx: &Context, curve: &mut Curve, pos: Pos2) {
+    let button = |pressed| Event::PointerButton {
+        pos,
+        button: PointerButton::Secondary,
+        pressed,
+        modifiers: Modifiers::NONE,
+    };
+    frame(ctx, curve, vec![Event::PointerMoved(pos)]);
+    frame(ctx, curve, vec![button(true)]);
+    frame(ctx, curve, vec![button(false)]);
+    frame(ctx, curve, vec![]);
+}
+
+#[test]
+fn removes_the_last_point() {
+    let ctx = Context::default();
+    // both points are in the top left corner, the one under the pointer is the last one
+    let mut curve = Curve::new(&[(0.0, 2.0), (0.0, 2.0)]);
+    let corner = frame(&ctx, &mut curve, vec![]);
+    right_click(&ctx, &mut curve, corner);
+    assert_eq!(curve.points.len(), 1);
+
+    // and the only one
+    right_click(&ctx, &mut curve, corner);
+    assert!(curve.points.is_empty());
+}
@@ -0,0 +1,80 @@
+mod sim;
+
+use sim::{id_of, load_pack, uncached, write_zip, TempDir};
+use zcblive::clickpack::{ClickType, Clickpack, LoadClickpackFor, SampleSettings};
+
+fn ids(pack: &Clickpack, player: usize, typ: ClickType) -> Vec<u32> {
+    pack[player].sounds(typ).iter().map(id_of).collect()
+}
+
+#[test]
+fn loads_zip_with_wrapper_folder() {
+    let dir = TempDir::new("layout-zip");
+    let path = dir.0.join("pack.zip");
+    write_zip(
+        &path,
+        &[
+            ("My Pack/Player1/Clicks/1.wav", 1),
+            ("My Pack/Player1/Releases/2.wav", 2),
+            ("My Pack/PLAYER2/clicks/3.wav", 3),
+        ],
+    );
+    let pack = load_pack(&path, uncached());
+    assert_eq!(ids(&pack, 0, ClickType::Click), [1]);
+    assert_eq!(ids(&pack, 0, ClickType::Release), [2]);
+    assert_eq!(ids(&pack, 1, ClickType::Click), [3]);
+}
+
+#[test]
+fn zip_with_single_player_folder_keeps_the_wrapper_as_root() {
+    let dir = TempDir::new("layout-zip-single");
+    let path = dir.0.join("pack.zip");
+    write_zip(&path, &[("Pack/Player2/Clicks/3.wav", 3)]);
+    let pack = load_pack(&path, uncached());
+    assert!(pack[0].sounds(ClickType::Click).is_empty());
+    assert_eq!(ids(&pack, 1, ClickType::Click), [3]);
+}
+
+#[test]
+fn loads_aliased_player_folders() {
+    let dir = TempDir::new("layout-aliases");
+    dir.add_sound("player1/clicks", 1);
+    dir.add_sound("P2/clicks", 2);
+    dir.add_sound("P2/loud", 3);
+    std::fs::write(
+        dir.0.join("zcb.json"),
+        r#"{ "aliases": { "player2": ["p2"], "hardclicks": ["loud"] } }"#,
+    )
+    .unwrap();
+    let pack = load_pack(&dir.0, uncached());
+    assert_eq!(ids(&pack, 0, ClickType::Click), [1]);
+    assert_eq!(ids(&pack, 1, ClickType::Click), [2]);
+    assert_eq!(ids(&pack, 1, ClickType::HardClick), [3]);
+}
+
+#[test]
+fn huge_zip_entries_are_an_error() {
+    let dir = TempDir::new("layout-zip-huge");
+    let path = dir.0.join("pack.zip");
+    write_zip(&path, &[("player1/clicks/1.wav", 1)]);
+
+    // claim an uncompressed size of almost 4 GB in both headers
+    let mut data = std::fs::read(&path).unwrap();
+    let local = 22;
+    let central = data.windows(4).position(|w| w == b"PK\x01\x02").unwrap() + 24;
+    for offset in [local, central] {
+        data[offset..offset + 4].copy_from_slice(&0xfff0_0000u32.to_le_bytes());
+    }
+    std::fs::write(&path, data).unwrap();
+
+    let mut pack = Clickpack::default();
+    let err = pack
+        .load_from_path(
+            &path,
+            LoadClickpackFor::All,
+            std::ptr::null_mut(),
+            &SampleSettings::default(),
+        )
+        .unwrap_err();
+    assert!(err.to_string().contains("too large"), "{err}");
+}
@@ -0,0 +1,144 @@
+mod sim;
+
+use sim::{id_of, load_pack, static_config, uncached, wav, write_zip, Sim, TempDir};
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+use zcblive::clickpack::{Button, Clickpack, SampleSettings, SoundKey};
+
+/// Every test sound is 64 stereo frames.
+const SOUND_SIZE: usize = 64 * 8;
+
+fn load(path: &Path, budget: f64) -> Clickpack {
+    let settings = SampleSettings {
+        memory_budget: budget,
+        ..uncached()
+    };
+    load_pack(path, settings)
+}
+
+fn load_with_budget(name: &str, budget: f64) -> (TempDir, Clickpack) {
+    let dir = TempDir::new(name);
+    dir.add_sound("player1/clicks", 1);
+    dir.add_sound("player1/clicks", 2);
+    dir.add_sound("player2/clicks", 1001);
+    let pack = load(&dir.0, budget);
+    (dir, pack)
+}
+
+fn is_loaded(pack: &Clickpack, slot: usize, index: usize) -> bool {
+    pack[slot].tier("").unwrap().clicks[index].is_loaded()
+}
+
+fn key(slot: usize, index: usize) -> SoundKey {
+    SoundKey {
+        slot,
+        tier: 0,
+        release: false,
+        index,
+    }
+}
+
+fn wait_for_reloads(pack: &mut Clickpack) {
+    let start = Instant::now();
+    while pack.is_reloading() {
+        pack.poll_reloads();
+        assert!(start.elapsed() < Duration::from_secs(10), "decoding hangs");
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
+
+#[test]
+fn no_budget_keeps_everything() {
+    let (_dir, pack) = load_with_budget("memory-unlimited", 0.0);
+    assert_eq!(pack.memory_usage(), 3 * SOUND_SIZE);
+    assert_eq!(pack.num_unloaded(), 0);
+}
+
+#[test]
+fn budget_unloads_rarely_used_sounds() {
+    // room for two sounds
+    let (_dir, pack) = load_with_budget("memory-budget", 0.0011);
+    assert_eq!(pack.memory_usage(), 2 * SOUND_SIZE);
+    assert!(is_loaded(&pack, 0, 0) && is_loaded(&pack, 0, 1));
+    assert!(!is_loaded(&pack, 1, 0));
+    // metadata is kept for normalization
+    assert!(pack[1].tier("").unwrap().clicks[0].loudness.peak > -120.0);
+}
+
+#[test]
+fn unloaded_sound_is_decoded_when_played() {
+    let (_dir, pack) = load_with_budget("memory-decode", 0.0011);
+    let mut sim = Sim::new(static_config(), pack, 0);
+    let played = sim.action(10.0, Button::Jump, true, true).unwrap();
+    assert_eq!(played.sound, 1001);
+
+    let pack = &sim.bot.clickpack;
+    assert!(is_loaded(pack, 1, 0));
+    assert_eq!(pack.memory_usage(), 2 * SOUND_SIZE);
+    assert_eq!(pack.num_unloaded(), 1);
+}
+
+#[test]
+fn least_recently_used_sound_is_unloaded() {
+    let (_dir, mut pack) = load_with_budget("memory-lru", 0.0011);
+    pack.use_sound(key(0, 0)).unwrap();
+    pack.use_sound(key(0, 1)).unwrap();
+    pack.use_sound(key(1, 0)).unwrap();
+    assert!(!is_loaded(&pack, 0, 0));
+    assert!(is_loaded(&pack, 0, 1) && is_loaded(&pack, 1, 0));
+
+    pack.use_sound(key(0, 0)).unwrap();
+    assert!(!is_loaded(&pack, 0, 1));
+    assert_eq!(pack.num_unloaded(), 1);
+}
+
+#[test]
+fn background_reload_plays_a_loaded_sound_meanwhile() {
+    let (_dir, mut pack) = load_with_budget("memory-background", 0.0011);
+    pack.background_reloads = true;
+    pack.use_sound(key(0, 1)).unwrap();
+    assert!(pack.use_sound(key(1, 0)).is_err()); // nothing else in the tier
+    wait_for_reloads(&mut pack);
+    assert!(is_loaded(&pack, 1, 0));
+    assert!(!is_loaded(&pack, 0, 0));
+
+    let fallback = id_of(pack.use_sound(key(0, 0)).unwrap());
+    assert_eq!(fallback, id_of(&pack[0].tier("").unwrap().clicks[1]));
+    assert!(!is_loaded(&pack, 0, 0));
+    wait_for_reloads(&mut pack);
+    assert!(is_loaded(&pack, 0, 0));
+    assert_eq!(pack.memory_usage(), 2 * SOUND_SIZE);
+}
+
+#[test]
+fn budget_counts_files_kept_from_archives() {
+    let dir = TempDir::new("memory-zip");
+    let path = dir.0.join("pack.zip");
+    write_zip(
+        &path,
+        &[("player1/clicks/1.wav", 1), ("player1/clicks/2.wav", 2)],
+    );
+    let file_size = wav(1).len();
+
+    // nothing is kept from archives without a budget
+    let pack = load(&path, 0.0);
+    assert_eq!(pack.memory_usage(), 2 * SOUND_SIZE);
+
+    // room for one decoded sound and both files
+    let budget = (SOUND_SIZE + 2 * file_size + 1) as f64 / 1_000_000.0;
+    let pack = load(&path, budget);
+    assert_eq!(pack.num_unloaded(), 1);
+    assert_eq!(pack.memory_usage(), SOUND_SIZE + 2 * file_size);
+}
+
+#[test]
+fn budget_can_be_set_after_loading() {
+    let (_dir, mut pack) = load_with_budget("memory-later", 0.0);
+    pack.memory_budget = Some(2 * SOUND_SIZE);
+    pack.apply_memory_budget();
+    assert_eq!(pack.num_unloaded(), 1);
+    pack.use_sound(key(1, 0)).unwrap();
+    assert_eq!(pack.num_unloaded(), 1);
+}
@@ -0,0 +1,109 @@
+mod sim;
+
+use sim::{clickpack, load_pack, sound_id, static_config, uncached, wav, Sim, TempDir};
+use zcblive::clickpack::{ClickType, Clickpack, SampleMeta};
+
+#[test]
+fn parses_filename_conventions() {
+    let meta = SampleMeta::from_filename("click_w3_+2db");
+    assert_eq!(meta.weight, Some(3.0));
+    assert_eq!(meta.gain, 2.0);
+    assert_eq!(meta.pitch, 0.0);
+
+    let meta = SampleMeta::from_filename("Click 4 -1.5ST w0.5");
+    assert_eq!(meta.weight, Some(0.5));
+    assert_eq!(meta.pitch, -1.5);
+
+    // gain and pitch need a sign, other words are ignored
+    assert_eq!(
+        SampleMeta::from_filename("first_3db_w_2st"),
+        SampleMeta::default()
+    );
+}
+
+#[test]
+fn loads_metadata_from_sidecars_and_filenames() {
+    let dir = TempDir::new("metadata");
+    let clicks = dir.0.join("player1/clicks");
+    std::fs::create_dir_all(&clicks).unwrap();
+    std::fs::write(clicks.join("click_w3_+2db.wav"), wav(1)).unwrap();
+    std::fs::write(clicks.join("click2_w9.wav"), wav(2)).unwrap();
+    // the sidecar wins over the file name
+    std::fs::write(
+        clicks.join("click2_w9.json"),
+        r#"{ "weight": 0.5, "pitch": 12, "tags": ["loud"] }"#,
+    )
+    .unwrap();
+
+    let pack = load_pack(&dir.0, uncached());
+    assert_eq!(pack.num_sounds, 2);
+
+    let mut clicks = pack[0].sounds(ClickType::Click).to_vec();
+    clicks.sort_by(|a, b| a.weight.total_cmp(&b.weight));
+    assert_eq!(clicks[0].weight, 0.5);
+    assert_eq!(clicks[0].meta.pitch, 12.0);
+    assert_eq!(clicks[0].meta.tags, ["loud"]);
+    assert_eq!(clicks[1].weight, 3.0);
+    assert_eq!(clicks[1].meta.gain, 2.0);
+    assert_eq!(pack.sample_tags(), ["loud"]);
+}
+
+fn pack_with_meta(meta: SampleMeta) -> Clickpack {
+    let mut pack = clickpack(&[0], &[ClickType::HardClick, ClickType::Click], 2);
+    pack[0].sounds_mut(ClickType::Click)[0].meta = meta;
+    pack
+}
+
+#[test]
+fn gain_and_pitch_apply_when_playing() {
+    let meta = SampleMeta {
+        gain: 6.0,
+        pitch: 12.0,
+        ..Default::default()
+    };
+    let mut plain = Sim::new(static_config(), pack_with_meta(SampleMeta::default()), 1);
+    let mut sim = Sim::new(static_config(), pack_with_meta(meta), 1);
+    let mut changed = 0;
+    for i in 1..20 {
+        let time = i as f64;
+        let expected = plain.jump(time, true).unwrap();
+        let played = sim.jump(time, true).unwrap();
+        plain.jump(time + 0.1, false);
+        sim.jump(time + 0.1, false);
+
+        assert_eq!(played.sound, expected.sound);
+        if played.sound == sound_id(0, ClickType::Click, 0) {
+            assert!((played.volume / expected.volume - 1.995).abs() < 0.001);
+            assert!((played.pitch / expected.pitch - 2.0).abs() < 1e-9);
+            changed += 1;
+        } else {
+            assert_eq!(played.volume, expected.volume);
+            assert_eq!(played.pitch, expected.pitch);
+        }
+    }
+    assert!(changed > 0);
+}
+
+#[test]
+fn excluded_tags_are_never_picked() {
+    let tagged = SampleMeta {
+        tags: vec!["loud".to_string()],
+        ..Default::default()
+    };
+    let mut conf = static_config();
+    conf.excluded_sample_tags = vec!["loud".to_string()];
+    let mut pack = pack_with_meta(tagged.clone());
+    let mut sim = Sim::new(conf.clone(), pack_with_meta(tagged.clone()), 0);
+    for i in 1..20 {
+        let played = sim.jump(i as f64, true).unwrap();
+        sim.jump(i as f64 + 0.1, false);
+        assert_eq!(played.sound, sound_id(0, ClickType::Click, 1));
+    }
+
+    // a tier where every sample is excluded counts as empty
+    pack[0].sounds_mut(ClickType::Click)[1].meta = tagged;
+    let mut sim = Sim::new(conf, pack, 0);
+    let played = sim.jump(1.0, true).unwrap();
+    assert_eq!(played.typ, ClickType::Click);
+    assert!(played.sound < sound_id(0, ClickType::HardRelease, 0));
+}
@@ -0,0 +1,212 @@
+mod sim;
+
+use kittyaudio::Frame;
+use sim::{load_pack, simple_clickpack, sound, static_config, uncached, wav, Sim, TempDir};
+use zcblive::{
+    audio::crossfade_loop,
+    bot::{Config, NoiseLayerSettings},
+    clickpack::NoiseLayer,
+};
+
+#[test]
+fn crossfaded_loop_has_no_seam() {
+    let frames: Vec<Frame> = (0..1000)
+        .map(|i| Frame {
+            left: i as f32,
+            right: -(i as f32),
+        })
+        .collect();
+    let looped = crossfade_loop(&frames, 100);
+    assert_eq!(looped.len(), 900);
+    // the end of the loop runs into the start, which is now mostly the old end
+    let jump = looped[0].left - looped[899].left;
+    assert!((0.0..1.5).contains(&jump), "jump of {jump}");
+    // only the start is changed
+    assert_eq!(looped[100..], frames[100..900]);
+}
+
+#[test]
+fn loads_all_noise_layers() {
+    let dir = TempDir::new("noise-layers");
+    dir.add_sound("player1/clicks", 1);
+    std::fs::write(dir.0.join("noise.wav"), wav(7)).unwrap();
+    std::fs::create_dir_all(dir.0.join("noise")).unwrap();
+    std::fs::write(dir.0.join("noise/fan.wav"), wav(8)).unwrap();
+    std::fs::write(dir.0.join("noise/hiss.wav"), wav(9)).unwrap();
+
+    let pack = load_pack(&dir.0, uncached());
+    let mut names: Vec<&str> = pack.noise.iter().map(|l| l.name.as_str()).collect();
+    names.sort();
+    assert_eq!(names, ["fan", "hiss", "noise"]);
+    // crossfading is capped at half of the sound
+    assert!(pack.noise.iter().all(|l| l.sound.frames.len() == 32));
+}
+
+fn noise_sim(conf: Config) -> Sim {
+    let mut pack = simple_clickpack();
+    pack.noise = vec![
+        NoiseLayer {
+            name: "fan".to_string(),
+            sound: sound(7),
+        },
+        NoiseLayer {
+            name: "hiss".to_string(),
+            sound: sound(8),
+        },
+    ];
+    Sim::new(conf, pack, 0)
+}
+
+fn noise_config() -> Config {
+    let mut conf = static_config();
+    conf.play_noise = true;
+    conf.noise.random_start = false;
+    conf
+}
+
+#[test]
+fn plays_enabled_layers_with_their_volume() {
+    let mut conf = noise_config();
+    conf.noise_volume = 0.5;
+    conf.noise.layers.insert(
+        "fan".to_string(),
+        NoiseLayerSettings {
+            enabled: true,
+            volume: 0.5,
+        },
+    );
+    conf.noise.layers.insert(
+        "hiss".to_string(),
+        NoiseLayerSettings {
+            enabled: false,
+            volume: 1.0,
+        },
+    );
+    let mut sim = noise_sim(conf);
+    sim.bot.play_noise();
+    assert_eq!(sim.noise(), [(7, 0.25, 0)]);
+    assert_eq!(sim.noise_gain(), 1.0);
+}
+
+#[test]
+fn random_start_is_inside_the_sound() {
+    let mut sim = noise_sim(static_config());
+    sim.bot.conf.play_noise = true;
+    for _ in 0..20 {
+        sim.bot.play_noise();
+        assert!(sim.noise().iter().all(|&(_, _, start)| start < 64));
+    }
+}
+
+#[test]
+fn fades_in_and_out() {
+    let mut conf = noise_config();
+    conf.noise.fade_in = 1.0;
+    conf.noise.fade_out = 0.5;
+    let mut sim = noise_sim(conf);
+    sim.bot.play_noise();
+    assert_eq!(sim.noise_gain(), 0.0);
+    sim.bot.update_noise_fade(0.5);
+    assert_eq!(sim.noise_gain(), 0.5);
+    sim.bot.update_noise_fade(0.6);
+    assert_eq!(sim.noise_gain(), 1.0);
+
+    // turning the noise off fades it out before stopping it
+    sim.bot.conf.play_noise = false;
+    sim.bot.play_noise();
+    assert_eq!(sim.noise().len(), 2);
+    sim.bot.update_noise_fade(0.25);
+    assert_eq!(sim.noise_gain(), 0.5);
+    sim.bot.update_noise_fade(0.3);
+    assert!(sim.noise().is_empty());
+}
+
+#[test]
+fn only_in_levels_follows_the_level() {
+    let mut conf = noise_config();
+    conf.noise.only_in_levels = true;
+    // in a level from the start
+    let mut sim = noise_sim(conf);
+    assert_eq!(sim.noise().len(), 2);
+
+    sim.bot.on_exit();
+    assert!(sim.noise().is_empty());
+    sim.bot.on_level_start(0);
+    assert_eq!(sim.noise().len(), 2);
+    // entering a level while the noise plays doesn't restart it
+    let starts = sim.noise_starts();
+    sim.bot.on_level_start(0);
+    assert_eq!(sim.noise_starts(), starts);
+}
+
+fn assert_gain(sim: &Sim, gain: f64) {
+    let actual = sim.noise_gain();
+    assert!(
+        (actual - gain).abs() < 1e-9,
+        "gain {actual}, expected {gain}"
+    );
+}
+
+#[test]
+fn ducks_in_menus_and_after_dying() {
+    let mut conf = noise_config();
+    conf.noise.menu_volume = 0.25;
+    conf.noise.death_volume = 0.5;
+    conf.noise.ducking_time = 1.0;
+    let mut sim = noise_sim(conf);
+    sim.bot.play_noise();
+    assert_gain(&sim, 1.0);
+
+    unsafe { sim.bot.on_death() };
+    sim.bot.update_noise_fade(0.25);
+    assert_gain(&sim, 0.75);
+    sim.bot.update_noise_fade(1.0);
+    assert_gain(&sim, 0.5);
+    sim.bot.on_reset();
+    sim.bot.update_noise_fade(1.0);
+    assert_gain(&sim, 1.0);
+
+    sim.bot.on_exit();
+    sim.bot.update_noise_fade(1.0);
+    assert_gain(&sim, 0.25);
+    // the noise keeps playing in the menu
+    assert_eq!(sim.noise().len(), 2);
+}
+
+#[test]
+fn fades_in_every_attempt() {
+    let mut conf = noise_config();
+    conf.noise.attempt_fade_in = 2.0;
+    let mut sim = noise_sim(conf);
+    sim.bot.play_noise();
+    sim.bot.update_noise_fade(2.0);
+    assert_gain(&sim, 1.0);
+
+    sim.bot.on_reset();
+    sim.bot.update_noise_fade(0.5);
+    assert_gain(&sim, 0.25);
+    sim.bot.update_noise_fade(1.5);
+    assert_gain(&sim, 1.0);
+    // leaving the level doesn't start a new fade, entering one does
+    sim.bot.on_exit();
+    sim.bot.update_noise_fade(0.5);
+    assert_gain(&sim, 1.0);
+    sim.bot.on_level_start(0);
+    sim.bot.update_noise_fade(0.5);
+    assert_gain(&sim, 0.25);
+}
+
+#[test]
+fn clicks_duck_the_noise() {
+    let mut conf = noise_config();
+    conf.noise.click_duck = 0.5;
+    conf.noise.click_duck_release = 0.1;
+    let mut sim = noise_sim(conf);
+    sim.bot.play_noise();
+    assert!(sim.jump(1.0, true).is_some());
+    assert_gain(&sim, 0.5);
+    sim.bot.update_noise_fade(0.05);
+    assert_gain(&sim, 0.75);
+    sim.bot.update_noise_fade(0.1);
+    assert_gain(&sim, 1.0);
+}
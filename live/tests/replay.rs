@@ -0,0 +1,181 @@
+use std::path::{Path, PathBuf};
+use zcblive::{
+    clickpack::Button,
+    replay::{Replay, ReplayFormat},
+};
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/replays")
+        .join(name)
+}
+
+/// Loads a fixture, checking that its format is guessed correctly.
+fn load(name: &str, format: ReplayFormat) -> Replay {
+    let path = fixture(name);
+    let data = std::fs::read(&path).unwrap();
+    assert_eq!(ReplayFormat::guess(&path, &data), Some(format));
+    Replay::load(&path, None, None).unwrap()
+}
+
+/// `(frame, player2, push)` of every action, for macros that only use the jump button.
+fn inputs(replay: &Replay) -> Vec<(f64, bool, bool)> {
+    replay
+        .actions
+        .iter()
+        .map(|a| {
+            assert_eq!(a.button, Button::Jump);
+            ((a.time * replay.fps).round(), a.player2, a.push)
+        })
+        .collect()
+}
+
+const EXPECTED: [(f64, bool, bool); 3] = [
+    (120.0, false, true),
+    (180.0, false, false),
+    (240.0, true, true),
+];
+
+#[test]
+fn parses_gdr() {
+    let replay = load("macro.gdr.json", ReplayFormat::Gdr);
+    assert_eq!(replay.fps, 240.0);
+    assert_eq!(replay.actions[0].time, 0.5);
+    let buttons: Vec<_> = replay.actions.iter().map(|a| a.button).collect();
+    assert_eq!(buttons, [Button::Jump, Button::Jump, Button::Left]);
+}
+
+#[test]
+fn parses_mhr_json() {
+    // the physics event without "down" is skipped
+    let replay = load("macro.mhr.json", ReplayFormat::MegaHackJson);
+    assert_eq!(replay.fps, 240.0);
+    assert_eq!(inputs(&replay), EXPECTED);
+}
+
+#[test]
+fn parses_tasbot() {
+    let replay = load("macro.tasbot.json", ReplayFormat::TasBot);
+    assert_eq!(replay.fps, 60.0);
+    assert_eq!(
+        inputs(&replay),
+        [
+            (30.0, false, true),
+            (45.0, false, false),
+            (45.0, true, true),
+            (60.0, true, false),
+        ]
+    );
+}
+
+#[test]
+fn parses_echo() {
+    // frames are relative to the starting frame
+    let replay = load("macro.echo", ReplayFormat::Echo);
+    assert_eq!(replay.fps, 240.0);
+    assert_eq!(inputs(&replay), EXPECTED);
+}
+
+#[test]
+fn parses_zbf() {
+    let replay = load("macro.zbf", ReplayFormat::Zbot);
+    assert!((replay.fps - 240.0).abs() < 1e-3);
+    assert_eq!(inputs(&replay), EXPECTED);
+}
+
+#[test]
+fn parses_ybf() {
+    let replay = load("macro.ybf", ReplayFormat::Ybot);
+    assert_eq!(replay.fps, 240.0);
+    assert_eq!(inputs(&replay), EXPECTED);
+}
+
+#[test]
+fn parses_plain() {
+    let replay = load("macro.txt", ReplayFormat::Plain);
+    assert_eq!(replay.fps, 120.0);
+    assert_eq!(replay.actions[0].time, 0.5);
+    let buttons: Vec<_> = replay.actions.iter().map(|a| a.button).collect();
+    assert_eq!(buttons, [Button::Jump, Button::Jump, Button::Left]);
+    assert!(replay.actions[2].player2);
+
+    // the fps can be given when the macro has none
+    let replay = Replay::parse(ReplayFormat::Plain, b"60 1 1\n", Some(60.0)).unwrap();
+    assert_eq!(replay.actions[0].time, 1.0);
+}
+
+#[test]
+fn truncated_binary_macros_are_errors() {
+    for (name, format) in [
+        ("macro.zbf", ReplayFormat::Zbot),
+        ("macro.ybf", ReplayFormat::Ybot),
+    ] {
+        let data = std::fs::read(fixture(name)).unwrap();
+        for len in 0..data.len() {
+            // zbf has no action count, so cutting off whole actions is still valid
+            if format == ReplayFormat::Zbot && len >= 8 && (len - 8) % 6 == 0 {
+                continue;
+            }
+            assert!(
+                Replay::parse(format, &data[..len], None).is_err(),
+                "{name} cut to {len} bytes"
+            );
+        }
+    }
+
+    // a huge action count doesn't allocate for all of it
+    let mut data = std::fs::read(fixture("macro.ybf")).unwrap();
+    data[8..12].copy_from_slice(&i32::MAX.to_le_bytes());
+    assert!(Replay::parse(ReplayFormat::Ybot, &data, None).is_err());
+}
+
+#[test]
+fn garbage_is_an_error() {
+    let garbage: &[&[u8]] = &[
+        b"",
+        b"\xff\xfe\x00garbage",
+        b"{",
+        b"[1, 2, 3]",
+        br#"{ "inputs": 5 }"#,
+        br#"{ "inputs": [{ "frame": 1 }] }"#,
+        br#"{ "events": {} }"#,
+        br#"{ "macro": [{ "player_1": { "click": 1 } }] }"#,
+        br#"{ "Echo Replay": [{ "Frame": 1 }] }"#,
+        br#"{ "inputs": [{ "frame": 1, "btn": 7, "down": true }] }"#,
+        br#"{ "events": [{ "frame": 1, "button": "jump", "down": true }] }"#,
+    ];
+    for format in [
+        ReplayFormat::Gdr,
+        ReplayFormat::MegaHackJson,
+        ReplayFormat::TasBot,
+        ReplayFormat::Echo,
+        ReplayFormat::Ybot,
+    ] {
+        for data in garbage {
+            assert!(
+                Replay::parse(format, data, None).is_err(),
+                "{format:?} accepted {:?}",
+                String::from_utf8_lossy(data)
+            );
+        }
+    }
+    assert!(Replay::parse(ReplayFormat::Ybot, b"ybbt\0\0\0\0", None).is_err());
+
+    for text in [
+        &b"\xff"[..],
+        b"240\n1 1",
+        b"240\n1 4 1",
+        b"240\n1 1 yes",
+        b"240\n1 1 1 1 1",
+        b"240\n240\n",
+    ] {
+        assert!(
+            Replay::parse(ReplayFormat::Plain, text, None).is_err(),
+            "accepted {:?}",
+            String::from_utf8_lossy(text)
+        );
+    }
+    // no fps anywhere, or a zero fps
+    assert!(Replay::parse(ReplayFormat::Plain, b"1 1 1", None).is_err());
+    assert!(Replay::parse(ReplayFormat::Zbot, &[0; 8], None).is_err());
+}
@@ -0,0 +1,265 @@
+mod sim;
+
+use sim::{sound, static_config, wav_from_frames, Sim};
+use zcblive::clickpack::{
+    ClickType, Clickpack, Loudness, Normalization, NormalizeMode, SampleSettings, SoundWrapper,
+};
+
+fn assert_close(a: f64, b: f64, eps: f64) {
+    assert!((a - b).abs() < eps, "{a} != {b}");
+}
+
+/// Test sounds are constant, so their amplitude is their id / 100000.
+fn loud_and_soft_pack() -> Clickpack {
+    let mut pack = Clickpack::default();
+    *pack.player1.sounds_mut(ClickType::HardClick) = vec![sound(50000)];
+    *pack.player1.sounds_mut(ClickType::SoftClick) = vec![sound(12500)];
+    pack.num_sounds = pack.num_sounds();
+    pack
+}
+
+fn gain_db(sound: &SoundWrapper) -> f64 {
+    20.0 * sound.gain.log10()
+}
+
+#[test]
+fn measures_peak_and_rms() {
+    let loudness = sound(50000).loudness;
+    assert_close(loudness.peak, -6.02, 0.01);
+    assert_close(loudness.rms, -6.02, 0.01);
+    assert_eq!(
+        Loudness::average(&[sound(0)]).unwrap(),
+        Loudness::default(),
+        "silence"
+    );
+}
+
+#[test]
+fn measures_folders_without_changing_gain() {
+    let mut pack = loud_and_soft_pack();
+    pack.normalize(&Normalization::default());
+
+    // power average of 0.5 and 0.125 amplitude
+    assert_close(pack.loudness.unwrap().rms, -8.77, 0.01);
+    let folders: Vec<_> = pack
+        .folder_loudness
+        .iter()
+        .map(|f| (f.name.as_str(), f.gain))
+        .collect();
+    assert_eq!(
+        folders,
+        [("player1/hardclicks", 0.0), ("player1/softclicks", 0.0)]
+    );
+    assert_eq!(pack.player1.sounds(ClickType::HardClick)[0].gain, 1.0);
+}
+
+#[test]
+fn normalizes_whole_clickpack() {
+    let mut pack = loud_and_soft_pack();
+    pack.normalize(&Normalization {
+        mode: NormalizeMode::Pack,
+        target: -12.0,
+    });
+    // same gain everywhere, so softclicks stay softer
+    let hard = gain_db(&pack.player1.sounds(ClickType::HardClick)[0]);
+    let soft = gain_db(&pack.player1.sounds(ClickType::SoftClick)[0]);
+    assert_close(hard, -3.23, 0.01);
+    assert_close(soft, hard, 1e-9);
+}
+
+#[test]
+fn normalizes_every_folder() {
+    let mut pack = loud_and_soft_pack();
+    pack.normalize(&Normalization {
+        mode: NormalizeMode::Folder,
+        target: -12.0,
+    });
+    assert_close(
+        gain_db(&pack.player1.sounds(ClickType::HardClick)[0]),
+        -5.98,
+        0.01,
+    );
+    assert_close(
+        gain_db(&pack.player1.sounds(ClickType::SoftClick)[0]),
+        6.06,
+        0.01,
+    );
+}
+
+#[test]
+fn normalization_gain_changes_click_volume() {
+    let mut pack = loud_and_soft_pack();
+    pack.normalize(&Normalization {
+        mode: NormalizeMode::Folder,
+        target: -12.0,
+    });
+    let gain = pack.player1.sounds(ClickType::HardClick)[0].gain;
+    let mut sim = Sim::new(static_config(), pack, 0);
+    let played = sim.jump(10.0, true).unwrap();
+    assert_eq!(played.typ, ClickType::HardClick);
+    assert_close(played.volume, gain, 1e-9);
+}
+
+#[test]
+fn silent_folders_are_not_normalized() {
+    let mut pack = loud_and_soft_pack();
+    *pack.player1.sounds_mut(ClickType::SoftClick) = vec![sound(0)];
+    pack.normalize(&Normalization {
+        mode: NormalizeMode::Folder,
+        target: -12.0,
+    });
+    assert_eq!(pack.player1.sounds(ClickType::SoftClick)[0].gain, 1.0);
+    assert_eq!(pack.folder_loudness[1].gain, 0.0);
+}
+
+#[test]
+fn normalization_gain_is_limited() {
+    // a quiet click with one loud sample, and a quiet click without it
+    let quiet = [(0.001, 0.001); 4410];
+    let mut spiky = quiet;
+    spiky[100] = (0.5, 0.5);
+    let load = |frames: &[(f32, f32)]| {
+        SoundWrapper::from_bytes(
+            std::ptr::null_mut(),
+            wav_from_frames(44100, frames),
+            &SampleSettings::default(),
+        )
+        .unwrap()
+    };
+    let mut pack = Clickpack::default();
+    *pack.player1.sounds_mut(ClickType::HardClick) = vec![load(&spiky)];
+    *pack.player1.sounds_mut(ClickType::SoftClick) = vec![load(&quiet)];
+    pack.num_sounds = pack.num_sounds();
+    pack.normalize(&Normalization {
+        mode: NormalizeMode::Folder,
+        target: -12.0,
+    });
+
+    // the peak stops at 0 dBFS
+    let spiky = &pack.player1.sounds(ClickType::HardClick)[0];
+    assert_close(spiky.loudness.peak, -6.02, 0.01);
+    assert_close(gain_db(spiky), 6.02, 0.01);
+    // at most 24 dB are added
+    let quiet = &pack.player1.sounds(ClickType::SoftClick)[0];
+    assert_close(quiet.loudness.rms, -60.0, 0.01);
+    assert_close(gain_db(quiet), 24.0, 1e-9);
+}
+
+/// 10ms of silence, a 1ms click that decays for 2ms and 20ms of silence, at 1000 Hz so
+/// every frame is 1ms.
+fn padded_click() -> Vec<u8> {
+    let mut frames = vec![(0.0, 0.0); 10];
+    frames.extend([(0.8, 0.8), (0.1, -0.1), (0.001, 0.001)]);
+    frames.extend([(0.0, 0.0); 20]);
+    wav_from_frames(1000, &frames)
+}
+
+fn load_trimmed(data: Vec<u8>, pre_roll: f64) -> SoundWrapper {
+    let settings = SampleSettings {
+        trim_silence: true,
+        trim_threshold: -40.0,
+        pre_roll,
+        ..Default::default()
+    };
+    SoundWrapper::from_bytes(std::ptr::null_mut(), data, &settings).unwrap()
+}
+
+#[test]
+fn trims_silence_around_click() {
+    let sound = load_trimmed(padded_click(), 0.0);
+    let left: Vec<f32> = sound.frames.iter().map(|f| f.left).collect();
+    // the last frame is below the threshold
+    assert_eq!(left, [0.8, 0.1]);
+    assert_close(sound.trimmed.0, 0.010, 1e-9);
+    assert_close(sound.trimmed.1, 0.021, 1e-9);
+}
+
+#[test]
+fn keeps_pre_roll() {
+    let sound = load_trimmed(padded_click(), 0.002);
+    assert_eq!(sound.frames.len(), 6);
+    assert_eq!(sound.frames[2].left, 0.8);
+    assert_close(sound.trimmed.0, 0.008, 1e-9);
+}
+
+#[test]
+fn untrimmed_by_default() {
+    let sound = SoundWrapper::from_bytes(std::ptr::null_mut(), padded_click(), &Default::default())
+        .unwrap();
+    assert_eq!(sound.frames.len(), 33);
+    assert_eq!(sound.trimmed, (0.0, 0.0));
+}
+
+fn load_resampled(data: Vec<u8>, sample_rate: u32) -> SoundWrapper {
+    let settings = SampleSettings {
+        resample: true,
+        sample_rate,
+        ..Default::default()
+    };
+    SoundWrapper::from_bytes(std::ptr::null_mut(), data, &settings).unwrap()
+}
+
+fn sine(sample_rate: u32, freq: f64, len: usize) -> Vec<(f32, f32)> {
+    (0..len)
+        .map(|i| {
+            let s = (std::f64::consts::TAU * freq * i as f64 / sample_rate as f64).sin() as f32;
+            (s * 0.5, s * 0.5)
+        })
+        .collect()
+}
+
+#[test]
+fn resamples_to_output_rate() {
+    let data = wav_from_frames(44100, &sine(44100, 1000.0, 4410));
+    let sound = load_resampled(data, 48000);
+    assert_eq!(sound.sample_rate(), 48000);
+    assert_eq!(sound.frames.len(), 4800);
+
+    // away from the edges it's the same sine at the new rate
+    let expected = sine(48000, 1000.0, 4800);
+    for (i, (frame, expected)) in sound.frames.iter().zip(&expected).enumerate() {
+        if (100..4700).contains(&i) {
+            let diff = (frame.left - expected.0).abs();
+            assert!(diff < 1e-3, "frame {i}: {diff}");
+        }
+    }
+}
+
+#[test]
+fn downsampling_removes_frequencies_above_nyquist() {
+    // 15 kHz can't be represented at 22050 Hz
+    let data = wav_from_frames(44100, &sine(44100, 15000.0, 4410));
+    let sound = load_resampled(data, 22050);
+    let middle = &sound.frames[200..2000];
+    let peak = middle.iter().map(|f| f.left.abs()).fold(0.0, f32::max);
+    assert!(peak < 0.01, "{peak}");
+}
+
+#[test]
+fn same_rate_is_not_resampled() {
+    let frames = sine(48000, 1000.0, 100);
+    let sound = load_resampled(wav_from_frames(48000, &frames), 48000);
+    let left: Vec<f32> = sound.frames.iter().map(|f| f.left).collect();
+    let expected: Vec<f32> = frames.iter().map(|f| f.0).collect();
+    assert_eq!(left, expected);
+}
+
+#[test]
+fn mono_sounds_play_on_both_channels() {
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate: 44100,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut data = std::io::Cursor::new(vec![]);
+    let mut writer = hound::WavWriter::new(&mut data, spec).unwrap();
+    for i in 0..100 {
+        writer.write_sample(i as i16 * 100).unwrap();
+    }
+    writer.finalize().unwrap();
+
+    let sound = load_resampled(data.into_inner(), 48000);
+    assert!(sound.frames.iter().all(|f| f.left == f.right));
+    assert!(sound.frames.iter().any(|f| f.left != 0.0));
+}
@@ -0,0 +1,311 @@
+//! Headless simulation of the clickbot: a [`Bot`] with a scripted clock, a seeded RNG
+//! and an audio backend that records what would have been played.
+
+// every test crate includes this module, but not all of them use everything
+#![allow(dead_code)]
+
+use std::{
+    cell::RefCell,
+    io::{Cursor, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+use zcblive::{
+    audio::AudioBackend,
+    bot::{Bot, Config, Env, SoundSettings},
+    clickpack::{
+        Button, ClickType, Clickpack, LoadClickpackFor, SampleSettings, SoundWrapper,
+        VolumeSettings,
+    },
+};
+
+/// Sound ids are stored in the first sample of each test sound, scaled down so they
+/// stay in the valid sample range.
+const SOUND_ID_SCALE: f32 = 100_000.0;
+
+/// A click that was sent to the audio backend.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Played {
+    pub time: f64,
+    pub typ: ClickType,
+    pub sound: u32,
+    pub pitch: f64,
+    pub volume: f64,
+    pub pan: f64,
+    pub lowpass: f64,
+}
+
+#[derive(Default)]
+struct Recorded {
+    plays: Vec<(u32, f64, f64, f64, f64)>,
+    cuts: usize,
+    /// Playing noise layers: sound id, volume and start frame
+    noise: Vec<(u32, f64, usize)>,
+    noise_starts: usize,
+    noise_gain: f64,
+}
+
+struct Recorder(Rc<RefCell<Recorded>>);
+
+impl AudioBackend for Recorder {
+    fn play(&mut self, sound: SoundWrapper, pitch: f64, volume: f64, pan: f64, lowpass: f64) {
+        self.0
+            .borrow_mut()
+            .plays
+            .push((id_of(&sound), pitch, volume, pan, lowpass));
+    }
+
+    fn start_noise(&mut self, noise: &SoundWrapper, _speed: f64, volume: f64, start: usize) {
+        let mut recorded = self.0.borrow_mut();
+        recorded.noise.push((id_of(noise), volume, start));
+        recorded.noise_starts += 1;
+    }
+
+    fn stop_noise(&mut self) {
+        self.0.borrow_mut().noise.clear();
+    }
+
+    fn set_noise_gain(&mut self, gain: f64) {
+        self.0.borrow_mut().noise_gain = gain;
+    }
+
+    fn cut(&mut self) {
+        self.0.borrow_mut().cuts += 1;
+    }
+
+    fn set_device(&mut self, _name: &str) {}
+
+    fn set_buffer_size(&mut self, _buffer_size: u32) -> bool {
+        true
+    }
+}
+
+/// Stereo float WAV file of `(left, right)` frames.
+pub fn wav_from_frames(sample_rate: u32, frames: &[(f32, f32)]) -> Vec<u8> {
+    let spec = hound::WavSpec {
+        channels: 2,
+        sample_rate,
+        bits_per_sample: 32,
+        sample_format: hound::SampleFormat::Float,
+    };
+    let mut data = Cursor::new(vec![]);
+    let mut writer = hound::WavWriter::new(&mut data, spec).unwrap();
+    for &(left, right) in frames {
+        writer.write_sample(left).unwrap();
+        writer.write_sample(right).unwrap();
+    }
+    writer.finalize().unwrap();
+    data.into_inner()
+}
+
+/// WAV file of a short sound that can be told apart from others by its id.
+pub fn wav(id: u32) -> Vec<u8> {
+    let sample = id as f32 / SOUND_ID_SCALE;
+    wav_from_frames(44100, &[(sample, sample); 64])
+}
+
+/// Id of a sound created by [`sound`] or loaded from a [`wav`] file.
+pub fn id_of(sound: &SoundWrapper) -> u32 {
+    (sound.frames[0].left * SOUND_ID_SCALE).round() as u32
+}
+
+pub fn sound(id: u32) -> SoundWrapper {
+    SoundWrapper::from_bytes(std::ptr::null_mut(), wav(id), &SampleSettings::default()).unwrap()
+}
+
+/// Directory in the system temp dir that is removed when dropped.
+pub struct TempDir(pub PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("zcblive-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    /// Writes a [`wav`] file named after its id into `dir`.
+    pub fn add_sound(&self, dir: &str, id: u32) -> PathBuf {
+        let dir = self.0.join(dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("{id}.wav"));
+        std::fs::write(&path, wav(id)).unwrap();
+        path
+    }
+}
+
+/// Sample settings for tests, which keep the cache in `.zcb` out of it.
+pub fn uncached() -> SampleSettings {
+    SampleSettings {
+        cache: false,
+        ..Default::default()
+    }
+}
+
+/// Loads a clickpack for all players, like the bot does.
+pub fn load_pack(path: &Path, settings: SampleSettings) -> Clickpack {
+    let mut pack = Clickpack::default();
+    pack.load_from_path(path, LoadClickpackFor::All, std::ptr::null_mut(), &settings)
+        .unwrap();
+    pack
+}
+
+/// Writes a zip archive with a [`wav`] for every `(path, id)`.
+pub fn write_zip(path: &Path, sounds: &[(&str, u32)]) {
+    let mut zip = zip::ZipWriter::new(std::fs::File::create(path).unwrap());
+    for (name, id) in sounds {
+        zip.start_file(*name, zip::write::SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(&wav(*id)).unwrap();
+    }
+    zip.finish().unwrap();
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+pub const ALL_TYPES: [ClickType; 8] = [
+    ClickType::HardClick,
+    ClickType::HardRelease,
+    ClickType::Click,
+    ClickType::Release,
+    ClickType::SoftClick,
+    ClickType::SoftRelease,
+    ClickType::MicroClick,
+    ClickType::MicroRelease,
+];
+
+/// Id of the `n`th sound of a click type in a clickpack made by [`clickpack`].
+///
+/// `player` is the index of the player clicks: 0 = player1, 1 = player2, 2 = left1,
+/// 3 = right1, 4 = left2, 5 = right2.
+pub fn sound_id(player: usize, typ: ClickType, n: usize) -> u32 {
+    let typ = ALL_TYPES.iter().position(|t| *t == typ).unwrap();
+    (player * 1000 + typ * 100 + n) as u32
+}
+
+/// Builds a clickpack with `count` sounds of each click type for the given players.
+pub fn clickpack(players: &[usize], types: &[ClickType], count: usize) -> Clickpack {
+    let mut clickpack = Clickpack::default();
+    for &player in players {
+        for &typ in types {
+            *clickpack[player].sounds_mut(typ) = (0..count)
+                .map(|n| sound(sound_id(player, typ, n)))
+                .collect();
+        }
+    }
+    clickpack.num_sounds = clickpack.num_sounds();
+    clickpack.has_platformer_sounds = (2..6).any(|i| clickpack[i].num_sounds() != 0);
+    clickpack
+}
+
+/// A full clickpack for player 1 with one sound per click type.
+pub fn simple_clickpack() -> Clickpack {
+    clickpack(&[0], &ALL_TYPES, 1)
+}
+
+/// Config without any randomness, so pitch and volume can be compared exactly.
+pub fn static_config() -> Config {
+    Config {
+        sound: SoundSettings {
+            pitch_enabled: false,
+            volume_settings: VolumeSettings {
+                volume_var: 0.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+pub struct Sim {
+    pub bot: Box<Bot>,
+    recorded: Rc<RefCell<Recorded>>,
+}
+
+impl Sim {
+    /// Creates a bot that is in a level, with the RNG seeded with `seed`.
+    pub fn new(mut conf: Config, clickpack: Clickpack, seed: u64) -> Self {
+        conf.rng_seed = Some(seed);
+        let mut bot = Box::new(Bot::with_config(conf, Env::default()));
+        let recorded = Rc::new(RefCell::new(Recorded::default()));
+        bot.audio = Box::new(Recorder(recorded.clone()));
+        bot.clickpack = clickpack;
+        bot.time_override = Some(0.0);
+        bot.on_level_start(0);
+        Self { bot, recorded }
+    }
+
+    /// Performs an action at `time` seconds. Returns the click that was played, if any.
+    ///
+    /// Time 0 is treated as "not in a level" by the bot, so scripts should start later.
+    pub fn action(
+        &mut self,
+        time: f64,
+        button: Button,
+        player2: bool,
+        push: bool,
+    ) -> Option<Played> {
+        let num_plays = self.recorded.borrow().plays.len();
+        self.bot.time_override = Some(time);
+        unsafe { self.bot.on_action(button, player2, push) };
+
+        let recorded = self.recorded.borrow();
+        let &(sound, pitch, volume, pan, lowpass) = recorded.plays.get(num_plays)?;
+        let typ = self.bot.click_state.prev_times.last_click_type(
+            button,
+            player2,
+            self.bot.conf.decouple_platformer,
+        );
+        Some(Played {
+            time,
+            typ,
+            sound,
+            pitch,
+            volume,
+            pan,
+            lowpass,
+        })
+    }
+
+    /// Shorthand for player 1 jump actions.
+    pub fn jump(&mut self, time: f64, push: bool) -> Option<Played> {
+        self.action(time, Button::Jump, false, push)
+    }
+
+    /// Runs `(time, button, player2, push)` actions, returning the clicks that played.
+    pub fn run(&mut self, script: &[(f64, Button, bool, bool)]) -> Vec<Played> {
+        script
+            .iter()
+            .filter_map(|&(time, button, player2, push)| self.action(time, button, player2, push))
+            .collect()
+    }
+
+    /// Number of sounds played so far.
+    pub fn num_played(&self) -> usize {
+        self.recorded.borrow().plays.len()
+    }
+
+    /// Number of times playing sounds were cut.
+    pub fn cuts(&self) -> usize {
+        self.recorded.borrow().cuts
+    }
+
+    /// Noise layers that are playing: sound id, volume and start frame.
+    pub fn noise(&self) -> Vec<(u32, f64, usize)> {
+        self.recorded.borrow().noise.clone()
+    }
+
+    /// Number of noise layers started so far.
+    pub fn noise_starts(&self) -> usize {
+        self.recorded.borrow().noise_starts
+    }
+
+    pub fn noise_gain(&self) -> f64 {
+        self.recorded.borrow().noise_gain
+    }
+}
@@ -0,0 +1,153 @@
+mod sim;
+
+use kittyaudio::Frame;
+use sim::{clickpack, id_of, load_pack, sound_id, uncached, TempDir};
+use zcblive::{
+    clickpack::{ClickType, SampleSettings},
+    synth::{synthesize_release, ReleaseSynth},
+};
+
+fn enabled() -> ReleaseSynth {
+    ReleaseSynth {
+        enabled: true,
+        ..Default::default()
+    }
+}
+
+fn rms(frames: &[Frame]) -> f32 {
+    (frames.iter().map(|f| f.left * f.left).sum::<f32>() / frames.len() as f32).sqrt()
+}
+
+#[test]
+fn release_is_the_reversed_tail() {
+    // a decaying 1 kHz tone after some silence
+    let click: Vec<Frame> = (0..8820)
+        .map(|i| {
+            let t = i.max(441) as f32 - 441.0;
+            let sample = if i < 441 {
+                0.0
+            } else {
+                (t / 44.1 * std::f32::consts::TAU).sin() * (-t / 1000.0).exp()
+            };
+            Frame {
+                left: sample,
+                right: sample,
+            }
+        })
+        .collect();
+    let settings = ReleaseSynth {
+        tail: 0.05,
+        pitch: 1.25,
+        ..enabled()
+    };
+    let release = synthesize_release(&click, 44100, &settings);
+    // 50ms played 1.25x faster
+    assert!(
+        release.len().abs_diff(1764) <= 2,
+        "{} frames",
+        release.len()
+    );
+    assert_eq!(release[0].left, 0.0);
+    assert_eq!(release[release.len() - 1].left, 0.0);
+    let peak = release.iter().map(|f| f.left.abs()).fold(0.0, f32::max);
+    assert!(peak > 0.0 && peak <= settings.volume as f32);
+    // it swells instead of decaying
+    let quarter = release.len() / 4;
+    assert!(rms(&release[release.len() - quarter..]) > rms(&release[..quarter]) * 2.0);
+}
+
+#[test]
+fn generates_releases_for_players_without_any() {
+    let mut pack = clickpack(&[0], &[ClickType::Click, ClickType::SoftClick], 2);
+    pack.player2 = clickpack(&[1], &[ClickType::Click, ClickType::Release], 1)
+        .player2
+        .clone();
+
+    assert_eq!(
+        pack.synthesize_releases(&enabled(), std::ptr::null_mut()),
+        4
+    );
+    assert_eq!(pack.num_synthesized(), 4);
+    assert_eq!(pack.num_sounds, 4 + 4 + 2);
+    assert_eq!(pack[0].sounds(ClickType::Release).len(), 2);
+    assert_eq!(pack[0].sounds(ClickType::SoftRelease).len(), 2);
+    // player 2 has its own releases
+    let releases = pack[1].sounds(ClickType::Release);
+    assert_eq!(releases.len(), 1);
+    assert_eq!(id_of(&releases[0]), sound_id(1, ClickType::Release, 0));
+
+    // generating again replaces the old releases, disabling removes them
+    assert_eq!(
+        pack.synthesize_releases(&enabled(), std::ptr::null_mut()),
+        4
+    );
+    assert_eq!(pack[0].sounds(ClickType::Release).len(), 2);
+    let disabled = ReleaseSynth::default();
+    assert_eq!(pack.synthesize_releases(&disabled, std::ptr::null_mut()), 0);
+    assert_eq!(pack.num_synthesized(), 0);
+    assert!(pack[0].sounds(ClickType::Release).is_empty());
+}
+
+#[test]
+fn saved_releases_are_loaded_next_time() {
+    let dir = TempDir::new("synth-save");
+    dir.add_sound("player1/clicks", 1);
+    dir.add_sound("player1/clicks", 2);
+    dir.add_sound("player1/softclicks", 3);
+
+    let mut pack = load_pack(&dir.0, uncached());
+    assert_eq!(
+        pack.synthesize_releases(&enabled(), std::ptr::null_mut()),
+        3
+    );
+    assert_eq!(pack.save_synthesized_releases().unwrap(), 3);
+    assert_eq!(pack.num_synthesized(), 0);
+    assert!(dir.0.join("player1/releases/release2.wav").exists());
+    assert!(dir.0.join("player1/softreleases/release1.wav").exists());
+
+    let mut pack = load_pack(&dir.0, uncached());
+    assert_eq!(pack[0].sounds(ClickType::Release).len(), 2);
+    assert_eq!(pack[0].sounds(ClickType::SoftRelease).len(), 1);
+    assert_eq!(
+        pack.synthesize_releases(&enabled(), std::ptr::null_mut()),
+        0
+    );
+}
+
+#[test]
+fn unloaded_clicks_get_releases() {
+    let dir = TempDir::new("synth-unloaded");
+    dir.add_sound("player1/clicks", 1);
+    dir.add_sound("player1/clicks", 2);
+    dir.add_sound("player2/clicks", 1001);
+    let settings = SampleSettings {
+        // room for two clicks
+        memory_budget: 0.0011,
+        ..uncached()
+    };
+    let mut pack = load_pack(&dir.0, settings);
+    assert_eq!(pack.num_unloaded(), 1);
+    assert_eq!(
+        pack.synthesize_releases(&enabled(), std::ptr::null_mut()),
+        3
+    );
+    assert_eq!(pack[1].sounds(ClickType::Release).len(), 1);
+}
+
+#[test]
+fn existing_files_stop_the_save_before_writing() {
+    let dir = TempDir::new("synth-conflict");
+    dir.add_sound("player1/clicks", 1);
+    dir.add_sound("player2/clicks", 1001);
+    let mut pack = load_pack(&dir.0, uncached());
+    assert_eq!(
+        pack.synthesize_releases(&enabled(), std::ptr::null_mut()),
+        2
+    );
+    std::fs::create_dir_all(dir.0.join("player2/releases")).unwrap();
+    std::fs::write(dir.0.join("player2/releases/release1.wav"), "").unwrap();
+
+    assert!(pack.save_synthesized_releases().is_err());
+    assert!(!dir.0.join("player1/releases").exists());
+    assert_eq!(pack.num_synthesized(), 2);
+}
@@ -0,0 +1,112 @@
+mod sim;
+
+use sim::{
+    clickpack, id_of, load_pack, simple_clickpack, sound, sound_id, static_config, uncached, Sim,
+    TempDir,
+};
+use zcblive::clickpack::{ClickType, Clickpack};
+
+fn with_tier(mut pack: Clickpack, tier: &str, id: u32) -> Clickpack {
+    pack.player1.tier_mut(tier).clicks = vec![sound(id)];
+    pack.num_sounds = pack.num_sounds();
+    pack
+}
+
+#[test]
+fn extra_tier_between_standard_tiers() {
+    let pack = with_tier(simple_clickpack(), "ultrasoft", 9000);
+    let mut sim = Sim::new(static_config(), pack, 0);
+    sim.jump(10.0, true).unwrap();
+    sim.jump(10.5, false).unwrap();
+
+    // 0.02s is above the default ultrasoft threshold, but still a microclick
+    let played = sim.jump(10.52, true).unwrap();
+    assert_eq!(played.sound, 9000);
+    assert_eq!(played.typ, ClickType::MicroClick);
+    sim.jump(10.53, false).unwrap();
+    let played = sim.jump(10.535, true).unwrap();
+    assert_eq!(played.sound, sound_id(0, ClickType::MicroClick, 0));
+}
+
+#[test]
+fn extra_tier_threshold_from_timings() {
+    let mut conf = static_config();
+    conf.sound.timings.tiers.insert("3".to_string(), 1.0);
+    let pack = with_tier(simple_clickpack(), "3", 9000);
+    let mut sim = Sim::new(conf, pack, 0);
+    sim.jump(10.0, true).unwrap();
+    sim.jump(10.1, false).unwrap();
+    assert_eq!(sim.jump(11.5, true).unwrap().sound, 9000);
+    sim.jump(11.6, false).unwrap();
+    assert_eq!(
+        sim.jump(12.4, true).unwrap().sound,
+        sound_id(0, ClickType::Click, 0)
+    );
+}
+
+#[test]
+fn tier_without_threshold_is_not_played() {
+    let pack = with_tier(clickpack(&[0], &[ClickType::Click], 1), "velocity", 9000);
+    let mut sim = Sim::new(static_config(), pack, 0);
+    for i in 0..10 {
+        let played = sim.jump(10.0 + i as f64 * 3.0, i % 2 == 0).unwrap();
+        assert_eq!(played.sound, sound_id(0, ClickType::Click, 0));
+    }
+}
+
+#[test]
+fn falls_back_to_nearest_tier() {
+    let pack = with_tier(clickpack(&[0], &[ClickType::SoftClick], 1), "hardest", 9000);
+    let mut sim = Sim::new(static_config(), pack, 0);
+    // hard range: hardest is closer than softclicks
+    assert_eq!(sim.jump(10.0, true).unwrap().sound, 9000);
+    // regular range: hardclicks don't exist, softclicks are next
+    sim.jump(10.05, false).unwrap();
+    assert_eq!(
+        sim.jump(10.15, true).unwrap().sound,
+        sound_id(0, ClickType::SoftClick, 0)
+    );
+}
+
+fn tier_sounds(pack: &Clickpack, tier: &str, release: bool) -> Vec<u32> {
+    pack.player1
+        .tier(tier)
+        .map(|tier| tier.sounds(release).iter().map(id_of).collect())
+        .unwrap_or_default()
+}
+
+#[test]
+fn loads_tiers_from_folder_names() {
+    let dir = TempDir::new("tier-folders");
+    dir.add_sound("hardclicks", 1);
+    dir.add_sound("Ultrasoft Clicks", 2);
+    dir.add_sound("hardest_releases", 3);
+    dir.add_sound("clicks2", 4);
+    let pack = load_pack(&dir.0, uncached());
+
+    assert_eq!(tier_sounds(&pack, "hard", false), [1]);
+    assert_eq!(tier_sounds(&pack, "ultrasoft", false), [2]);
+    assert_eq!(tier_sounds(&pack, "hardest", true), [3]);
+    // numbered folders are regular clicks unless the manifest declares them
+    assert_eq!(tier_sounds(&pack, "", false), [4]);
+    assert_eq!(pack.extra_tiers(), ["hardest", "ultrasoft"]);
+}
+
+#[test]
+fn loads_tiers_declared_in_manifest() {
+    let dir = TempDir::new("tier-manifest");
+    dir.add_sound("clicks1", 1);
+    dir.add_sound("clicks2", 2);
+    std::fs::write(
+        dir.0.join("zcb.json"),
+        r#"{ "timings": { "tiers": { "1": 1.0, "2": 0.5 } } }"#,
+    )
+    .unwrap();
+    let pack = load_pack(&dir.0, uncached());
+
+    assert_eq!(tier_sounds(&pack, "1", false), [1]);
+    assert_eq!(tier_sounds(&pack, "2", false), [2]);
+    assert!(tier_sounds(&pack, "", false).is_empty());
+    let timings = pack.manifest.as_ref().and_then(|m| m.timings.as_ref());
+    assert_eq!(timings.unwrap().threshold("2"), Some(0.5));
+}